@@ -1,9 +1,13 @@
 use anyhow::Result;
 use serenity::prelude::*;
 use serenity::all::{ApplicationCommandInteraction, InteractionResponseType};
-use crate::services::Database;
+use crate::services::{AccountService, Database, OrderService, PterodactylClient, QueueService};
 use crate::config::Config;
-use crate::models::{Coupon, Resources};
+use crate::errors::BotError;
+use crate::commands::coins::{format_transactions, HISTORY_PAGE_SIZE};
+use crate::commands::orders::{format_orders, ORDERS_PAGE_SIZE};
+use crate::models::{CoinChange, Coupon, OrderQuery, OrderStatus, Resources, ServerStatus, TransactionReason};
+use crate::workers::orders::{notify_completed, notify_reversed};
 
 pub async fn handle_admin(ctx: &Context, command: &ApplicationCommandInteraction, database: &Database, config: &Config) -> Result<()> {
     let discord_id = command.user.id.0;
@@ -28,6 +32,12 @@ pub async fn handle_admin(ctx: &Context, command: &ApplicationCommandInteraction
         Some("resources") => handle_admin_resources(ctx, command, database).await,
         Some("coupons") => handle_admin_coupons(ctx, command, database, discord_id).await,
         Some("stats") => show_admin_stats(ctx, command, database).await,
+        Some("queue") => handle_admin_queue(ctx, command, database).await,
+        Some("accounts") => handle_admin_accounts(ctx, command, database, config).await,
+        Some("renewals") => list_auto_renewals(ctx, command, database, config).await,
+        Some("allocations") => list_over_allocated(ctx, command, database, config).await,
+        Some("ledger") => handle_admin_ledger(ctx, command, database).await,
+        Some("orders") => handle_admin_orders(ctx, command, database, config).await,
         _ => {
             command.create_interaction_response(&ctx.http, |response| {
                 response
@@ -72,13 +82,24 @@ async fn handle_admin_coins(ctx: &Context, command: &ApplicationCommandInteracti
     let target_user = target_user.unwrap();
     let amount = amount.unwrap();
 
-    let user = database.get_or_create_user(target_user).await?;
-    let mut user = user;
+    if amount <= 0 && matches!(action, Some("add") | Some("remove")) {
+        command.create_interaction_response(&ctx.http, |response| {
+            response
+                .kind(InteractionResponseType::ChannelMessageWithSource)
+                .interaction_response_data(|message| {
+                    message.content("The amount to add or remove must be positive").ephemeral(true)
+                })
+        }).await?;
+        return Ok(());
+    }
+
+    // Make sure the target has a user document to update
+    database.get_or_create_user(target_user).await?;
+    let change = CoinChange::new(TransactionReason::Admin).counterparty(command.user.id.0);
 
     match action {
         Some("set") => {
-            user.coins = amount;
-            database.update_user(&user).await?;
+            database.set_coins(target_user, amount, change).await?;
             
             command.create_interaction_response(&ctx.http, |response| {
                 response
@@ -96,8 +117,7 @@ async fn handle_admin_coins(ctx: &Context, command: &ApplicationCommandInteracti
             }).await?;
         }
         Some("add") => {
-            user.add_coins(amount);
-            database.update_user(&user).await?;
+            let balance = database.add_coins(target_user, amount, change).await?;
             
             command.create_interaction_response(&ctx.http, |response| {
                 response
@@ -107,7 +127,7 @@ async fn handle_admin_coins(ctx: &Context, command: &ApplicationCommandInteracti
                             .embed(|embed| {
                                 embed
                                     .title("✅ Admin Action Complete")
-                                    .description(format!("Added **{}** coins to <@{}>\nNew balance: **{}**", amount, target_user, user.coins))
+                                    .description(format!("Added **{}** coins to <@{}>\nNew balance: **{}**", amount, target_user, balance))
                                     .color(0x00ff00)
                             })
                             .ephemeral(true)
@@ -115,18 +135,20 @@ async fn handle_admin_coins(ctx: &Context, command: &ApplicationCommandInteracti
             }).await?;
         }
         Some("remove") => {
-            if let Err(e) = user.deduct_coins(amount) {
-                command.create_interaction_response(&ctx.http, |response| {
-                    response
-                        .kind(InteractionResponseType::ChannelMessageWithSource)
-                        .interaction_response_data(|message| {
-                            message.content(format!("Error: {}", e)).ephemeral(true)
-                        })
-                }).await?;
-                return Ok(());
-            }
-            
-            database.update_user(&user).await?;
+            let balance = match database.deduct_coins(target_user, amount, change).await {
+                Ok(balance) => balance,
+                Err(e @ BotError::InsufficientCoins { .. }) => {
+                    command.create_interaction_response(&ctx.http, |response| {
+                        response
+                            .kind(InteractionResponseType::ChannelMessageWithSource)
+                            .interaction_response_data(|message| {
+                                message.content(format!("Error: {}", e)).ephemeral(true)
+                            })
+                    }).await?;
+                    return Ok(());
+                }
+                Err(e) => return Err(e.into()),
+            };
             
             command.create_interaction_response(&ctx.http, |response| {
                 response
@@ -136,7 +158,7 @@ async fn handle_admin_coins(ctx: &Context, command: &ApplicationCommandInteracti
                             .embed(|embed| {
                                 embed
                                     .title("✅ Admin Action Complete")
-                                    .description(format!("Removed **{}** coins from <@{}>\nNew balance: **{}**", amount, target_user, user.coins))
+                                    .description(format!("Removed **{}** coins from <@{}>\nNew balance: **{}**", amount, target_user, balance))
                                     .color(0x00ff00)
                             })
                             .ephemeral(true)
@@ -327,3 +349,576 @@ async fn show_admin_stats(ctx: &Context, command: &ApplicationCommandInteraction
 
     Ok(())
 }
+
+async fn list_auto_renewals(ctx: &Context, command: &ApplicationCommandInteraction, database: &Database, config: &Config) -> Result<()> {
+    let servers = database.get_auto_renew_servers().await?;
+
+    let description = if servers.is_empty() {
+        "No servers are enrolled in auto-renew.".to_string()
+    } else {
+        let mut description = String::new();
+        for server in servers.iter().take(20) {
+            let price = config.store_config.plan(&server.plan)
+                .map(|plan| format!("{} coins", plan.renewal_price()))
+                .unwrap_or_else(|| "unknown plan".to_string());
+            description.push_str(&format!(
+                "**{}** `{}`\n👤 <@{}> • 📋 {} ({}) • ⏰ {}\n\n",
+                server.name,
+                server.id,
+                server.discord_id,
+                server.plan,
+                price,
+                server.expires_at.format("%Y-%m-%d %H:%M UTC")
+            ));
+        }
+        description
+    };
+
+    command.create_interaction_response(&ctx.http, |response| {
+        response
+            .kind(InteractionResponseType::ChannelMessageWithSource)
+            .interaction_response_data(|message| {
+                message
+                    .embed(|embed| {
+                        embed
+                            .title(format!("🔁 Auto-Renew Enrollments ({} total)", servers.len()))
+                            .description(description)
+                            .color(0x3498db)
+                    })
+                    .ephemeral(true)
+            })
+    }).await?;
+
+    Ok(())
+}
+
+async fn list_over_allocated(ctx: &Context, command: &ApplicationCommandInteraction, database: &Database, config: &Config) -> Result<()> {
+    let servers = database.get_over_allocated_servers().await?;
+
+    let description = if servers.is_empty() {
+        "No servers are over their owner's resources.".to_string()
+    } else {
+        let mut description = String::new();
+        for server in servers.iter().take(20) {
+            let state = match (&server.status, server.over_allocated_at) {
+                (ServerStatus::Suspended, _) => "⛔ suspended".to_string(),
+                (_, Some(flagged_at)) => format!(
+                    "⏰ suspends {}",
+                    (flagged_at + chrono::Duration::days(config.over_allocation_grace_days)).format("%Y-%m-%d %H:%M UTC")
+                ),
+                (_, None) => "⏰ notice period not started".to_string(),
+            };
+            description.push_str(&format!(
+                "**{}** `{}`\n👤 <@{}> • 📋 {} • {}\n\n",
+                server.name,
+                server.id,
+                server.discord_id,
+                server.plan,
+                state
+            ));
+        }
+        description
+    };
+
+    command.create_interaction_response(&ctx.http, |response| {
+        response
+            .kind(InteractionResponseType::ChannelMessageWithSource)
+            .interaction_response_data(|message| {
+                message
+                    .embed(|embed| {
+                        embed
+                            .title(format!("⚠️ Over-Allocated Servers ({} total)", servers.len()))
+                            .description(description)
+                            .color(0xff6b6b)
+                    })
+                    .ephemeral(true)
+            })
+    }).await?;
+
+    Ok(())
+}
+
+async fn handle_admin_ledger(ctx: &Context, command: &ApplicationCommandInteraction, database: &Database) -> Result<()> {
+    let options = command.data.options.get(0).map(|opt| opt.options.as_slice()).unwrap_or(&[]);
+    let action = options.iter()
+        .find(|o| o.name == "action")
+        .and_then(|opt| opt.value.as_ref())
+        .and_then(|val| val.as_str());
+
+    match action {
+        Some("view") => {
+            let target_user = options.iter()
+                .find(|o| o.name == "user")
+                .and_then(|opt| opt.value.as_ref())
+                .and_then(|val| val.as_str())
+                .and_then(|s| s.parse::<u64>().ok());
+
+            let target_user = match target_user {
+                Some(target_user) => target_user,
+                None => {
+                    command.create_interaction_response(&ctx.http, |response| {
+                        response
+                            .kind(InteractionResponseType::ChannelMessageWithSource)
+                            .interaction_response_data(|message| {
+                                message.content("Please provide a user").ephemeral(true)
+                            })
+                    }).await?;
+                    return Ok(());
+                }
+            };
+
+            let page = options.iter()
+                .find(|o| o.name == "page")
+                .and_then(|opt| opt.value.as_ref())
+                .and_then(|val| val.as_i64())
+                .unwrap_or(1)
+                .max(1) as u64;
+
+            let (transactions, total) = database.get_transactions(target_user, page - 1, HISTORY_PAGE_SIZE).await?;
+            let pages = ((total + HISTORY_PAGE_SIZE as u64 - 1) / HISTORY_PAGE_SIZE as u64).max(1);
+            let balance = database.get_user(target_user).await?.map(|user| user.coins).unwrap_or(0);
+            let ledger_total = database.get_ledger_total(target_user).await?;
+            let status = if balance == ledger_total { "✅ Matches balance" } else { "⚠️ Does not match balance" };
+
+            command.create_interaction_response(&ctx.http, |response| {
+                response
+                    .kind(InteractionResponseType::ChannelMessageWithSource)
+                    .interaction_response_data(|message| {
+                        message
+                            .embed(|embed| {
+                                embed
+                                    .title("📜 Coin Ledger")
+                                    .description(format!("<@{}>\n\n{}", target_user, format_transactions(&transactions)))
+                                    .field("Balance", format!("{} coins", balance), true)
+                                    .field("Ledger Total", format!("{} coins\n{}", ledger_total, status), true)
+                                    .footer(|footer| footer.text(format!("Page {} of {} • {} transaction(s)", page, pages, total)))
+                                    .color(0x3498db)
+                            })
+                            .ephemeral(true)
+                    })
+            }).await?;
+        }
+        Some("check") => {
+            let mismatches = database.get_ledger_mismatches().await?;
+
+            let description = if mismatches.is_empty() {
+                "Every balance matches its ledger.".to_string()
+            } else {
+                let mut description = String::new();
+                for (discord_id, coins, ledger_total) in mismatches.iter().take(20) {
+                    description.push_str(&format!(
+                        "<@{}>: balance **{}**, ledger **{}** (off by {:+})\n",
+                        discord_id,
+                        coins,
+                        ledger_total,
+                        coins - ledger_total
+                    ));
+                }
+                description
+            };
+
+            command.create_interaction_response(&ctx.http, |response| {
+                response
+                    .kind(InteractionResponseType::ChannelMessageWithSource)
+                    .interaction_response_data(|message| {
+                        message
+                            .embed(|embed| {
+                                embed
+                                    .title(format!("🧮 Ledger Check ({} mismatch(es))", mismatches.len()))
+                                    .description(description)
+                                    .color(if mismatches.is_empty() { 0x00ff00 } else { 0xff6b6b })
+                            })
+                            .ephemeral(true)
+                    })
+            }).await?;
+        }
+        Some("open") => {
+            let opened = database.open_ledger_balances().await?;
+
+            command.create_interaction_response(&ctx.http, |response| {
+                response
+                    .kind(InteractionResponseType::ChannelMessageWithSource)
+                    .interaction_response_data(|message| {
+                        message.content(format!("✅ Recorded opening balances for {} user(s)", opened)).ephemeral(true)
+                    })
+            }).await?;
+        }
+        _ => {
+            command.create_interaction_response(&ctx.http, |response| {
+                response
+                    .kind(InteractionResponseType::ChannelMessageWithSource)
+                    .interaction_response_data(|message| {
+                        message.content("Invalid ledger action").ephemeral(true)
+                    })
+            }).await?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn handle_admin_orders(ctx: &Context, command: &ApplicationCommandInteraction, database: &Database, config: &Config) -> Result<()> {
+    let options = command.data.options.get(0).map(|opt| opt.options.as_slice()).unwrap_or(&[]);
+    let option_str = |name: &str| options.iter()
+        .find(|o| o.name == name)
+        .and_then(|opt| opt.value.as_ref())
+        .and_then(|val| val.as_str());
+
+    let orders = OrderService::new(database.clone(), config);
+
+    let content = match option_str("action") {
+        Some("search") => return search_orders(ctx, command, database, config).await,
+        Some("reconcile") => {
+            let report = orders.reconcile(chrono::Duration::minutes(config.order_reconcile_after_minutes)).await?;
+            for order in &report.completed {
+                notify_completed(&ctx.http, order).await;
+            }
+
+            command.create_interaction_response(&ctx.http, |response| {
+                response
+                    .kind(InteractionResponseType::ChannelMessageWithSource)
+                    .interaction_response_data(|message| {
+                        message
+                            .embed(|embed| {
+                                embed
+                                    .title("🔄 Order Reconciliation Complete")
+                                    .description(format!("Checked orders pending for over {} minute(s) with their provider.", config.order_reconcile_after_minutes))
+                                    .field("Completed", report.completed.len().to_string(), true)
+                                    .field("Cancelled", report.cancelled.to_string(), true)
+                                    .field("Still Pending", report.pending.to_string(), true)
+                                    .field("Failed", report.failed.to_string(), true)
+                                    .color(if report.failed == 0 { 0x00ff00 } else { 0xffaa00 })
+                            })
+                            .ephemeral(true)
+                    })
+            }).await?;
+            return Ok(());
+        }
+        Some(action @ ("complete" | "cancel" | "refund")) => {
+            let order = match option_str("order_id") {
+                Some(order_id) => database.get_order(order_id.trim()).await?,
+                None => None,
+            };
+
+            match (action, order) {
+                (_, None) => "❌ Please provide a valid order ID".to_string(),
+                ("complete", Some(order)) => {
+                    if orders.complete(&order, None).await? {
+                        notify_completed(&ctx.http, &order).await;
+                        format!("✅ Completed order `{}` and credited **{} coins** to <@{}>", order.id, order.coins, order.discord_id)
+                    } else {
+                        format!("❌ Only pending orders can be completed; `{}` is {:?}", order.id, order.status)
+                    }
+                }
+                ("cancel", Some(order)) => {
+                    if orders.cancel(&order).await? {
+                        format!("✅ Cancelled order `{}`", order.id)
+                    } else {
+                        format!("❌ Only pending orders can be cancelled; `{}` is {:?}", order.id, order.status)
+                    }
+                }
+                (_, Some(order)) => match orders.refund(&order).await {
+                    Ok(true) => {
+                        notify_reversed(&ctx.http, &order, "refunded").await;
+                        format!("✅ Refunded order `{}` through {} and took back **{} coins** from <@{}>", order.id, order.provider, order.coins, order.discord_id)
+                    }
+                    Ok(false) => format!("✅ Refunded order `{}`; its coins had already been taken back", order.id),
+                    Err(BotError::InvalidInput(reason)) => format!("❌ {}", reason),
+                    Err(e) => format!("❌ Refund failed: {}", e),
+                },
+            }
+        }
+        _ => "Invalid orders action".to_string(),
+    };
+
+    command.create_interaction_response(&ctx.http, |response| {
+        response
+            .kind(InteractionResponseType::ChannelMessageWithSource)
+            .interaction_response_data(|message| {
+                message.content(content).ephemeral(true)
+            })
+    }).await?;
+
+    Ok(())
+}
+
+async fn search_orders(ctx: &Context, command: &ApplicationCommandInteraction, database: &Database, config: &Config) -> Result<()> {
+    let options = command.data.options.get(0).map(|opt| opt.options.as_slice()).unwrap_or(&[]);
+    let option_str = |name: &str| options.iter()
+        .find(|o| o.name == name)
+        .and_then(|opt| opt.value.as_ref())
+        .and_then(|val| val.as_str());
+
+    let status = match option_str("status") {
+        Some("Pending") => Some(OrderStatus::Pending),
+        Some("Completed") => Some(OrderStatus::Completed),
+        Some("Failed") => Some(OrderStatus::Failed),
+        Some("Cancelled") => Some(OrderStatus::Cancelled),
+        _ => None,
+    };
+
+    // Dates cover whole UTC days, so `to` runs until the start of the next day
+    let parse_date = |name: &str, days: i64| -> std::result::Result<Option<chrono::DateTime<chrono::Utc>>, String> {
+        match option_str(name) {
+            Some(value) => chrono::NaiveDate::parse_from_str(value.trim(), "%Y-%m-%d")
+                .map(|date| Some((date + chrono::Duration::days(days)).and_hms_opt(0, 0, 0).unwrap_or_default().and_utc()))
+                .map_err(|_| format!("❌ `{}` is not a date, use YYYY-MM-DD", value)),
+            None => Ok(None),
+        }
+    };
+
+    let dates = parse_date("from", 0).and_then(|from| Ok((from, parse_date("to", 1)?)));
+    let (from, to) = match dates {
+        Ok(dates) => dates,
+        Err(content) => {
+            command.create_interaction_response(&ctx.http, |response| {
+                response
+                    .kind(InteractionResponseType::ChannelMessageWithSource)
+                    .interaction_response_data(|message| {
+                        message.content(content).ephemeral(true)
+                    })
+            }).await?;
+            return Ok(());
+        }
+    };
+
+    let query = OrderQuery {
+        discord_id: option_str("user").and_then(|s| s.parse::<u64>().ok()),
+        status,
+        from,
+        to,
+    };
+
+    let page = options.iter()
+        .find(|o| o.name == "page")
+        .and_then(|opt| opt.value.as_ref())
+        .and_then(|val| val.as_i64())
+        .unwrap_or(1)
+        .max(1) as u64;
+
+    let (orders, total) = database.find_orders(&query, page - 1, ORDERS_PAGE_SIZE).await?;
+    let pages = ((total + ORDERS_PAGE_SIZE as u64 - 1) / ORDERS_PAGE_SIZE as u64).max(1);
+
+    command.create_interaction_response(&ctx.http, |response| {
+        response
+            .kind(InteractionResponseType::ChannelMessageWithSource)
+            .interaction_response_data(|message| {
+                message
+                    .embed(|embed| {
+                        embed
+                            .title(format!("🧾 Orders ({} found)", total))
+                            .description(format_orders(&orders, config, true))
+                            .footer(|footer| footer.text(format!("Page {} of {}", page, pages)))
+                            .color(0x635bff)
+                    })
+                    .ephemeral(true)
+            })
+    }).await?;
+
+    Ok(())
+}
+
+async fn handle_admin_queue(ctx: &Context, command: &ApplicationCommandInteraction, database: &Database) -> Result<()> {
+    let action = command.data.options.get(0)
+        .and_then(|opt| opt.options.get(0))
+        .and_then(|opt| opt.value.as_ref())
+        .and_then(|val| val.as_str());
+
+    match action {
+        Some("dead") => list_dead_jobs(ctx, command, database).await,
+        Some("requeue") => requeue_dead_job(ctx, command, database).await,
+        _ => {
+            command.create_interaction_response(&ctx.http, |response| {
+                response
+                    .kind(InteractionResponseType::ChannelMessageWithSource)
+                    .interaction_response_data(|message| {
+                        message.content("Invalid queue action").ephemeral(true)
+                    })
+            }).await?;
+            Ok(())
+        }
+    }
+}
+
+async fn list_dead_jobs(ctx: &Context, command: &ApplicationCommandInteraction, database: &Database) -> Result<()> {
+    let queue_service = QueueService::new(database.clone());
+    let total = queue_service.get_dead_length().await?;
+    let jobs = queue_service.get_dead_jobs(10).await?;
+
+    let description = if jobs.is_empty() {
+        "The dead-letter queue is empty.".to_string()
+    } else {
+        let mut description = String::new();
+        for job in &jobs {
+            description.push_str(&format!(
+                "**{:?}** `{}`\n👤 <@{}> • 🔁 {} attempt(s)\n⚠️ {}\n\n",
+                job.job_type,
+                job.id,
+                job.discord_id,
+                job.attempts,
+                job.last_error.as_deref().unwrap_or("unknown error")
+            ));
+        }
+        description
+    };
+
+    command.create_interaction_response(&ctx.http, |response| {
+        response
+            .kind(InteractionResponseType::ChannelMessageWithSource)
+            .interaction_response_data(|message| {
+                message
+                    .embed(|embed| {
+                        embed
+                            .title(format!("💀 Dead Jobs ({} total)", total))
+                            .description(description)
+                            .footer(|f| f.text("Use /admin queue action:requeue job_id:<id> to retry a job"))
+                            .color(0xff6b6b)
+                    })
+                    .ephemeral(true)
+            })
+    }).await?;
+
+    Ok(())
+}
+
+async fn requeue_dead_job(ctx: &Context, command: &ApplicationCommandInteraction, database: &Database) -> Result<()> {
+    let job_id = command.data.options.get(0)
+        .and_then(|opt| opt.options.iter().find(|o| o.name == "job_id"))
+        .and_then(|opt| opt.value.as_ref())
+        .and_then(|val| val.as_str())
+        .and_then(|s| uuid::Uuid::parse_str(s).ok());
+
+    if job_id.is_none() {
+        command.create_interaction_response(&ctx.http, |response| {
+            response
+                .kind(InteractionResponseType::ChannelMessageWithSource)
+                .interaction_response_data(|message| {
+                    message.content("Please provide a valid job ID").ephemeral(true)
+                })
+        }).await?;
+        return Ok(());
+    }
+
+    let job_id = job_id.unwrap();
+    let queue_service = QueueService::new(database.clone());
+
+    if !queue_service.requeue_dead(job_id).await? {
+        command.create_interaction_response(&ctx.http, |response| {
+            response
+                .kind(InteractionResponseType::ChannelMessageWithSource)
+                .interaction_response_data(|message| {
+                    message.content("Job not found in the dead-letter queue").ephemeral(true)
+                })
+        }).await?;
+        return Ok(());
+    }
+
+    command.create_interaction_response(&ctx.http, |response| {
+        response
+            .kind(InteractionResponseType::ChannelMessageWithSource)
+            .interaction_response_data(|message| {
+                message
+                    .embed(|embed| {
+                        embed
+                            .title("✅ Job Requeued")
+                            .description(format!("Job `{}` has been moved back onto the queue", job_id))
+                            .color(0x00ff00)
+                    })
+                    .ephemeral(true)
+            })
+    }).await?;
+
+    Ok(())
+}
+
+async fn handle_admin_accounts(ctx: &Context, command: &ApplicationCommandInteraction, database: &Database, config: &Config) -> Result<()> {
+    let action = command.data.options.get(0)
+        .and_then(|opt| opt.options.get(0))
+        .and_then(|opt| opt.value.as_ref())
+        .and_then(|val| val.as_str());
+
+    let accounts = AccountService::new(
+        database.clone(),
+        PterodactylClient::new(config),
+        config.pterodactyl_user_email_domain.clone(),
+    );
+
+    match action {
+        Some("reconcile") => {
+            let report = accounts.reconcile().await?;
+
+            command.create_interaction_response(&ctx.http, |response| {
+                response
+                    .kind(InteractionResponseType::ChannelMessageWithSource)
+                    .interaction_response_data(|message| {
+                        message
+                            .embed(|embed| {
+                                embed
+                                    .title("🔗 Account Reconciliation Complete")
+                                    .field("Linked", report.linked.to_string(), true)
+                                    .field("Created", report.created.to_string(), true)
+                                    .field("Failed", report.failed.to_string(), true)
+                                    .color(if report.failed == 0 { 0x00ff00 } else { 0xffaa00 })
+                            })
+                            .ephemeral(true)
+                    })
+            }).await?;
+        }
+        Some("link") => {
+            let target_user = command.data.options.get(0)
+                .and_then(|opt| opt.options.iter().find(|o| o.name == "user"))
+                .and_then(|opt| opt.value.as_ref())
+                .and_then(|val| val.as_str())
+                .and_then(|s| s.parse::<u64>().ok());
+
+            let email = command.data.options.get(0)
+                .and_then(|opt| opt.options.iter().find(|o| o.name == "email"))
+                .and_then(|opt| opt.value.as_ref())
+                .and_then(|val| val.as_str());
+
+            if target_user.is_none() || email.is_none() {
+                command.create_interaction_response(&ctx.http, |response| {
+                    response
+                        .kind(InteractionResponseType::ChannelMessageWithSource)
+                        .interaction_response_data(|message| {
+                            message.content("Please provide both user and email").ephemeral(true)
+                        })
+                }).await?;
+                return Ok(());
+            }
+
+            let target_user = target_user.unwrap();
+            database.get_or_create_user(target_user).await?;
+
+            let content = match accounts.link(target_user, &format!("User {}", target_user), email, true).await {
+                Ok(link) => format!(
+                    "✅ Linked <@{}> to panel user **{}** (ID {}){}",
+                    target_user,
+                    link.panel_user.username,
+                    link.panel_user.id,
+                    if link.created { " — a new account was created" } else { "" }
+                ),
+                Err(e) => format!("❌ Failed to link account: {}", e),
+            };
+
+            command.create_interaction_response(&ctx.http, |response| {
+                response
+                    .kind(InteractionResponseType::ChannelMessageWithSource)
+                    .interaction_response_data(|message| {
+                        message.content(content).ephemeral(true)
+                    })
+            }).await?;
+        }
+        _ => {
+            command.create_interaction_response(&ctx.http, |response| {
+                response
+                    .kind(InteractionResponseType::ChannelMessageWithSource)
+                    .interaction_response_data(|message| {
+                        message.content("Invalid accounts action").ephemeral(true)
+                    })
+            }).await?;
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,201 @@
+use anyhow::Result;
+use chrono::Utc;
+use serenity::prelude::*;
+use serenity::all::{ApplicationCommandInteraction, InteractionResponseType};
+use crate::services::{BackupService, Database, PterodactylBackup, PterodactylClient};
+use crate::models::Server;
+use crate::config::Config;
+use super::{bool_option, reply, string_option};
+
+/// Longest automatic backup interval `/servers backups schedule` accepts, 30 days.
+const MAX_BACKUP_INTERVAL_HOURS: i64 = 720;
+
+/// Handles `/servers backups`; `backup_action` picks what to do.
+pub async fn handle_backups(ctx: &Context, command: &ApplicationCommandInteraction, database: &Database, config: &Config, discord_id: u64) -> Result<()> {
+    if !PterodactylClient::new(config).has_client_api() {
+        return reply(ctx, command, "❌ Backups are not available on this bot.").await;
+    }
+
+    let server_id = string_option(command, "server_id");
+    let server = match server_id {
+        Some(server_id) => database.get_server(&server_id).await?,
+        None => None,
+    };
+    let server = match server {
+        Some(server) if server.discord_id == discord_id => server,
+        _ => return reply(ctx, command, "❌ Server not found. Use `/servers list` to see your servers.").await,
+    };
+
+    let action = string_option(command, "backup_action").unwrap_or_else(|| "list".to_string());
+
+    // Most actions make several panel requests, more than Discord allows for a response
+    command.create_interaction_response(&ctx.http, |response| {
+        response
+            .kind(InteractionResponseType::DeferredChannelMessageWithSource)
+            .interaction_response_data(|message| message.ephemeral(true))
+    }).await?;
+
+    let backups = BackupService::new(database.clone(), PterodactylClient::new(config));
+    if action == "list" {
+        return list_backups(ctx, command, &backups, &server).await;
+    }
+
+    let content = match action.as_str() {
+        "create" => match backups.create(&server, string_option(command, "name").as_deref()).await {
+            Ok(backup) => format!("💾 Backup **{}** of **{}** started. ID: `{}`", backup.name, server.name, short_id(&backup)),
+            Err(e) => format!("❌ {}", e),
+        },
+        "download" => match with_backup(command, &backups, &server).await {
+            Ok(backup) => match backups.download_url(&server, &backup).await {
+                Ok(url) => format!("📥 [Download **{}**]({})\nThe link expires in a few minutes.", backup.name, url),
+                Err(e) => format!("❌ {}", e),
+            },
+            Err(e) => format!("❌ {}", e),
+        },
+        "restore" => {
+            let truncate = bool_option(command, "truncate").unwrap_or(false);
+            match with_backup(command, &backups, &server).await {
+                Ok(backup) => match backups.restore(&server, &backup, truncate).await {
+                    Ok(()) if truncate => format!("♻️ Deleting all files on **{}** and restoring **{}**. The server is unavailable until it finishes.", server.name, backup.name),
+                    Ok(()) => format!("♻️ Restoring **{}** onto **{}**. The server is unavailable until it finishes.", backup.name, server.name),
+                    Err(e) => format!("❌ {}", e),
+                },
+                Err(e) => format!("❌ {}", e),
+            }
+        }
+        "lock" => match with_backup(command, &backups, &server).await {
+            Ok(backup) => match backups.toggle_lock(&server, &backup).await {
+                Ok(backup) if backup.is_locked => format!("🔒 **{}** is now locked and won't be deleted or rotated out.", backup.name),
+                Ok(backup) => format!("🔓 **{}** is now unlocked.", backup.name),
+                Err(e) => format!("❌ {}", e),
+            },
+            Err(e) => format!("❌ {}", e),
+        },
+        "delete" => match with_backup(command, &backups, &server).await {
+            Ok(backup) => match backups.delete(&server, &backup).await {
+                Ok(()) => format!("🗑️ Deleted backup **{}**.", backup.name),
+                Err(e) => format!("❌ {}", e),
+            },
+            Err(e) => format!("❌ {}", e),
+        },
+        "schedule" => set_schedule(command, database, &backups, &server).await?,
+        _ => "❌ Invalid backup action".to_string(),
+    };
+
+    command.create_followup_message(&ctx.http, |message| message.content(content).ephemeral(true)).await?;
+
+    Ok(())
+}
+
+async fn list_backups(ctx: &Context, command: &ApplicationCommandInteraction, backups: &BackupService, server: &Server) -> Result<()> {
+    let (list, limit) = match (backups.list(server).await, backups.limit(server).await) {
+        (Ok(list), Ok(limit)) => (list, limit),
+        (Err(e), _) | (_, Err(e)) => {
+            command.create_followup_message(&ctx.http, |message| message.content(format!("❌ {}", e)).ephemeral(true)).await?;
+            return Ok(());
+        }
+    };
+    let used = list.iter().filter(|backup| backup.uses_slot()).count();
+
+    let description = if list.is_empty() {
+        "No backups yet. Create one with `/servers backups create`.".to_string()
+    } else {
+        list.iter()
+            .map(|backup| {
+                let state = if backup.completed_at.is_none() {
+                    "⏳"
+                } else if backup.is_successful {
+                    "✅"
+                } else {
+                    "❌"
+                };
+                format!(
+                    "{} {}**{}** `{}`\n{} • {}",
+                    state,
+                    if backup.is_locked { "🔒 " } else { "" },
+                    backup.name,
+                    short_id(backup),
+                    format_size(backup.bytes),
+                    backup.created_at.format("%Y-%m-%d %H:%M UTC"),
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    };
+
+    let schedule = match server.backup_interval_hours {
+        Some(hours) => format!("Every {}h", hours),
+        None => "Off".to_string(),
+    };
+
+    command.create_followup_message(&ctx.http, |message| {
+        message
+            .embed(|embed| {
+                embed
+                    .title(format!("💾 Backups: {}", server.name))
+                    .description(description)
+                    .field("Slots", format!("{} / {}", used, limit), true)
+                    .field("Automatic", schedule, true)
+                    .color(0x3498db)
+            })
+            .ephemeral(true)
+    }).await?;
+
+    Ok(())
+}
+
+async fn set_schedule(command: &ApplicationCommandInteraction, database: &Database, backups: &BackupService, server: &Server) -> Result<String> {
+    let hours = command.data.options.iter()
+        .find(|opt| opt.name == "hours")
+        .and_then(|opt| opt.value.as_ref())
+        .and_then(|val| val.as_i64())
+        .unwrap_or(0);
+
+    if hours <= 0 {
+        database.set_backup_schedule(&server.id.to_string(), None).await?;
+        return Ok(format!("⏹️ Automatic backups of **{}** are off.", server.name));
+    }
+
+    if hours > MAX_BACKUP_INTERVAL_HOURS {
+        return Ok(format!("❌ Automatic backups can run at most every {} hours.", MAX_BACKUP_INTERVAL_HOURS));
+    }
+
+    match backups.limit(server).await {
+        Ok(limit) if limit > 0 => {}
+        Ok(_) => return Ok("❌ This server has no backup slots to schedule backups into.".to_string()),
+        Err(e) => return Ok(format!("❌ {}", e)),
+    }
+
+    database.set_backup_schedule(&server.id.to_string(), Some(hours as u32)).await?;
+    let next = server.last_auto_backup_at
+        .map(|last| (last + chrono::Duration::hours(hours)).max(Utc::now()))
+        .unwrap_or_else(Utc::now);
+    Ok(format!(
+        "⏰ **{}** will be backed up every {} hours, next around {}. When every slot is in use the oldest unlocked backup is replaced.",
+        server.name,
+        hours,
+        next.format("%Y-%m-%d %H:%M UTC"),
+    ))
+}
+
+async fn with_backup(command: &ApplicationCommandInteraction, backups: &BackupService, server: &Server) -> crate::errors::BotResult<PterodactylBackup> {
+    match string_option(command, "backup_id") {
+        Some(backup_id) => backups.find(server, &backup_id).await,
+        None => Err(crate::errors::BotError::InvalidInput("Please provide a backup ID from `/servers backups list`".to_string())),
+    }
+}
+
+/// Enough of a backup's UUID to tell it apart in commands.
+fn short_id(backup: &PterodactylBackup) -> &str {
+    &backup.uuid[..backup.uuid.len().min(8)]
+}
+
+fn format_size(bytes: i64) -> String {
+    const MIB: f64 = 1024.0 * 1024.0;
+    let mib = bytes as f64 / MIB;
+    if mib >= 1024.0 {
+        format!("{:.2}GB", mib / 1024.0)
+    } else {
+        format!("{:.1}MB", mib)
+    }
+}
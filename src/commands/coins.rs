@@ -1,15 +1,42 @@
 use anyhow::Result;
 use serenity::prelude::*;
 use serenity::all::{ApplicationCommandInteraction, InteractionResponseType};
-use crate::services::Database;
+use crate::services::{format_cents, AccountService, Database, PaymentProviders, PterodactylClient};
 use crate::errors::BotError;
+use crate::models::{CoinChange, CoinTransaction, Order, ResourceGrant, TransactionReason, User};
 use crate::config::Config;
 
-pub async fn login(ctx: &Context, command: &ApplicationCommandInteraction, database: &Database) -> Result<()> {
+pub async fn login(ctx: &Context, command: &ApplicationCommandInteraction, database: &Database, config: &Config) -> Result<()> {
     let discord_id = command.user.id.0;
+
+    let email = command.data.options.iter()
+        .find(|opt| opt.name == "email")
+        .and_then(|opt| opt.value.as_ref())
+        .and_then(|val| val.as_str());
     
     match database.get_or_create_user(discord_id).await {
         Ok(user) => {
+            // Link a panel account the first time the user logs in
+            let panel_status = if user.pterodactyl_user_id.is_some() {
+                "🔗 Panel account linked".to_string()
+            } else {
+                let accounts = AccountService::new(
+                    database.clone(),
+                    PterodactylClient::new(config),
+                    config.pterodactyl_user_email_domain.clone(),
+                );
+
+                match accounts.link(discord_id, &command.user.name, email, false).await {
+                    Ok(link) => {
+                        if link.created && email.is_some() && config.pterodactyl_send_reset_link {
+                            send_reset_link(ctx, command, &link.panel_user.email, config).await;
+                        }
+                        format!("🔗 Panel account linked (`{}`)", link.panel_user.username)
+                    }
+                    Err(e) => format!("⚠️ Panel account could not be linked: {}\nRun `/login` again later.", e),
+                }
+            };
+
             command.create_interaction_response(&ctx.http, |response| {
                 response
                     .kind(InteractionResponseType::ChannelMessageWithSource)
@@ -18,8 +45,8 @@ pub async fn login(ctx: &Context, command: &ApplicationCommandInteraction, datab
                             .embed(|embed| {
                                 embed
                                     .title("Welcome to Shaden-RS!")
-                                    .description(format!("Successfully logged in!\n\n**Your Stats:**\n💰 Coins: {}\n🖥️ RAM: {}MB\n⚡ CPU: {}%\n💾 Disk: {}MB", 
-                                        user.coins, user.resources.ram, user.resources.cpu, user.resources.disk))
+                                    .description(format!("Successfully logged in!\n\n**Your Stats:**\n💰 Coins: {}\n🖥️ RAM: {}MB\n⚡ CPU: {}%\n💾 Disk: {}MB\n\n{}", 
+                                        user.coins, user.resources.ram, user.resources.cpu, user.resources.disk, panel_status))
                                     .color(0x00ff00)
                             })
                             .ephemeral(true)
@@ -42,7 +69,23 @@ pub async fn login(ctx: &Context, command: &ApplicationCommandInteraction, datab
     Ok(())
 }
 
-pub async fn handle_coins(ctx: &Context, command: &ApplicationCommandInteraction, database: &Database) -> Result<()> {
+async fn send_reset_link(ctx: &Context, command: &ApplicationCommandInteraction, email: &str, config: &Config) {
+    let reset_url = PterodactylClient::new(config).password_reset_url();
+    let result = command.user.direct_message(&ctx.http, |message| {
+        message.embed(|embed| {
+            embed
+                .title("🔑 Your Panel Account")
+                .description(format!("A panel account has been created for **{}**.\n\nSet your password here: {}\n\nThis link sends a one-time reset email to the address above.", email, reset_url))
+                .color(0x00ff00)
+        })
+    }).await;
+
+    if let Err(e) = result {
+        tracing::warn!("Failed to DM reset link to {}: {}", command.user.id, e);
+    }
+}
+
+pub async fn handle_coins(ctx: &Context, command: &ApplicationCommandInteraction, database: &Database, config: &Config) -> Result<()> {
     let discord_id = command.user.id.0;
     let action = command.data.options.get(0)
         .and_then(|opt| opt.value.as_ref())
@@ -50,10 +93,12 @@ pub async fn handle_coins(ctx: &Context, command: &ApplicationCommandInteraction
         .unwrap_or("balance");
 
     match action {
-        "balance" => show_balance(ctx, command, database, discord_id).await,
+        "balance" => show_balance(ctx, command, database, config, discord_id).await,
         "earn-afk" => earn_afk(ctx, command, database, discord_id).await,
         "earn-linkvertise" => earn_linkvertise(ctx, command, database, discord_id).await,
         "gift" => gift_coins(ctx, command, database, discord_id).await,
+        "history" => show_history(ctx, command, database, discord_id).await,
+        "buy" => buy_coins(ctx, command, database, config, discord_id).await,
         _ => {
             command.create_interaction_response(&ctx.http, |response| {
                 response
@@ -121,22 +166,21 @@ pub async fn transfer_coins(ctx: &Context, command: &ApplicationCommandInteracti
     let receiver = database.get_user(target_user).await?;
 
     match (sender, receiver) {
-        (Some(mut sender), Some(mut receiver)) => {
-            if let Err(e) = sender.deduct_coins(amount) {
-                command.create_interaction_response(&ctx.http, |response| {
-                    response
-                        .kind(InteractionResponseType::ChannelMessageWithSource)
-                        .interaction_response_data(|message| {
-                            message.content(format!("❌ Transfer failed: {}", e)).ephemeral(true)
-                        })
-                }).await?;
-                return Ok(());
-            }
-
-            receiver.add_coins(amount);
-            
-            database.update_user(&sender).await?;
-            database.update_user(&receiver).await?;
+        (Some(_), Some(_)) => {
+            let balance = match database.transfer_coins(discord_id, target_user, amount).await {
+                Ok(balance) => balance,
+                Err(e @ BotError::InsufficientCoins { .. }) => {
+                    command.create_interaction_response(&ctx.http, |response| {
+                        response
+                            .kind(InteractionResponseType::ChannelMessageWithSource)
+                            .interaction_response_data(|message| {
+                                message.content(format!("❌ Transfer failed: {}", e)).ephemeral(true)
+                            })
+                    }).await?;
+                    return Ok(());
+                }
+                Err(e) => return Err(e.into()),
+            };
 
             command.create_interaction_response(&ctx.http, |response| {
                 response
@@ -147,7 +191,7 @@ pub async fn transfer_coins(ctx: &Context, command: &ApplicationCommandInteracti
                                 embed
                                     .title("💸 Transfer Successful!")
                                     .description(format!("Successfully transferred **{} coins** to <@{}>!", amount, target_user))
-                                    .field("Your Balance", format!("{} coins", sender.coins), true)
+                                    .field("Your Balance", format!("{} coins", balance), true)
                                     .field("Transfer Amount", format!("{} coins", amount), true)
                                     .field("Transaction Fee", "0 coins", true)
                                     .color(0x00ff00)
@@ -170,9 +214,11 @@ pub async fn transfer_coins(ctx: &Context, command: &ApplicationCommandInteracti
     Ok(())
 }
 
-async fn show_balance(ctx: &Context, command: &ApplicationCommandInteraction, database: &Database, discord_id: u64) -> Result<()> {
+async fn show_balance(ctx: &Context, command: &ApplicationCommandInteraction, database: &Database, config: &Config, discord_id: u64) -> Result<()> {
     match database.get_user(discord_id).await {
         Ok(Some(user)) => {
+            let grants = database.get_active_grants(discord_id).await?;
+
             command.create_interaction_response(&ctx.http, |response| {
                 response
                     .kind(InteractionResponseType::ChannelMessageWithSource)
@@ -182,7 +228,16 @@ async fn show_balance(ctx: &Context, command: &ApplicationCommandInteraction, da
                                 embed
                                     .title("💰 Your Balance")
                                     .description(format!("You have **{}** coins", user.coins))
-                                    .color(0x00ff00)
+                                    .field("Resources (used / total, free)", format_resources(&user), false);
+
+                                if !grants.is_empty() {
+                                    embed.field("Timed Resources", format_grants(&grants, config), false);
+                                }
+                                if user.is_over_allocated() {
+                                    embed.field("⚠️ Over Allocated", "Your servers use more than your resources since a timed purchase ran out. Buy more resources or delete a server.", false);
+                                }
+
+                                embed.color(0x00ff00)
                             })
                             .ephemeral(true)
                     })
@@ -211,12 +266,196 @@ async fn show_balance(ctx: &Context, command: &ApplicationCommandInteraction, da
     Ok(())
 }
 
+fn format_grants(grants: &[ResourceGrant], config: &Config) -> String {
+    grants.iter()
+        .take(10)
+        .map(|grant| {
+            let name = config.store_config.items.iter()
+                .find(|item| item.id == grant.item_id)
+                .map(|item| item.name.as_str())
+                .unwrap_or(&grant.item_id);
+            format!("**{}**: {} — expires {}", name, grant.resources.summary(), grant.expires_at.format("%Y-%m-%d %H:%M UTC"))
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn format_resources(user: &User) -> String {
+    let total = &user.resources;
+    let used = &user.allocated;
+    let free = user.free_resources();
+
+    [
+        ("🖥️ RAM", used.ram, total.ram, free.ram, "MB"),
+        ("⚡ CPU", used.cpu, total.cpu, free.cpu, "%"),
+        ("💾 Disk", used.disk, total.disk, free.disk, "MB"),
+        ("🗄️ Databases", used.databases, total.databases, free.databases, ""),
+        ("🔌 Allocations", used.allocations, total.allocations, free.allocations, ""),
+        ("📦 Backups", used.backups, total.backups, free.backups, ""),
+    ]
+    .iter()
+    .map(|(name, used, total, free, unit)| format!("{}: {}{unit} / {}{unit} ({}{unit} free)", name, used, total, free, unit = unit))
+    .collect::<Vec<_>>()
+    .join("\n")
+}
+
+async fn buy_coins(ctx: &Context, command: &ApplicationCommandInteraction, database: &Database, config: &Config, discord_id: u64) -> Result<()> {
+    let package = command.data.options.iter()
+        .find(|opt| opt.name == "package")
+        .and_then(|opt| opt.value.as_ref())
+        .and_then(|val| val.as_str())
+        .and_then(|id| config.store_config.coin_package(id));
+
+    let package = match package {
+        Some(package) => package,
+        None => {
+            command.create_interaction_response(&ctx.http, |response| {
+                response
+                    .kind(InteractionResponseType::ChannelMessageWithSource)
+                    .interaction_response_data(|message| {
+                        message.content("❌ Please choose a valid coin package.").ephemeral(true)
+                    })
+            }).await?;
+            return Ok(());
+        }
+    };
+
+    if database.get_user(discord_id).await?.is_none() {
+        command.create_interaction_response(&ctx.http, |response| {
+            response
+                .kind(InteractionResponseType::ChannelMessageWithSource)
+                .interaction_response_data(|message| {
+                    message.content("You need to login first! Use `/login`").ephemeral(true)
+                })
+        }).await?;
+        return Ok(());
+    }
+
+    let providers = PaymentProviders::new(config);
+    let provider = match command.data.options.iter().find(|opt| opt.name == "provider") {
+        Some(option) => option.value.as_ref()
+            .and_then(|val| val.as_str())
+            .and_then(|name| providers.get(name)),
+        None => providers.default_provider().ok(),
+    };
+
+    let provider = match provider {
+        Some(provider) => provider,
+        None => {
+            command.create_interaction_response(&ctx.http, |response| {
+                response
+                    .kind(InteractionResponseType::ChannelMessageWithSource)
+                    .interaction_response_data(|message| {
+                        message.content("❌ That payment method is not available.").ephemeral(true)
+                    })
+            }).await?;
+            return Ok(());
+        }
+    };
+
+    // The order ID doubles as the idempotency key for the checkout
+    let mut order = Order::new(discord_id, provider.name().to_string(), package.id.clone(), package.price_cents, package.coins);
+    let checkout = provider.create_checkout(package, discord_id, &order.id.to_string()).await?;
+    order.checkout_id = checkout.id.clone();
+    database.create_order(&order).await?;
+
+    let description = match &checkout.url {
+        Some(url) => format!(
+            "**[Pay for {}]({})**\n\nYour coins are added as soon as the payment goes through.",
+            package.name, url
+        ),
+        None => format!(
+            "Your order for **{}** is waiting for an admin to confirm your payment.\n\nPayment reference: `{}`",
+            package.name, checkout.id
+        ),
+    };
+
+    command.create_interaction_response(&ctx.http, |response| {
+        response
+            .kind(InteractionResponseType::ChannelMessageWithSource)
+            .interaction_response_data(|message| {
+                message
+                    .embed(|embed| {
+                        embed
+                            .title("💳 Checkout")
+                            .description(description)
+                            .field("Coins", package.coins.to_string(), true)
+                            .field("Price", format!("{} {}", format_cents(package.price_cents), config.payment_currency.to_uppercase()), true)
+                            .footer(|footer| footer.text(format!("Order {}", order.id)))
+                            .color(0x635bff)
+                    })
+                    .ephemeral(true)
+            })
+    }).await?;
+
+    Ok(())
+}
+
+/// Ledger entries shown per page of `/coins history` and `/admin ledger`.
+pub const HISTORY_PAGE_SIZE: i64 = 10;
+
+async fn show_history(ctx: &Context, command: &ApplicationCommandInteraction, database: &Database, discord_id: u64) -> Result<()> {
+    let page = command.data.options.iter()
+        .find(|opt| opt.name == "page")
+        .and_then(|opt| opt.value.as_ref())
+        .and_then(|val| val.as_i64())
+        .unwrap_or(1)
+        .max(1) as u64;
+
+    let (transactions, total) = database.get_transactions(discord_id, page - 1, HISTORY_PAGE_SIZE).await?;
+    let pages = ((total + HISTORY_PAGE_SIZE as u64 - 1) / HISTORY_PAGE_SIZE as u64).max(1);
+
+    command.create_interaction_response(&ctx.http, |response| {
+        response
+            .kind(InteractionResponseType::ChannelMessageWithSource)
+            .interaction_response_data(|message| {
+                message
+                    .embed(|embed| {
+                        embed
+                            .title("📜 Coin History")
+                            .description(format_transactions(&transactions))
+                            .footer(|footer| footer.text(format!("Page {} of {} • {} transaction(s)", page, pages, total)))
+                            .color(0x00aaff)
+                    })
+                    .ephemeral(true)
+            })
+    }).await?;
+
+    Ok(())
+}
+
+/// One line per ledger entry, for history embeds.
+pub fn format_transactions(transactions: &[CoinTransaction]) -> String {
+    if transactions.is_empty() {
+        return "No transactions on this page.".to_string();
+    }
+
+    transactions.iter()
+        .map(|transaction| {
+            let mut line = format!(
+                "`{}` **{:+}** → {} — {}",
+                transaction.created_at.format("%Y-%m-%d %H:%M"),
+                transaction.amount,
+                transaction.balance_after,
+                transaction.reason.label()
+            );
+            if let Some(counterparty) = transaction.counterparty {
+                line.push_str(&format!(" (<@{}>)", counterparty));
+            }
+            if let Some(reference) = &transaction.reference {
+                line.push_str(&format!(" `{}`", reference));
+            }
+            line
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
 async fn earn_afk(ctx: &Context, command: &ApplicationCommandInteraction, database: &Database, discord_id: u64) -> Result<()> {
     // Placeholder implementation - in real bot, verify AFK page completion
     match database.get_user(discord_id).await {
-        Ok(Some(mut user)) => {
-            user.add_coins(10); // Award 10 coins for AFK
-            database.update_user(&user).await?;
+        Ok(Some(_)) => {
+            database.add_coins(discord_id, 10, CoinChange::new(TransactionReason::Afk)).await?; // Award 10 coins for AFK
             
             command.create_interaction_response(&ctx.http, |response| {
                 response
@@ -275,9 +514,8 @@ async fn earn_linkvertise(ctx: &Context, command: &ApplicationCommandInteraction
     }
 
     match database.get_user(discord_id).await {
-        Ok(Some(mut user)) => {
-            user.add_coins(25); // Award 25 coins for Linkvertise
-            database.update_user(&user).await?;
+        Ok(Some(_)) => {
+            database.add_coins(discord_id, 25, CoinChange::new(TransactionReason::Linkvertise)).await?; // Award 25 coins for Linkvertise
             
             command.create_interaction_response(&ctx.http, |response| {
                 response
@@ -358,23 +596,22 @@ async fn gift_coins(ctx: &Context, command: &ApplicationCommandInteraction, data
     let receiver = database.get_user(target_user).await?;
 
     match (sender, receiver) {
-        (Some(mut sender), Some(mut receiver)) => {
-            if let Err(e) = sender.deduct_coins(amount) {
-                command.create_interaction_response(&ctx.http, |response| {
-                    response
-                        .kind(InteractionResponseType::ChannelMessageWithSource)
-                        .interaction_response_data(|message| {
-                            message.content(format!("Error: {}", e)).ephemeral(true)
-                        })
-                }).await?;
-                return Ok(());
+        (Some(_), Some(_)) => {
+            match database.transfer_coins(discord_id, target_user, amount).await {
+                Ok(_) => {}
+                Err(e @ BotError::InsufficientCoins { .. }) => {
+                    command.create_interaction_response(&ctx.http, |response| {
+                        response
+                            .kind(InteractionResponseType::ChannelMessageWithSource)
+                            .interaction_response_data(|message| {
+                                message.content(format!("Error: {}", e)).ephemeral(true)
+                            })
+                    }).await?;
+                    return Ok(());
+                }
+                Err(e) => return Err(e.into()),
             }
 
-            receiver.add_coins(amount);
-            
-            database.update_user(&sender).await?;
-            database.update_user(&receiver).await?;
-
             command.create_interaction_response(&ctx.http, |response| {
                 response
                     .kind(InteractionResponseType::ChannelMessageWithSource)
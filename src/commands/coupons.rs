@@ -3,6 +3,7 @@ use serenity::prelude::*;
 use serenity::all::{ApplicationCommandInteraction, InteractionResponseType};
 use crate::services::Database;
 use crate::models::Resources;
+use crate::errors::BotError;
 
 pub async fn handle_coupons(ctx: &Context, command: &ApplicationCommandInteraction, database: &Database) -> Result<()> {
     let discord_id = command.user.id.0;
@@ -50,35 +51,22 @@ async fn redeem_coupon(ctx: &Context, command: &ApplicationCommandInteraction, d
     let coupon = database.get_coupon(code).await?;
 
     match (user, coupon) {
-        (Some(mut user), Some(mut coupon)) => {
-            if !coupon.can_be_used(discord_id) {
-                command.create_interaction_response(&ctx.http, |response| {
-                    response
-                        .kind(InteractionResponseType::ChannelMessageWithSource)
-                        .interaction_response_data(|message| {
-                            message.content("This coupon is invalid, expired, or you've already used it").ephemeral(true)
-                        })
-                }).await?;
-                return Ok(());
-            }
-
-            // Use the coupon
-            coupon.use_coupon(discord_id);
-            user.add_coins(coupon.coins);
-
-            // Add resources if any
-            if let Some(resources) = &coupon.resources {
-                user.resources.ram += resources.ram;
-                user.resources.cpu += resources.cpu;
-                user.resources.disk += resources.disk;
-                user.resources.databases += resources.databases;
-                user.resources.allocations += resources.allocations;
-                user.resources.backups += resources.backups;
-            }
-
-            // Update database
-            database.update_user(&user).await?;
-            database.update_coupon(&coupon).await?;
+        (Some(_), Some(_)) => {
+            // Claims the coupon and credits the user atomically
+            let coupon = match database.redeem_coupon(code, discord_id).await {
+                Ok(coupon) => coupon,
+                Err(BotError::CouponInvalid) => {
+                    command.create_interaction_response(&ctx.http, |response| {
+                        response
+                            .kind(InteractionResponseType::ChannelMessageWithSource)
+                            .interaction_response_data(|message| {
+                                message.content("This coupon is invalid, expired, or you've already used it").ephemeral(true)
+                            })
+                    }).await?;
+                    return Ok(());
+                }
+                Err(e) => return Err(e.into()),
+            };
 
             let mut description = format!("Successfully redeemed coupon **{}**!\n\n", code);
             description.push_str(&format!("💰 Coins: +{}\n", coupon.coins));
@@ -0,0 +1,120 @@
+use anyhow::Result;
+use serenity::prelude::*;
+use serenity::all::{ApplicationCommandInteraction, InteractionResponseType};
+use crate::services::{Database, PterodactylClient, PterodactylDatabase, ServerDatabaseService};
+use crate::config::Config;
+use super::{reply, string_option};
+
+/// Handles `/servers databases`; `database_action` picks what to do.
+/// Every reply is ephemeral since most of them carry credentials.
+pub async fn handle_databases(ctx: &Context, command: &ApplicationCommandInteraction, database: &Database, config: &Config, discord_id: u64) -> Result<()> {
+    if !PterodactylClient::new(config).has_client_api() {
+        return reply(ctx, command, "❌ Databases are not available on this bot.").await;
+    }
+
+    let server = match string_option(command, "server_id") {
+        Some(server_id) => database.get_server(&server_id).await?,
+        None => None,
+    };
+    let server = match server {
+        Some(server) if server.discord_id == discord_id => server,
+        _ => return reply(ctx, command, "❌ Server not found. Use `/servers list` to see your servers.").await,
+    };
+
+    let action = string_option(command, "database_action").unwrap_or_else(|| "list".to_string());
+
+    command.create_interaction_response(&ctx.http, |response| {
+        response
+            .kind(InteractionResponseType::DeferredChannelMessageWithSource)
+            .interaction_response_data(|message| message.ephemeral(true))
+    }).await?;
+
+    let databases = ServerDatabaseService::new(database.clone(), PterodactylClient::new(config));
+    let result = match action.as_str() {
+        "list" => databases.list(&server).await
+            .map(|list| {
+                let description = if list.is_empty() {
+                    "No databases yet. Create one with `/servers databases create`.".to_string()
+                } else {
+                    format!("Using {} of {} database slot(s).", list.len(), server.resources.databases)
+                };
+                (format!("🗄️ Databases: {}", server.name), description, database_fields(list))
+            }),
+        "create" => match string_option(command, "name") {
+            Some(name) => {
+                let remote = string_option(command, "remote").unwrap_or_else(|| "%".to_string());
+                databases.create(&server, &name, &remote).await
+                    .map(|created| (
+                        "🗄️ Database Created".to_string(),
+                        "Keep these credentials private; rotate the password if they leak.".to_string(),
+                        database_fields(vec![created]),
+                    ))
+            }
+            None => Ok(error_fields("Please provide a database `name`.")),
+        },
+        "rotate-password" | "delete" => match string_option(command, "database_id") {
+            Some(database_id) => match databases.find(&server, &database_id).await {
+                Ok(found) if action == "delete" => databases.delete(&server, &found).await
+                    .map(|()| (
+                        "🗑️ Database Deleted".to_string(),
+                        format!("Deleted **{}** and everything in it.", found.name),
+                        Vec::new(),
+                    )),
+                Ok(found) => databases.rotate_password(&server, &found).await
+                    .map(|rotated| (
+                        "🔑 Password Rotated".to_string(),
+                        "The old password no longer works.".to_string(),
+                        database_fields(vec![rotated]),
+                    )),
+                Err(e) => Err(e),
+            },
+            None => Ok(error_fields("Please provide a `database_id` from `/servers databases list`.")),
+        },
+        _ => Ok(error_fields("Invalid database action")),
+    };
+
+    let (title, description, fields) = result.unwrap_or_else(|e| error_fields(&e.to_string()));
+
+    command.create_followup_message(&ctx.http, |message| {
+        message
+            .embed(|embed| {
+                embed.title(title).description(description).color(0x3498db);
+                for (name, value) in fields {
+                    embed.field(name, value, false);
+                }
+                embed
+            })
+            .ephemeral(true)
+    }).await?;
+
+    Ok(())
+}
+
+type EmbedParts = (String, String, Vec<(String, String)>);
+
+/// One field per database with its connection details. Passwords are spoilered
+/// so they aren't readable over the owner's shoulder.
+fn database_fields(databases: Vec<PterodactylDatabase>) -> Vec<(String, String)> {
+    databases.into_iter()
+        .map(|database| {
+            let password = database.password
+                .map(|password| format!("||`{}`||", password))
+                .unwrap_or_else(|| "hidden".to_string());
+            (
+                format!("{} (`{}`)", database.name, database.id),
+                format!(
+                    "Host: `{}:{}`\nUsername: `{}`\nPassword: {}\nConnections from: `{}`",
+                    database.host.address,
+                    database.host.port,
+                    database.username,
+                    password,
+                    database.connections_from,
+                ),
+            )
+        })
+        .collect()
+}
+
+fn error_fields(message: &str) -> EmbedParts {
+    ("❌ Databases".to_string(), message.to_string(), Vec::new())
+}
@@ -2,6 +2,7 @@ use anyhow::Result;
 use serenity::prelude::*;
 use serenity::all::{ApplicationCommandInteraction, InteractionResponseType};
 use crate::services::Database;
+use crate::models::{CoinChange, TransactionReason};
 
 pub async fn handle_join_rewards(ctx: &Context, command: &ApplicationCommandInteraction, database: &Database) -> Result<()> {
     let discord_id = command.user.id.0;
@@ -43,8 +44,7 @@ async fn claim_join_reward(ctx: &Context, command: &ApplicationCommandInteractio
     }
 
     // Placeholder implementation - in real bot, verify Discord server membership
-    let user = database.get_user(discord_id).await?;
-    if user.is_none() {
+    if database.get_user(discord_id).await?.is_none() {
         command.create_interaction_response(&ctx.http, |response| {
             response
                 .kind(InteractionResponseType::ChannelMessageWithSource)
@@ -55,9 +55,7 @@ async fn claim_join_reward(ctx: &Context, command: &ApplicationCommandInteractio
         return Ok(());
     }
 
-    let mut user = user.unwrap();
-    user.add_coins(50); // Award 50 coins for joining
-    database.update_user(&user).await?;
+    database.add_coins(discord_id, 50, CoinChange::new(TransactionReason::JoinReward)).await?; // Award 50 coins for joining
 
     command.create_interaction_response(&ctx.http, |response| {
         response
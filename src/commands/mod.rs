@@ -1,6 +1,6 @@
 use anyhow::Result;
 use serenity::prelude::*;
-use serenity::all::{Command, CommandOptionType, ApplicationCommandInteraction};
+use serenity::all::{Command, CommandOptionType, ApplicationCommandInteraction, InteractionResponseType};
 use crate::config::Config;
 
 pub mod coins;
@@ -9,11 +9,23 @@ pub mod servers;
 pub mod store;
 pub mod admin;
 pub mod join_rewards;
+pub mod orders;
+pub mod backups;
+pub mod databases;
 
 pub async fn register_commands(ctx: &Context, config: &Config) -> Result<()> {
     // Register all slash commands
     Command::create_global_command(&ctx.http, |command| {
-        command.name("login").description("Register or login to the bot")
+        command
+            .name("login")
+            .description("Register or login to the bot")
+            .create_option(|option| {
+                option
+                    .name("email")
+                    .description("Email for your panel account")
+                    .kind(CommandOptionType::String)
+                    .required(false)
+            })
     }).await?;
 
     Command::create_global_command(&ctx.http, |command| {
@@ -30,6 +42,8 @@ pub async fn register_commands(ctx: &Context, config: &Config) -> Result<()> {
                     .add_string_choice("earn-afk", "earn-afk")
                     .add_string_choice("earn-linkvertise", "earn-linkvertise")
                     .add_string_choice("gift", "gift")
+                    .add_string_choice("history", "history")
+                    .add_string_choice("buy", "buy")
             })
             .create_option(|option| {
                 option
@@ -52,6 +66,60 @@ pub async fn register_commands(ctx: &Context, config: &Config) -> Result<()> {
                     .kind(CommandOptionType::String)
                     .required(false)
             })
+            .create_option(|option| {
+                option
+                    .name("page")
+                    .description("History page")
+                    .kind(CommandOptionType::Integer)
+                    .required(false)
+                    .min_int_value(1)
+            })
+            .create_option(|option| {
+                option
+                    .name("package")
+                    .description("Coin package to buy")
+                    .kind(CommandOptionType::String)
+                    .required(false);
+
+                for package in config.store_config.coin_packages.iter().filter(|p| p.enabled).take(25) {
+                    option.add_string_choice(&package.name, &package.id);
+                }
+
+                option
+            })
+            .create_option(|option| {
+                option
+                    .name("provider")
+                    .description("How to pay (defaults to the first enabled provider)")
+                    .kind(CommandOptionType::String)
+                    .required(false);
+
+                for provider in &config.payment_providers {
+                    option.add_string_choice(provider, provider);
+                }
+
+                option
+            })
+    }).await?;
+
+    Command::create_global_command(&ctx.http, |command| {
+        command
+            .name("orders")
+            .description("View your coin purchases")
+            .create_option(|option| {
+                option
+                    .name("list")
+                    .description("List your orders")
+                    .kind(CommandOptionType::SubCommand)
+                    .create_sub_option(|sub| {
+                        sub
+                            .name("page")
+                            .description("Orders page")
+                            .kind(CommandOptionType::Integer)
+                            .required(false)
+                            .min_int_value(1)
+                    })
+            })
     }).await?;
 
     if config.enable_transfer {
@@ -91,6 +159,11 @@ pub async fn register_commands(ctx: &Context, config: &Config) -> Result<()> {
                     .add_string_choice("create", "create")
                     .add_string_choice("view", "view")
                     .add_string_choice("power", "power")
+                    .add_string_choice("console", "console")
+                    .add_string_choice("logs", "logs")
+                    .add_string_choice("backups", "backups")
+                    .add_string_choice("databases", "databases")
+                    .add_string_choice("auto-renew", "auto-renew")
             })
             .create_option(|option| {
                 option
@@ -105,11 +178,101 @@ pub async fn register_commands(ctx: &Context, config: &Config) -> Result<()> {
                     .description("Server plan")
                     .kind(CommandOptionType::String)
                     .required(false)
+                    .set_autocomplete(true)
             })
             .create_option(|option| {
                 option
                     .name("name")
-                    .description("Server name")
+                    .description("Server name, or the backup or database name to create")
+                    .kind(CommandOptionType::String)
+                    .required(false)
+            })
+            .create_option(|option| {
+                option
+                    .name("software")
+                    .description("Software to run on the server")
+                    .kind(CommandOptionType::String)
+                    .required(false);
+
+                for template in config.template_config.templates.iter().filter(|t| t.enabled).take(25) {
+                    option.add_string_choice(&template.name, &template.id);
+                }
+
+                option
+            })
+            .create_option(|option| {
+                option
+                    .name("enabled")
+                    .description("Turn auto-renew on or off")
+                    .kind(CommandOptionType::Boolean)
+                    .required(false)
+            })
+            .create_option(|option| {
+                option
+                    .name("command")
+                    .description("Console command to send")
+                    .kind(CommandOptionType::String)
+                    .required(false)
+            })
+            .create_option(|option| {
+                option
+                    .name("backup_action")
+                    .description("What to do with the server's backups")
+                    .kind(CommandOptionType::String)
+                    .required(false)
+                    .add_string_choice("list", "list")
+                    .add_string_choice("create", "create")
+                    .add_string_choice("download", "download")
+                    .add_string_choice("restore", "restore")
+                    .add_string_choice("lock", "lock")
+                    .add_string_choice("delete", "delete")
+                    .add_string_choice("schedule", "schedule")
+            })
+            .create_option(|option| {
+                option
+                    .name("backup_id")
+                    .description("Backup ID from the backup list")
+                    .kind(CommandOptionType::String)
+                    .required(false)
+            })
+            .create_option(|option| {
+                option
+                    .name("truncate")
+                    .description("Delete every file before restoring")
+                    .kind(CommandOptionType::Boolean)
+                    .required(false)
+            })
+            .create_option(|option| {
+                option
+                    .name("hours")
+                    .description("Hours between automatic backups, 0 turns them off")
+                    .kind(CommandOptionType::Integer)
+                    .required(false)
+                    .min_int_value(0)
+                    .max_int_value(720)
+            })
+            .create_option(|option| {
+                option
+                    .name("database_action")
+                    .description("What to do with the server's databases")
+                    .kind(CommandOptionType::String)
+                    .required(false)
+                    .add_string_choice("list", "list")
+                    .add_string_choice("create", "create")
+                    .add_string_choice("rotate-password", "rotate-password")
+                    .add_string_choice("delete", "delete")
+            })
+            .create_option(|option| {
+                option
+                    .name("database_id")
+                    .description("Database ID or name from the database list")
+                    .kind(CommandOptionType::String)
+                    .required(false)
+            })
+            .create_option(|option| {
+                option
+                    .name("remote")
+                    .description("Addresses allowed to connect to a new database, % for any")
                     .kind(CommandOptionType::String)
                     .required(false)
             })
@@ -145,7 +308,7 @@ pub async fn register_commands(ctx: &Context, config: &Config) -> Result<()> {
                 .create_option(|option| {
                     option
                         .name("duration")
-                        .description("Renewal duration in days")
+                        .description("Renewal duration in days, a multiple of the plan's period")
                         .kind(CommandOptionType::Integer)
                         .required(false)
                         .min_int_value(1)
@@ -182,6 +345,249 @@ pub async fn register_commands(ctx: &Context, config: &Config) -> Result<()> {
                     .kind(CommandOptionType::String)
                     .required(false)
             })
+            .create_option(|option| {
+                option
+                    .name("software")
+                    .description("Software to run on a server you buy")
+                    .kind(CommandOptionType::String)
+                    .required(false);
+
+                for template in config.template_config.templates.iter().filter(|t| t.enabled).take(25) {
+                    option.add_string_choice(&template.name, &template.id);
+                }
+
+                option
+            })
+    }).await?;
+
+    Command::create_global_command(&ctx.http, |command| {
+        command
+            .name("admin")
+            .description("Administrative commands")
+            .create_option(|option| {
+                option
+                    .name("coins")
+                    .description("Manage a user's coins")
+                    .kind(CommandOptionType::SubCommand)
+                    .create_sub_option(|sub| {
+                        sub
+                            .name("action")
+                            .description("Action to perform")
+                            .kind(CommandOptionType::String)
+                            .required(true)
+                            .add_string_choice("set", "set")
+                            .add_string_choice("add", "add")
+                            .add_string_choice("remove", "remove")
+                    })
+                    .create_sub_option(|sub| {
+                        sub
+                            .name("user")
+                            .description("Target user")
+                            .kind(CommandOptionType::User)
+                            .required(true)
+                    })
+                    .create_sub_option(|sub| {
+                        sub
+                            .name("amount")
+                            .description("Amount of coins")
+                            .kind(CommandOptionType::Integer)
+                            .required(true)
+                    })
+            })
+            .create_option(|option| {
+                option
+                    .name("coupons")
+                    .description("Manage coupons")
+                    .kind(CommandOptionType::SubCommand)
+                    .create_sub_option(|sub| {
+                        sub
+                            .name("action")
+                            .description("Action to perform")
+                            .kind(CommandOptionType::String)
+                            .required(true)
+                            .add_string_choice("create", "create")
+                            .add_string_choice("revoke", "revoke")
+                    })
+                    .create_sub_option(|sub| {
+                        sub
+                            .name("code")
+                            .description("Coupon code")
+                            .kind(CommandOptionType::String)
+                            .required(true)
+                    })
+                    .create_sub_option(|sub| {
+                        sub
+                            .name("coins")
+                            .description("Coins granted by the coupon")
+                            .kind(CommandOptionType::Integer)
+                            .required(false)
+                    })
+            })
+            .create_option(|option| {
+                option
+                    .name("stats")
+                    .description("View system statistics")
+                    .kind(CommandOptionType::SubCommand)
+            })
+            .create_option(|option| {
+                option
+                    .name("accounts")
+                    .description("Link users to panel accounts")
+                    .kind(CommandOptionType::SubCommand)
+                    .create_sub_option(|sub| {
+                        sub
+                            .name("action")
+                            .description("Action to perform")
+                            .kind(CommandOptionType::String)
+                            .required(true)
+                            .add_string_choice("reconcile", "reconcile")
+                            .add_string_choice("link", "link")
+                    })
+                    .create_sub_option(|sub| {
+                        sub
+                            .name("user")
+                            .description("User to link")
+                            .kind(CommandOptionType::User)
+                            .required(false)
+                    })
+                    .create_sub_option(|sub| {
+                        sub
+                            .name("email")
+                            .description("Email of the existing panel account")
+                            .kind(CommandOptionType::String)
+                            .required(false)
+                    })
+            })
+            .create_option(|option| {
+                option
+                    .name("renewals")
+                    .description("List servers enrolled in auto-renew")
+                    .kind(CommandOptionType::SubCommand)
+            })
+            .create_option(|option| {
+                option
+                    .name("allocations")
+                    .description("List servers flagged as over their owner's resources")
+                    .kind(CommandOptionType::SubCommand)
+            })
+            .create_option(|option| {
+                option
+                    .name("ledger")
+                    .description("View a user's coin ledger or check it against balances")
+                    .kind(CommandOptionType::SubCommand)
+                    .create_sub_option(|sub| {
+                        sub
+                            .name("action")
+                            .description("Action to perform")
+                            .kind(CommandOptionType::String)
+                            .required(true)
+                            .add_string_choice("view", "view")
+                            .add_string_choice("check", "check")
+                            .add_string_choice("open", "open")
+                    })
+                    .create_sub_option(|sub| {
+                        sub
+                            .name("user")
+                            .description("User whose ledger to view")
+                            .kind(CommandOptionType::User)
+                            .required(false)
+                    })
+                    .create_sub_option(|sub| {
+                        sub
+                            .name("page")
+                            .description("Ledger page")
+                            .kind(CommandOptionType::Integer)
+                            .required(false)
+                            .min_int_value(1)
+                    })
+            })
+            .create_option(|option| {
+                option
+                    .name("orders")
+                    .description("Search, settle and refund coin orders")
+                    .kind(CommandOptionType::SubCommand)
+                    .create_sub_option(|sub| {
+                        sub
+                            .name("action")
+                            .description("Action to perform")
+                            .kind(CommandOptionType::String)
+                            .required(true)
+                            .add_string_choice("search", "search")
+                            .add_string_choice("complete", "complete")
+                            .add_string_choice("cancel", "cancel")
+                            .add_string_choice("refund", "refund")
+                            .add_string_choice("reconcile", "reconcile")
+                    })
+                    .create_sub_option(|sub| {
+                        sub
+                            .name("order_id")
+                            .description("Order to complete, cancel or refund")
+                            .kind(CommandOptionType::String)
+                            .required(false)
+                    })
+                    .create_sub_option(|sub| {
+                        sub
+                            .name("user")
+                            .description("Only orders by this user")
+                            .kind(CommandOptionType::User)
+                            .required(false)
+                    })
+                    .create_sub_option(|sub| {
+                        sub
+                            .name("status")
+                            .description("Only orders with this status")
+                            .kind(CommandOptionType::String)
+                            .required(false)
+                            .add_string_choice("Pending", "Pending")
+                            .add_string_choice("Completed", "Completed")
+                            .add_string_choice("Failed", "Failed")
+                            .add_string_choice("Cancelled", "Cancelled")
+                    })
+                    .create_sub_option(|sub| {
+                        sub
+                            .name("from")
+                            .description("Only orders placed on or after this date (YYYY-MM-DD)")
+                            .kind(CommandOptionType::String)
+                            .required(false)
+                    })
+                    .create_sub_option(|sub| {
+                        sub
+                            .name("to")
+                            .description("Only orders placed on or before this date (YYYY-MM-DD)")
+                            .kind(CommandOptionType::String)
+                            .required(false)
+                    })
+                    .create_sub_option(|sub| {
+                        sub
+                            .name("page")
+                            .description("Results page")
+                            .kind(CommandOptionType::Integer)
+                            .required(false)
+                            .min_int_value(1)
+                    })
+            })
+            .create_option(|option| {
+                option
+                    .name("queue")
+                    .description("Inspect and requeue dead jobs")
+                    .kind(CommandOptionType::SubCommand)
+                    .create_sub_option(|sub| {
+                        sub
+                            .name("action")
+                            .description("Action to perform")
+                            .kind(CommandOptionType::String)
+                            .required(true)
+                            .add_string_choice("dead", "dead")
+                            .add_string_choice("requeue", "requeue")
+                    })
+                    .create_sub_option(|sub| {
+                        sub
+                            .name("job_id")
+                            .description("Dead job ID to requeue")
+                            .kind(CommandOptionType::String)
+                            .required(false)
+                    })
+            })
     }).await?;
 
     // Add more command registrations here...
@@ -199,16 +605,24 @@ pub async fn help(ctx: &Context, command: &ApplicationCommandInteraction) -> Res
                         embed
                             .title("Shaden-RS Bot Help")
                             .description("Available commands:")
-                            .field("/login", "Register or login to the bot", false)
+                            .field("/login [email]", "Register or login and link your panel account", false)
                             .field("/coins balance", "Check your coin balance", false)
                             .field("/coins earn afk", "Earn coins via AFK page", false)
                             .field("/coins gift <user> <amount>", "Gift coins to another user", false)
                             .field("/transfer <user> <amount>", "Transfer coins to another user", false)
                             .field("/servers list", "List your servers", false)
-                            .field("/servers create <plan> <name>", "Create a new server", false)
+                            .field("/servers create <plan> <name> [software]", "Create a new server", false)
+                            .field("/servers view <server_id>", "View a server and its live usage", false)
                             .field("/delete <server_id>", "Delete a server", false)
                             .field("/renew <server_id> [duration]", "Renew a server", false)
+                            .field("/servers auto-renew <server_id> <enabled>", "Renew a server from your balance before it expires", false)
+                            .field("/servers power <server_id>", "Start, stop, restart or kill a server", false)
+                            .field("/servers console <server_id> <command>", "Send a command to a server's console", false)
+                            .field("/servers logs <server_id>", "Show a server's recent console output", false)
+                            .field("/servers backups <server_id> [backup_action]", "List, create, download, restore, lock, delete or schedule backups", false)
+                            .field("/servers databases <server_id> [database_action]", "List, create, rotate the password of or delete databases", false)
                             .field("/store list", "View available items", false)
+                            .field("/orders list", "View your coin purchases", false)
                             .color(0x00ff00)
                     })
                     .ephemeral(true)
@@ -217,3 +631,30 @@ pub async fn help(ctx: &Context, command: &ApplicationCommandInteraction) -> Res
 
     Ok(())
 }
+
+/// Replies with an ephemeral message.
+pub(crate) async fn reply(ctx: &Context, command: &ApplicationCommandInteraction, content: &str) -> Result<()> {
+    command.create_interaction_response(&ctx.http, |response| {
+        response
+            .kind(InteractionResponseType::ChannelMessageWithSource)
+            .interaction_response_data(|message| message.content(content).ephemeral(true))
+    }).await?;
+    Ok(())
+}
+
+/// A top-level string option, trimmed, or `None` if missing or blank.
+pub(crate) fn string_option(command: &ApplicationCommandInteraction, name: &str) -> Option<String> {
+    command.data.options.iter()
+        .find(|opt| opt.name == name)
+        .and_then(|opt| opt.value.as_ref())
+        .and_then(|val| val.as_str())
+        .map(|val| val.trim().to_string())
+        .filter(|val| !val.is_empty())
+}
+
+pub(crate) fn bool_option(command: &ApplicationCommandInteraction, name: &str) -> Option<bool> {
+    command.data.options.iter()
+        .find(|opt| opt.name == name)
+        .and_then(|opt| opt.value.as_ref())
+        .and_then(|val| val.as_bool())
+}
@@ -0,0 +1,98 @@
+use anyhow::Result;
+use serenity::prelude::*;
+use serenity::all::{ApplicationCommandInteraction, InteractionResponseType};
+use crate::services::{format_cents, Database};
+use crate::models::{Order, OrderQuery};
+use crate::config::Config;
+
+/// Orders shown per page of `/orders list` and `/admin orders search`.
+pub const ORDERS_PAGE_SIZE: i64 = 10;
+
+pub async fn handle_orders(ctx: &Context, command: &ApplicationCommandInteraction, database: &Database, config: &Config) -> Result<()> {
+    let subcommand = command.data.options.get(0)
+        .map(|opt| opt.name.as_str());
+
+    match subcommand {
+        Some("list") => list_orders(ctx, command, database, config).await,
+        _ => {
+            command.create_interaction_response(&ctx.http, |response| {
+                response
+                    .kind(InteractionResponseType::ChannelMessageWithSource)
+                    .interaction_response_data(|message| {
+                        message.content("Invalid orders command").ephemeral(true)
+                    })
+            }).await?;
+            Ok(())
+        }
+    }
+}
+
+async fn list_orders(ctx: &Context, command: &ApplicationCommandInteraction, database: &Database, config: &Config) -> Result<()> {
+    let discord_id = command.user.id.0;
+
+    let page = command.data.options.get(0)
+        .and_then(|opt| opt.options.iter().find(|o| o.name == "page"))
+        .and_then(|opt| opt.value.as_ref())
+        .and_then(|val| val.as_i64())
+        .unwrap_or(1)
+        .max(1) as u64;
+
+    let query = OrderQuery {
+        discord_id: Some(discord_id),
+        ..Default::default()
+    };
+    let (orders, total) = database.find_orders(&query, page - 1, ORDERS_PAGE_SIZE).await?;
+    let pages = ((total + ORDERS_PAGE_SIZE as u64 - 1) / ORDERS_PAGE_SIZE as u64).max(1);
+
+    let description = if total == 0 {
+        "You haven't bought any coins yet. Use `/coins buy` to get some!".to_string()
+    } else {
+        format_orders(&orders, config, false)
+    };
+
+    command.create_interaction_response(&ctx.http, |response| {
+        response
+            .kind(InteractionResponseType::ChannelMessageWithSource)
+            .interaction_response_data(|message| {
+                message
+                    .embed(|embed| {
+                        embed
+                            .title("🧾 Your Orders")
+                            .description(description)
+                            .footer(|footer| footer.text(format!("Page {} of {} • {} order(s)", page, pages, total)))
+                            .color(0x635bff)
+                    })
+                    .ephemeral(true)
+            })
+    }).await?;
+
+    Ok(())
+}
+
+/// One line per order; `with_user` adds the buyer, for admins.
+pub fn format_orders(orders: &[Order], config: &Config, with_user: bool) -> String {
+    if orders.is_empty() {
+        return "No orders on this page.".to_string();
+    }
+
+    let currency = config.payment_currency.to_uppercase();
+    orders.iter()
+        .map(|order| {
+            let mut line = format!(
+                "`{}` **{:?}** — {} coins for {} {} via {}",
+                order.created_at.format("%Y-%m-%d %H:%M"),
+                order.status,
+                order.coins,
+                format_cents(order.amount_cents),
+                currency,
+                order.provider
+            );
+            if with_user {
+                line.push_str(&format!(" • <@{}>", order.discord_id));
+            }
+            line.push_str(&format!("\n└ `{}`", order.id));
+            line
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
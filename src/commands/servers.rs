@@ -1,9 +1,11 @@
 use anyhow::Result;
 use serenity::prelude::*;
-use serenity::all::{ApplicationCommandInteraction, InteractionResponseType};
-use crate::services::{Database, QueueService, QueueJob, JobType};
-use crate::models::{Server, Resources, ServerStatus};
+use tracing::warn;
+use serenity::all::{ApplicationCommandInteraction, AttachmentType, AutocompleteInteraction, ButtonStyle, CreateComponents, CreateEmbed, InteractionResponseType, MessageComponentInteraction};
+use crate::services::{ConsoleService, Database, LifecycleService, PowerService, PowerSignal, PterodactylClient, QueueService, QueueJob, JobType, ServerResourceUsage};
+use crate::models::{CoinChange, Server, ServerStatus, TransactionReason};
 use crate::config::Config;
+use crate::errors::BotError;
 
 pub async fn handle_servers(ctx: &Context, command: &ApplicationCommandInteraction, database: &Database, config: &Config) -> Result<()> {
     let discord_id = command.user.id.0;
@@ -14,11 +16,16 @@ pub async fn handle_servers(ctx: &Context, command: &ApplicationCommandInteracti
 
     match action {
         "list" => list_servers(ctx, command, database, discord_id).await,
-        "create" => create_server(ctx, command, database, discord_id).await,
-        "view" => view_server(ctx, command, database, discord_id).await,
+        "create" => create_server(ctx, command, database, config, discord_id).await,
+        "view" => view_server(ctx, command, database, config, discord_id).await,
         "delete" => delete_server_command(ctx, command, database, config).await,
-        "power" => power_server(ctx, command, database, discord_id).await,
+        "power" => power_server(ctx, command, database, config, discord_id).await,
+        "console" => console_command(ctx, command, database, config, discord_id).await,
+        "logs" => tail_logs(ctx, command, database, config, discord_id).await,
+        "backups" => super::backups::handle_backups(ctx, command, database, config, discord_id).await,
+        "databases" => super::databases::handle_databases(ctx, command, database, config, discord_id).await,
         "renew" => renew_server(ctx, command, database, config).await,
+        "auto-renew" => set_auto_renew(ctx, command, database, config, discord_id).await,
         _ => {
             command.create_interaction_response(&ctx.http, |response| {
                 response
@@ -59,17 +66,19 @@ async fn list_servers(ctx: &Context, command: &ApplicationCommandInteraction, da
                         ServerStatus::Creating => "🟡",
                         ServerStatus::Suspended => "🟠",
                         ServerStatus::Deleted => "⚫",
+                        ServerStatus::Failed => "❌",
                     };
                     
                     description.push_str(&format!(
-                        "{} **{}** ({})\n📊 {}MB RAM, {}% CPU, {}MB Disk\n⏰ Expires: {}\n\n",
+                        "{} **{}** ({})\n📊 {}MB RAM, {}% CPU, {}MB Disk\n⏰ Expires: {}\n{}\n",
                         status_emoji,
                         server.name,
                         server.id,
                         server.resources.ram,
                         server.resources.cpu,
                         server.resources.disk,
-                        server.expires_at.format("%Y-%m-%d %H:%M UTC")
+                        server.expires_at.format("%Y-%m-%d %H:%M UTC"),
+                        if server.over_allocated { "⚠️ Over your resources\n" } else { "" }
                     ));
                 }
 
@@ -103,7 +112,37 @@ async fn list_servers(ctx: &Context, command: &ApplicationCommandInteraction, da
     Ok(())
 }
 
-async fn create_server(ctx: &Context, command: &ApplicationCommandInteraction, database: &Database, discord_id: u64) -> Result<()> {
+/// Suggests visible plans for the `plan` option of `/servers create`.
+pub async fn autocomplete_plans(ctx: &Context, autocomplete: &AutocompleteInteraction, config: &Config) -> Result<()> {
+    let typed = autocomplete.data.options.iter()
+        .find(|opt| opt.focused)
+        .and_then(|opt| opt.value.as_ref())
+        .and_then(|val| val.as_str())
+        .unwrap_or("")
+        .to_lowercase();
+
+    let plans: Vec<_> = config.store_config.plans.iter()
+        .filter(|plan| plan.visible)
+        .filter(|plan| plan.id.contains(&typed) || plan.name.to_lowercase().contains(&typed))
+        .take(25) // Discord's limit on autocomplete choices
+        .collect();
+
+    autocomplete.create_autocomplete_response(&ctx.http, |response| {
+        for plan in plans {
+            let label = if plan.price == 0 {
+                format!("{} - {} (free)", plan.name, plan.description)
+            } else {
+                format!("{} - {} ({} coins)", plan.name, plan.description, plan.price)
+            };
+            response.add_string_choice(label, &plan.id);
+        }
+        response
+    }).await?;
+
+    Ok(())
+}
+
+async fn create_server(ctx: &Context, command: &ApplicationCommandInteraction, database: &Database, config: &Config, discord_id: u64) -> Result<()> {
     let plan = command.data.options.iter()
         .find(|opt| opt.name == "plan")
         .and_then(|opt| opt.value.as_ref())
@@ -114,6 +153,11 @@ async fn create_server(ctx: &Context, command: &ApplicationCommandInteraction, d
         .and_then(|opt| opt.value.as_ref())
         .and_then(|val| val.as_str());
 
+    let software = command.data.options.iter()
+        .find(|opt| opt.name == "software")
+        .and_then(|opt| opt.value.as_ref())
+        .and_then(|val| val.as_str());
+
     if plan.is_none() || name.is_none() {
         command.create_interaction_response(&ctx.http, |response| {
             response
@@ -125,79 +169,80 @@ async fn create_server(ctx: &Context, command: &ApplicationCommandInteraction, d
         return Ok(());
     }
 
-    let plan = plan.unwrap();
+    let plan_id = plan.unwrap();
     let name = name.unwrap();
 
-    // Get user to check resources
-    let user = database.get_user(discord_id).await?;
-    if user.is_none() {
-        command.create_interaction_response(&ctx.http, |response| {
-            response
-                .kind(InteractionResponseType::ChannelMessageWithSource)
-                .interaction_response_data(|message| {
-                    message.content("You need to login first! Use `/login`").ephemeral(true)
-                })
-        }).await?;
-        return Ok(());
-    }
-
-    let user = user.unwrap();
-
-    // Define plan resources (this should be configurable)
-    let plan_resources = match plan {
-        "free" => Resources {
-            ram: 512,
-            cpu: 50,
-            disk: 1024,
-            databases: 1,
-            allocations: 1,
-            backups: 1,
-        },
-        "basic" => Resources {
-            ram: 1024,
-            cpu: 100,
-            disk: 2048,
-            databases: 2,
-            allocations: 2,
-            backups: 2,
-        },
+    // Hidden plans can only be handed out by admins
+    let plan = match config.store_config.plan(plan_id) {
+        Some(plan) if plan.visible || config.is_admin(discord_id) => plan,
         _ => {
+            let available: Vec<&str> = config.store_config.plans.iter()
+                .filter(|plan| plan.visible)
+                .map(|plan| plan.id.as_str())
+                .collect();
+            command.create_interaction_response(&ctx.http, |response| {
+                response
+                    .kind(InteractionResponseType::ChannelMessageWithSource)
+                    .interaction_response_data(|message| {
+                        message.content(format!("Invalid plan. Available plans: {}", available.join(", "))).ephemeral(true)
+                    })
+            }).await?;
+            return Ok(());
+        }
+    };
+
+    let software = software.unwrap_or_else(|| plan.default_template(&config.template_config));
+    let template = match config.template_config.get(software) {
+        Some(template) if plan.allows_template(&template.id) => template,
+        Some(_) => {
             command.create_interaction_response(&ctx.http, |response| {
                 response
                     .kind(InteractionResponseType::ChannelMessageWithSource)
                     .interaction_response_data(|message| {
-                        message.content("Invalid plan. Available plans: free, basic").ephemeral(true)
+                        message.content(format!("The {} plan doesn't support '{}'", plan.name, software)).ephemeral(true)
+                    })
+            }).await?;
+            return Ok(());
+        }
+        None => {
+            command.create_interaction_response(&ctx.http, |response| {
+                response
+                    .kind(InteractionResponseType::ChannelMessageWithSource)
+                    .interaction_response_data(|message| {
+                        message.content(format!("Invalid software '{}'", software)).ephemeral(true)
                     })
             }).await?;
             return Ok(());
         }
     };
 
-    // Check if user has enough resources
-    if user.resources.ram < plan_resources.ram ||
-       user.resources.cpu < plan_resources.cpu ||
-       user.resources.disk < plan_resources.disk {
+    // Count, reserve, charge and save in one transaction so concurrent
+    // creates can't both slip under the plan's limit
+    let mut server = Server::new(discord_id, name.to_string(), plan.id.clone(), template.id.clone(), plan.resources.to_resources(), plan.duration_days);
+    server.prepaid_coins = plan.price as i64;
+
+    let change = CoinChange::new(TransactionReason::Store).reference(plan.id.clone());
+    if let Err(e) = database.create_plan_server(&server, plan.price as i64, plan.max_per_user, change).await {
+        let content = match e {
+            BotError::UserNotFound => "You need to login first! Use `/login`".to_string(),
+            BotError::PlanLimitReached(max_per_user) => format!("You can only have {} server(s) on the {} plan", max_per_user, plan.name),
+            BotError::InsufficientResources(missing) => format!("Insufficient resources for this plan. Not enough free: {}", missing.join(", ")),
+            BotError::InsufficientCoins { needed, available } => format!("The {} plan costs {} coins, but you only have {}", plan.name, needed, available),
+            e => return Err(e.into()),
+        };
         command.create_interaction_response(&ctx.http, |response| {
             response
                 .kind(InteractionResponseType::ChannelMessageWithSource)
-                .interaction_response_data(|message| {
-                    message.content("Insufficient resources for this plan").ephemeral(true)
-                })
+                .interaction_response_data(|message| message.content(content).ephemeral(true))
         }).await?;
         return Ok(());
     }
 
-    // Create server and add to queue
-    let server = Server::new(discord_id, name.to_string(), plan.to_string(), plan_resources);
-    database.create_server(&server).await?;
-
+    // Respond before enqueuing: the worker edits this message as soon as it
+    // picks the job up, and can't edit a response that doesn't exist yet
     let queue_service = QueueService::new(database.clone());
-    let job = QueueJob::new(
-        JobType::CreateServer,
-        discord_id,
-        serde_json::json!({ "server_id": server.id.to_string() })
-    );
-    queue_service.enqueue(job).await?;
+    let position = queue_service.get_queue_length().await? + 1;
+    let wait = queue_service.estimate_wait(position, config.queue_workers).await?;
 
     command.create_interaction_response(&ctx.http, |response| {
         response
@@ -207,17 +252,121 @@ async fn create_server(ctx: &Context, command: &ApplicationCommandInteraction, d
                     .embed(|embed| {
                         embed
                             .title("🚀 Server Creation Started")
-                            .description(format!("Server **{}** has been queued for creation!\n\nServer ID: `{}`\nPlan: {}\n\nYour server will be created shortly.", name, server.id, plan))
+                            .description(format!("Server **{}** has been queued for creation!\n\nServer ID: `{}`\nPlan: {}\nSoftware: {}\n\nYour server will be created shortly.", name, server.id, plan.name, template.name))
+                            .field("Queue Position", format_position(Some(position), position), true)
+                            .field("Estimated Wait", format_wait(Some(wait)), true)
                             .color(0x00ff00)
                     })
                     .ephemeral(true)
             })
     }).await?;
 
+    let job = QueueJob::new(
+        JobType::CreateServer,
+        discord_id,
+        serde_json::json!({
+            "server_id": server.id.to_string(),
+            "interaction_token": command.token,
+        })
+    );
+    let job_id = job.id;
+    if let Err(e) = queue_service.enqueue(job).await {
+        // Nothing will create the server, so undo the creation
+        database.cancel_server_creation(&server).await?;
+        // The queue error is the one worth reporting, not a failed edit
+        command.edit_original_interaction_response(&ctx.http, |response| {
+            response.embed(|embed| {
+                embed
+                    .title("❌ Server Creation Failed")
+                    .description(format!("Server **{}** couldn't be queued for creation. Your coins have been refunded, please try again later.", name))
+                    .color(0xff0000)
+            })
+        }).await.ok();
+        return Err(e.into());
+    }
+
+    spawn_position_updates(ctx.clone(), command.clone(), database.clone(), config.queue_workers, job_id, position, server);
+
     Ok(())
 }
 
-async fn view_server(ctx: &Context, command: &ApplicationCommandInteraction, database: &Database, discord_id: u64) -> Result<()> {
+// Polled often so the task lets go of the message as soon as a worker
+// picks the job up; the message is only edited when the position changes
+const POSITION_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(2);
+const POSITION_UPDATE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(15);
+// Interaction responses can only be edited for 15 minutes
+const POSITION_UPDATE_WINDOW: std::time::Duration = std::time::Duration::from_secs(14 * 60);
+
+/// Keeps the "Server Creation Started" message's queue position and ETA current
+/// until a worker picks the job up and takes over the message. Stops for good
+/// once the job leaves the waiting set so a stale position never overwrites
+/// the worker's progress.
+fn spawn_position_updates(ctx: Context, command: ApplicationCommandInteraction, database: Database, workers: usize, job_id: uuid::Uuid, shown_position: usize, server: Server) {
+    tokio::spawn(async move {
+        let queue_service = QueueService::new(database);
+        let started = std::time::Instant::now();
+        let mut last_position = shown_position;
+        let mut last_edit = std::time::Instant::now();
+
+        while started.elapsed() < POSITION_UPDATE_WINDOW {
+            tokio::time::sleep(POSITION_POLL_INTERVAL).await;
+
+            let position = match queue_service.get_job_position(job_id).await {
+                Ok(Some(position)) => position,
+                _ => break,
+            };
+
+            if position == last_position || last_edit.elapsed() < POSITION_UPDATE_INTERVAL {
+                continue;
+            }
+
+            let queue_length = queue_service.get_queue_length().await.unwrap_or(position);
+            let wait = queue_service.estimate_wait(position, workers).await.ok();
+
+            // The lookups above take time; make sure no worker has taken over
+            // the message in the meantime
+            match queue_service.get_job_position(job_id).await {
+                Ok(Some(current)) if current == position => {}
+                Ok(Some(_)) => continue,
+                _ => break,
+            }
+
+            let result = command.edit_original_interaction_response(&ctx.http, |response| {
+                response.embed(|embed| {
+                    embed
+                        .title("🚀 Server Creation Started")
+                        .description(format!("Server **{}** has been queued for creation!\n\nServer ID: `{}`\nPlan: {}\n\nYour server will be created shortly.", server.name, server.id, server.plan))
+                        .field("Queue Position", format_position(Some(position), queue_length), true)
+                        .field("Estimated Wait", format_wait(wait), true)
+                        .color(0x00ff00)
+                })
+            }).await;
+
+            if result.is_err() {
+                break;
+            }
+            last_position = position;
+            last_edit = std::time::Instant::now();
+        }
+    });
+}
+
+pub fn format_position(position: Option<usize>, queue_length: usize) -> String {
+    match position {
+        Some(position) => format!("#{} of {}", position, queue_length.max(position)),
+        None => "Processing".to_string(),
+    }
+}
+
+pub fn format_wait(wait_secs: Option<u64>) -> String {
+    match wait_secs {
+        Some(secs) if secs < 60 => "< 1 minute".to_string(),
+        Some(secs) => format!("~{} minute(s)", (secs + 59) / 60),
+        None => "Starting now".to_string(),
+    }
+}
+
+async fn view_server(ctx: &Context, command: &ApplicationCommandInteraction, database: &Database, config: &Config, discord_id: u64) -> Result<()> {
     let server_id = command.data.options.iter()
         .find(|opt| opt.name == "server_id")
         .and_then(|opt| opt.value.as_ref())
@@ -237,7 +386,7 @@ async fn view_server(ctx: &Context, command: &ApplicationCommandInteraction, dat
     let server_id = server_id.unwrap();
 
     match database.get_server(server_id).await {
-        Ok(Some(server)) => {
+        Ok(Some(mut server)) => {
             if server.discord_id != discord_id {
                 command.create_interaction_response(&ctx.http, |response| {
                     response
@@ -249,31 +398,18 @@ async fn view_server(ctx: &Context, command: &ApplicationCommandInteraction, dat
                 return Ok(());
             }
 
-            let status_emoji = match server.status {
-                ServerStatus::Running => "🟢 Running",
-                ServerStatus::Stopped => "🔴 Stopped",
-                ServerStatus::Creating => "🟡 Creating",
-                ServerStatus::Suspended => "🟠 Suspended",
-                ServerStatus::Deleted => "⚫ Deleted",
-            };
+            let usage = live_usage(database, config, &mut server).await;
+            let refreshable = usage.is_some();
 
             command.create_interaction_response(&ctx.http, |response| {
                 response
                     .kind(InteractionResponseType::ChannelMessageWithSource)
                     .interaction_response_data(|message| {
-                        message
-                            .embed(|embed| {
-                                embed
-                                    .title(format!("🖥️ Server: {}", server.name))
-                                    .field("Status", status_emoji, true)
-                                    .field("Plan", &server.plan, true)
-                                    .field("Server ID", server.id.to_string(), true)
-                                    .field("Resources", format!("🖥️ {}MB RAM\n⚡ {}% CPU\n💾 {}MB Disk", server.resources.ram, server.resources.cpu, server.resources.disk), true)
-                                    .field("Limits", format!("🗄️ {} Databases\n🌐 {} Allocations\n💾 {} Backups", server.resources.databases, server.resources.allocations, server.resources.backups), true)
-                                    .field("Expires", server.expires_at.format("%Y-%m-%d %H:%M UTC").to_string(), true)
-                                    .color(0x00ff00)
-                            })
-                            .ephemeral(true)
+                        message.embed(|embed| server_embed(embed, &server, config, usage.as_ref()));
+                        if refreshable {
+                            message.components(|components| refresh_button(components, &server));
+                        }
+                        message.ephemeral(true)
                     })
             }).await?;
         }
@@ -347,17 +483,10 @@ pub async fn delete_server_command(ctx: &Context, command: &ApplicationCommandIn
             }
 
             // Perform deletion
-            match database.delete_server(server_id).await {
-                Ok(_) => {
-                    // Add deletion job to queue
-                    let queue_service = QueueService::new(database.clone());
-                    let job = QueueJob::new(
-                        JobType::DeleteServer,
-                        discord_id,
-                        serde_json::json!({ "server_id": server_id })
-                    );
-                    queue_service.enqueue(job).await?;
-
+            let lifecycle = LifecycleService::new(database.clone(), PterodactylClient::new(config));
+            let plan = config.store_config.plan(&server.plan);
+            match lifecycle.delete(&server, plan, config.refund_unused_days).await {
+                Ok(Some(refunded)) => {
                     command.create_interaction_response(&ctx.http, |response| {
                         response
                             .kind(InteractionResponseType::ChannelMessageWithSource)
@@ -368,13 +497,25 @@ pub async fn delete_server_command(ctx: &Context, command: &ApplicationCommandIn
                                             .title("🗑️ Server Deletion Started")
                                             .description(format!("Server **{}** has been queued for deletion!\n\n⚠️ **This action cannot be undone!**\n\nThe server will be permanently deleted from Pterodactyl shortly.", server.name))
                                             .field("Server ID", server_id, true)
-                                            .field("Status", "Queued for deletion", true)
-                                            .color(0xff6b6b)
+                                            .field("Status", "Queued for deletion", true);
+                                        if refunded > 0 {
+                                            embed.field("Refund", format!("{} coins for unused days", refunded), true);
+                                        }
+                                        embed.color(0xff6b6b)
                                     })
                                     .ephemeral(true)
                             })
                     }).await?;
                 }
+                Ok(None) => {
+                    command.create_interaction_response(&ctx.http, |response| {
+                        response
+                            .kind(InteractionResponseType::ChannelMessageWithSource)
+                            .interaction_response_data(|message| {
+                                message.content("❌ Server not found.").ephemeral(true)
+                            })
+                    }).await?;
+                }
                 Err(e) => {
                     command.create_interaction_response(&ctx.http, |response| {
                         response
@@ -409,13 +550,529 @@ pub async fn delete_server_command(ctx: &Context, command: &ApplicationCommandIn
     Ok(())
 }
 
-async fn power_server(ctx: &Context, command: &ApplicationCommandInteraction, database: &Database, discord_id: u64) -> Result<()> {
-    // Implementation for server power actions (start/stop/restart)
+pub const POWER_BUTTON_PREFIX: &str = "power:";
+pub const REFRESH_BUTTON_PREFIX: &str = "server_refresh:";
+
+/// Fetches a provisioned server's live usage, syncing its status, when the Client API is set up.
+async fn live_usage(database: &Database, config: &Config, server: &mut Server) -> Option<ServerResourceUsage> {
+    let pterodactyl = PterodactylClient::new(config);
+    if !pterodactyl.has_client_api() || server.pterodactyl_id.is_none()
+        || !matches!(server.status, ServerStatus::Running | ServerStatus::Stopped) {
+        return None;
+    }
+
+    match PowerService::new(database.clone(), pterodactyl).usage(server).await {
+        Ok(usage) => Some(usage),
+        Err(e) => {
+            warn!("Failed to fetch usage of server {}: {}", server.id, e);
+            None
+        }
+    }
+}
+
+fn server_embed<'a>(embed: &'a mut CreateEmbed, server: &Server, config: &Config, usage: Option<&ServerResourceUsage>) -> &'a mut CreateEmbed {
+    let status_emoji = match server.status {
+        ServerStatus::Running => "🟢 Running",
+        ServerStatus::Stopped => "🔴 Stopped",
+        ServerStatus::Creating => "🟡 Creating",
+        ServerStatus::Suspended => "🟠 Suspended",
+        ServerStatus::Deleted => "⚫ Deleted",
+        ServerStatus::Failed => "❌ Failed",
+    };
+
+    embed
+        .title(format!("🖥️ Server: {}", server.name))
+        .field("Status", status_emoji, true)
+        .field("Plan", &server.plan, true)
+        .field("Software", server.software.as_deref().unwrap_or(&config.template_config.default_template), true)
+        .field("Server ID", server.id.to_string(), true)
+        .field("Resources", format!("🖥️ {}MB RAM\n⚡ {}% CPU\n💾 {}MB Disk", server.resources.ram, server.resources.cpu, server.resources.disk), true)
+        .field("Limits", format!("🗄️ {} Databases\n🌐 {} Allocations\n💾 {} Backups", server.resources.databases, server.resources.allocations, server.resources.backups), true)
+        .field("Expires", server.expires_at.format("%Y-%m-%d %H:%M UTC").to_string(), true);
+
+    if let Some(usage) = usage {
+        let stats = &usage.resources;
+        embed
+            .field("Power", power_state(&usage.current_state), true)
+            .field("Uptime", format_uptime(stats.uptime), true)
+            .field("Usage", format!(
+                "⚡ {:.1}% / {}% CPU\n🖥️ {} / {}MB RAM\n💾 {} / {}MB Disk",
+                stats.cpu_absolute, server.resources.cpu,
+                format_bytes(stats.memory_bytes), server.resources.ram,
+                format_bytes(stats.disk_bytes), server.resources.disk,
+            ), false)
+            .field("Network", format!("⬇️ {} in\n⬆️ {} out", format_bytes(stats.network_rx_bytes), format_bytes(stats.network_tx_bytes)), true)
+            .footer(|footer| footer.text(format!("Live usage as of {}", chrono::Utc::now().format("%H:%M:%S UTC"))));
+    }
+
+    if server.over_allocated {
+        let consequence = match (&server.status, server.over_allocated_at) {
+            (ServerStatus::Suspended, _) => "It is suspended until you buy more resources or delete another server.".to_string(),
+            (_, Some(flagged_at)) => format!(
+                "It will be suspended on {} unless you buy more resources or delete another server.",
+                (flagged_at + chrono::Duration::days(config.over_allocation_grace_days)).format("%Y-%m-%d %H:%M UTC")
+            ),
+            (_, None) => "Buy more resources or delete another server to keep it running.".to_string(),
+        };
+        embed.field("⚠️ Over Allocated", format!("Your resources no longer cover this server since a timed purchase ran out. {}", consequence), false);
+    }
+
+    embed.color(0x00ff00)
+}
+
+fn refresh_button<'a>(components: &'a mut CreateComponents, server: &Server) -> &'a mut CreateComponents {
+    components.create_action_row(|row| {
+        row.create_button(|button| {
+            button
+                .custom_id(format!("{}{}", REFRESH_BUTTON_PREFIX, server.id))
+                .label("🔄 Refresh")
+                .style(ButtonStyle::Secondary)
+        })
+    })
+}
+
+/// Handles the refresh button under `/servers view`.
+pub async fn handle_refresh_button(ctx: &Context, component: &MessageComponentInteraction, database: &Database, config: &Config) -> Result<()> {
+    let server_id = match component.data.custom_id.strip_prefix(REFRESH_BUTTON_PREFIX) {
+        Some(server_id) => server_id,
+        None => return Ok(()),
+    };
+
+    let mut server = match database.get_server(server_id).await? {
+        Some(server) if server.discord_id == component.user.id.0 => server,
+        _ => {
+            component.create_interaction_response(&ctx.http, |response| {
+                response
+                    .kind(InteractionResponseType::ChannelMessageWithSource)
+                    .interaction_response_data(|message| {
+                        message.content("❌ Server not found.").ephemeral(true)
+                    })
+            }).await?;
+            return Ok(());
+        }
+    };
+
+    component.create_interaction_response(&ctx.http, |response| {
+        response.kind(InteractionResponseType::DeferredUpdateMessage)
+    }).await?;
+
+    let usage = live_usage(database, config, &mut server).await;
+
+    component.edit_original_interaction_response(&ctx.http, |response| {
+        response
+            .embed(|embed| server_embed(embed, &server, config, usage.as_ref()))
+            .components(|components| refresh_button(components, &server))
+    }).await?;
+
+    Ok(())
+}
+
+fn power_state(current_state: &str) -> &'static str {
+    match current_state {
+        "running" => "🟢 Running",
+        "starting" => "🟡 Starting",
+        "stopping" => "🟠 Stopping",
+        "offline" => "🔴 Offline",
+        _ => "❔ Unknown",
+    }
+}
+
+fn format_bytes(bytes: i64) -> String {
+    const MIB: f64 = 1024.0 * 1024.0;
+    let mib = bytes as f64 / MIB;
+    if mib >= 1024.0 {
+        format!("{:.2}GB", mib / 1024.0)
+    } else {
+        format!("{:.0}MB", mib)
+    }
+}
+
+fn format_uptime(uptime_ms: i64) -> String {
+    if uptime_ms <= 0 {
+        return "-".to_string();
+    }
+
+    let minutes = uptime_ms / 60_000;
+    let (days, hours, minutes) = (minutes / 1440, minutes / 60 % 24, minutes % 60);
+    match (days, hours) {
+        (0, 0) => format!("{}m", minutes),
+        (0, _) => format!("{}h {}m", hours, minutes),
+        _ => format!("{}d {}h {}m", days, hours, minutes),
+    }
+}
+
+async fn power_server(ctx: &Context, command: &ApplicationCommandInteraction, database: &Database, config: &Config, discord_id: u64) -> Result<()> {
+    let pterodactyl = PterodactylClient::new(config);
+    if !pterodactyl.has_client_api() {
+        command.create_interaction_response(&ctx.http, |response| {
+            response
+                .kind(InteractionResponseType::ChannelMessageWithSource)
+                .interaction_response_data(|message| {
+                    message.content("❌ Power actions are not available on this bot.").ephemeral(true)
+                })
+        }).await?;
+        return Ok(());
+    }
+
+    let server_id = command.data.options.iter()
+        .find(|opt| opt.name == "server_id")
+        .and_then(|opt| opt.value.as_ref())
+        .and_then(|val| val.as_str());
+
+    let server = match server_id {
+        Some(server_id) => database.get_server(server_id).await?,
+        None => None,
+    };
+
+    let server = match server {
+        Some(server) if server.discord_id == discord_id => server,
+        _ => {
+            command.create_interaction_response(&ctx.http, |response| {
+                response
+                    .kind(InteractionResponseType::ChannelMessageWithSource)
+                    .interaction_response_data(|message| {
+                        message.content("❌ Server not found. Use `/servers list` to see your servers.").ephemeral(true)
+                    })
+            }).await?;
+            return Ok(());
+        }
+    };
+
+    let mut server = server;
+    let power = PowerService::new(database.clone(), pterodactyl);
+    let (state, note) = match power.usage(&mut server).await {
+        Ok(usage) => (Some(usage), None),
+        Err(e) => (None, Some(format!("❌ {}", e))),
+    };
+
     command.create_interaction_response(&ctx.http, |response| {
         response
             .kind(InteractionResponseType::ChannelMessageWithSource)
             .interaction_response_data(|message| {
-                message.content("Server power actions not yet implemented").ephemeral(true)
+                message
+                    .embed(|embed| power_embed(embed, &server, state.as_ref(), note))
+                    .components(|components| power_buttons(components, &server))
+                    .ephemeral(true)
+            })
+    }).await?;
+
+    Ok(())
+}
+
+/// Handles the start/stop/restart/kill buttons under `/servers power`.
+pub async fn handle_power_button(ctx: &Context, component: &MessageComponentInteraction, database: &Database, config: &Config) -> Result<()> {
+    let (signal, server_id) = match component.data.custom_id
+        .strip_prefix(POWER_BUTTON_PREFIX)
+        .and_then(|rest| rest.split_once(':'))
+        .and_then(|(signal, server_id)| PowerSignal::parse(signal).map(|signal| (signal, server_id)))
+    {
+        Some(parsed) => parsed,
+        None => return Ok(()),
+    };
+
+    let server = match database.get_server(server_id).await? {
+        Some(server) if server.discord_id == component.user.id.0 => server,
+        _ => {
+            component.create_interaction_response(&ctx.http, |response| {
+                response
+                    .kind(InteractionResponseType::ChannelMessageWithSource)
+                    .interaction_response_data(|message| {
+                        message.content("❌ Server not found.").ephemeral(true)
+                    })
+            }).await?;
+            return Ok(());
+        }
+    };
+
+    // Waiting for the server to settle takes longer than Discord allows for a response
+    component.create_interaction_response(&ctx.http, |response| {
+        response.kind(InteractionResponseType::DeferredUpdateMessage)
+    }).await?;
+
+    let power = PowerService::new(database.clone(), PterodactylClient::new(config));
+    let (state, note) = match power.send(&server, signal).await {
+        Ok(usage) if usage.current_state == signal.target_state() => {
+            (Some(usage), Some(format!("✅ `{}` finished.", signal.as_str())))
+        }
+        Ok(usage) => {
+            (Some(usage), Some(format!("⏳ `{}` was sent but the server hasn't settled yet.", signal.as_str())))
+        }
+        Err(e) => (None, Some(format!("❌ Failed to {} the server: {}", signal.as_str(), e))),
+    };
+
+    // Reflect any status change the action caused
+    let server = database.get_server(server_id).await?.unwrap_or(server);
+
+    component.edit_original_interaction_response(&ctx.http, |response| {
+        response
+            .embed(|embed| power_embed(embed, &server, state.as_ref(), note))
+            .components(|components| power_buttons(components, &server))
+    }).await?;
+
+    Ok(())
+}
+
+fn power_embed<'a>(embed: &'a mut CreateEmbed, server: &Server, usage: Option<&ServerResourceUsage>, note: Option<String>) -> &'a mut CreateEmbed {
+    let state = usage.map(|usage| power_state(&usage.current_state)).unwrap_or("❔ Unknown");
+
+    embed
+        .title(format!("⚡ Power: {}", server.name))
+        .field("State", state, true)
+        .field("Server ID", server.id.to_string(), true)
+        .color(0x3498db);
+
+    if let Some(note) = note {
+        embed.description(note);
+    }
+
+    embed
+}
+
+fn power_buttons<'a>(components: &'a mut CreateComponents, server: &Server) -> &'a mut CreateComponents {
+    components.create_action_row(|row| {
+        for signal in PowerSignal::ALL {
+            let (label, style) = match signal {
+                PowerSignal::Start => ("▶️ Start", ButtonStyle::Success),
+                PowerSignal::Stop => ("⏹️ Stop", ButtonStyle::Secondary),
+                PowerSignal::Restart => ("🔄 Restart", ButtonStyle::Primary),
+                PowerSignal::Kill => ("💀 Kill", ButtonStyle::Danger),
+            };
+            row.create_button(|button| {
+                button
+                    .custom_id(format!("{}{}:{}", POWER_BUTTON_PREFIX, signal.as_str(), server.id))
+                    .label(label)
+                    .style(style)
+            });
+        }
+        row
+    })
+}
+
+/// Longest console output posted inline; anything more is attached as a file.
+const MAX_INLINE_LOG_CHARS: usize = 1900;
+
+/// Looks up a server for the console, checking ownership and what its plan allows.
+async fn console_target(command: &ApplicationCommandInteraction, database: &Database, config: &Config, discord_id: u64, logs: bool) -> Result<std::result::Result<Server, String>> {
+    if !PterodactylClient::new(config).has_client_api() {
+        return Ok(Err("❌ Console access is not available on this bot.".to_string()));
+    }
+
+    let server_id = command.data.options.iter()
+        .find(|opt| opt.name == "server_id")
+        .and_then(|opt| opt.value.as_ref())
+        .and_then(|val| val.as_str());
+
+    let server = match server_id {
+        Some(server_id) => database.get_server(server_id).await?,
+        None => None,
+    };
+
+    let server = match server {
+        Some(server) if server.discord_id == discord_id => server,
+        _ => return Ok(Err("❌ Server not found. Use `/servers list` to see your servers.".to_string())),
+    };
+
+    let permissions = config.store_config.plan(&server.plan)
+        .map(|plan| plan.console.clone())
+        .unwrap_or_default();
+    let allowed = if logs { permissions.logs } else { permissions.commands };
+    if !allowed {
+        let what = if logs { "console logs" } else { "console commands" };
+        return Ok(Err(format!("❌ The **{}** plan doesn't include {}.", server.plan, what)));
+    }
+
+    Ok(Ok(server))
+}
+
+async fn console_command(ctx: &Context, command: &ApplicationCommandInteraction, database: &Database, config: &Config, discord_id: u64) -> Result<()> {
+    let console_input = command.data.options.iter()
+        .find(|opt| opt.name == "command")
+        .and_then(|opt| opt.value.as_ref())
+        .and_then(|val| val.as_str())
+        .map(|val| val.trim().to_string())
+        .filter(|val| !val.is_empty());
+
+    let console_input = match console_input {
+        Some(console_input) => console_input,
+        None => {
+            command.create_interaction_response(&ctx.http, |response| {
+                response
+                    .kind(InteractionResponseType::ChannelMessageWithSource)
+                    .interaction_response_data(|message| {
+                        message.content("❌ Please provide a command to send.").ephemeral(true)
+                    })
+            }).await?;
+            return Ok(());
+        }
+    };
+
+    let server = match console_target(command, database, config, discord_id, false).await? {
+        Ok(server) => server,
+        Err(message) => {
+            command.create_interaction_response(&ctx.http, |response| {
+                response
+                    .kind(InteractionResponseType::ChannelMessageWithSource)
+                    .interaction_response_data(|data| data.content(message).ephemeral(true))
+            }).await?;
+            return Ok(());
+        }
+    };
+
+    let permissions = config.store_config.plan(&server.plan)
+        .map(|plan| plan.console.clone())
+        .unwrap_or_default();
+    if !permissions.allows_command(&console_input) {
+        command.create_interaction_response(&ctx.http, |response| {
+            response
+                .kind(InteractionResponseType::ChannelMessageWithSource)
+                .interaction_response_data(|message| {
+                    message
+                        .content(format!("❌ That command isn't allowed on the **{}** plan. Allowed: {}", server.plan, permissions.allowed_commands.join(", ")))
+                        .ephemeral(true)
+                })
+        }).await?;
+        return Ok(());
+    }
+
+    let console = ConsoleService::new(database.clone(), PterodactylClient::new(config));
+    let content = match console.send_command(&server, &console_input).await {
+        Ok(()) => format!("✅ Sent to **{}**:\n```\n{}\n```", server.name, console_input.replace("```", "`\u{200b}``")),
+        Err(e) => format!("❌ Failed to send the command: {}", e),
+    };
+
+    command.create_interaction_response(&ctx.http, |response| {
+        response
+            .kind(InteractionResponseType::ChannelMessageWithSource)
+            .interaction_response_data(|message| message.content(content).ephemeral(true))
+    }).await?;
+
+    Ok(())
+}
+
+async fn tail_logs(ctx: &Context, command: &ApplicationCommandInteraction, database: &Database, config: &Config, discord_id: u64) -> Result<()> {
+    let server = match console_target(command, database, config, discord_id, true).await? {
+        Ok(server) => server,
+        Err(message) => {
+            command.create_interaction_response(&ctx.http, |response| {
+                response
+                    .kind(InteractionResponseType::ChannelMessageWithSource)
+                    .interaction_response_data(|data| data.content(message).ephemeral(true))
+            }).await?;
+            return Ok(());
+        }
+    };
+
+    // Listening takes longer than Discord allows for a response
+    command.create_interaction_response(&ctx.http, |response| {
+        response
+            .kind(InteractionResponseType::DeferredChannelMessageWithSource)
+            .interaction_response_data(|message| message.ephemeral(true))
+    }).await?;
+
+    let console = ConsoleService::new(database.clone(), PterodactylClient::new(config));
+    let window = std::time::Duration::from_secs(config.console_tail_secs);
+    let lines = match console.tail(&server, window).await {
+        Ok(lines) => lines,
+        Err(e) => {
+            command.create_followup_message(&ctx.http, |message| {
+                message.content(format!("❌ Failed to read the console: {}", e)).ephemeral(true)
+            }).await?;
+            return Ok(());
+        }
+    };
+
+    let output = lines.join("\n");
+    let header = format!("📜 Console of **{}** ({} lines over {}s)", server.name, lines.len(), config.console_tail_secs);
+
+    command.create_followup_message(&ctx.http, |message| {
+        if output.is_empty() {
+            message.content(format!("{}\n\nNo output.", header));
+        } else if output.len() <= MAX_INLINE_LOG_CHARS {
+            message.content(format!("{}\n```\n{}\n```", header, output.replace("```", "`\u{200b}``")));
+        } else {
+            message
+                .content(header)
+                .add_file(AttachmentType::Bytes {
+                    data: std::borrow::Cow::Owned(output.into_bytes()),
+                    filename: format!("{}-console.log", server.id),
+                });
+        }
+        message.ephemeral(true)
+    }).await?;
+
+    Ok(())
+}
+
+async fn set_auto_renew(ctx: &Context, command: &ApplicationCommandInteraction, database: &Database, config: &Config, discord_id: u64) -> Result<()> {
+    let server_id = command.data.options.iter()
+        .find(|opt| opt.name == "server_id")
+        .and_then(|opt| opt.value.as_ref())
+        .and_then(|val| val.as_str());
+
+    let enabled = command.data.options.iter()
+        .find(|opt| opt.name == "enabled")
+        .and_then(|opt| opt.value.as_ref())
+        .and_then(|val| val.as_bool());
+
+    let server = match server_id {
+        Some(server_id) => database.get_server(server_id).await?,
+        None => None,
+    };
+
+    let server = match server {
+        Some(server) if server.discord_id == discord_id => server,
+        _ => {
+            command.create_interaction_response(&ctx.http, |response| {
+                response
+                    .kind(InteractionResponseType::ChannelMessageWithSource)
+                    .interaction_response_data(|message| {
+                        message.content("❌ Server not found.").ephemeral(true)
+                    })
+            }).await?;
+            return Ok(());
+        }
+    };
+
+    // Without `enabled`, just report the current setting
+    let enabled = enabled.unwrap_or(server.auto_renew);
+    let free = config.store_config.plan(&server.plan).map_or(false, |plan| plan.renewal_cost(1) == 0);
+    if enabled && free {
+        command.create_interaction_response(&ctx.http, |response| {
+            response
+                .kind(InteractionResponseType::ChannelMessageWithSource)
+                .interaction_response_data(|message| {
+                    message.content(format!("❌ Free servers can't auto-renew. Renew **{}** with `/renew {}` before it expires.", server.name, server.id)).ephemeral(true)
+                })
+        }).await?;
+        return Ok(());
+    }
+    if enabled != server.auto_renew {
+        database.set_auto_renew(&server.id.to_string(), enabled).await?;
+    }
+
+    let price = match config.store_config.plan(&server.plan) {
+        Some(plan) => format!("{} coins every {} days", plan.renewal_price(), plan.duration_days),
+        None => "Unavailable for this plan".to_string(),
+    };
+
+    command.create_interaction_response(&ctx.http, |response| {
+        response
+            .kind(InteractionResponseType::ChannelMessageWithSource)
+            .interaction_response_data(|message| {
+                message
+                    .embed(|embed| {
+                        embed
+                            .title("🔁 Auto-Renew")
+                            .description(if enabled {
+                                format!("Auto-renew is **on** for **{}**. It will be renewed from your balance before it expires.", server.name)
+                            } else {
+                                format!("Auto-renew is **off** for **{}**.", server.name)
+                            })
+                            .field("Renewal Price", price, true)
+                            .field("Expires", server.expires_at.format("%Y-%m-%d %H:%M UTC").to_string(), true)
+                            .color(if enabled { 0x00ff00 } else { 0xffaa00 })
+                    })
+                    .ephemeral(true)
             })
     }).await?;
 
@@ -445,7 +1102,7 @@ pub async fn renew_server(ctx: &Context, command: &ApplicationCommandInteraction
         .find(|opt| opt.name == "duration")
         .and_then(|opt| opt.value.as_ref())
         .and_then(|val| val.as_i64())
-        .unwrap_or(30) as u32; // Default 30 days
+        .map(|days| days.max(0) as u32); // Defaults to one plan period
 
     if server_id.is_none() {
         command.create_interaction_response(&ctx.http, |response| {
@@ -474,40 +1131,58 @@ pub async fn renew_server(ctx: &Context, command: &ApplicationCommandInteraction
                 return Ok(());
             }
 
-            // Calculate renewal cost (1 coin per day)
-            let renewal_cost = duration as u64;
-
-            // Get user to check coins
-            let user = database.get_user(discord_id).await?;
-            if user.is_none() {
+            if !server.is_renewable() {
                 command.create_interaction_response(&ctx.http, |response| {
                     response
                         .kind(InteractionResponseType::ChannelMessageWithSource)
                         .interaction_response_data(|message| {
-                            message.content("❌ You need to login first! Use `/login`").ephemeral(true)
+                            message.content("❌ This server can no longer be renewed.").ephemeral(true)
                         })
                 }).await?;
                 return Ok(());
             }
 
-            let user = user.unwrap();
+            let plan = match config.store_config.plan(&server.plan) {
+                Some(plan) => plan,
+                None => {
+                    command.create_interaction_response(&ctx.http, |response| {
+                        response
+                            .kind(InteractionResponseType::ChannelMessageWithSource)
+                            .interaction_response_data(|message| {
+                                message.content(format!("❌ The **{}** plan is no longer available for renewal.", server.plan)).ephemeral(true)
+                            })
+                    }).await?;
+                    return Ok(());
+                }
+            };
 
-            if user.coins < renewal_cost {
+            // Renewals are sold in whole plan periods
+            let duration = duration.unwrap_or(plan.duration_days);
+            if plan.duration_days == 0 || duration == 0 || duration % plan.duration_days != 0 {
                 command.create_interaction_response(&ctx.http, |response| {
                     response
                         .kind(InteractionResponseType::ChannelMessageWithSource)
                         .interaction_response_data(|message| {
-                            message.content(format!("❌ Insufficient coins! Renewal costs {} coins ({} days × 1 coin/day). You have {} coins.", renewal_cost, duration, user.coins)).ephemeral(true)
+                            message.content(format!("❌ The {} plan renews in multiples of {} days.", plan.name, plan.duration_days)).ephemeral(true)
                         })
                 }).await?;
                 return Ok(());
             }
+            let periods = duration / plan.duration_days;
+
+            let lifecycle = LifecycleService::new(database.clone(), PterodactylClient::new(config));
+            match lifecycle.renew(&server, plan, periods).await {
+                Ok(renewal) => {
+                    let cost = if renewal.discount_percent > 0 {
+                        format!("{} coins ({}% off)", renewal.cost, renewal.discount_percent)
+                    } else {
+                        format!("{} coins", renewal.cost)
+                    };
+                    let mut description = format!("Server **{}** has been renewed for {} days!", server.name, renewal.days);
+                    if renewal.unsuspended {
+                        description.push_str("\n\nThe server has been unsuspended.");
+                    }
 
-            // Perform renewal
-            match database.renew_server(server_id, duration, renewal_cost).await {
-                Ok(_) => {
-                    // Get updated server to get new expiry date
-                    let updated_server = database.get_server(server_id).await?.unwrap();
                     command.create_interaction_response(&ctx.http, |response| {
                         response
                             .kind(InteractionResponseType::ChannelMessageWithSource)
@@ -516,16 +1191,25 @@ pub async fn renew_server(ctx: &Context, command: &ApplicationCommandInteraction
                                     .embed(|embed| {
                                         embed
                                             .title("🔄 Server Renewed Successfully!")
-                                            .description(format!("Server **{}** has been renewed for {} days!", server.name, duration))
-                                            .field("Cost", format!("{} coins", renewal_cost), true)
-                                            .field("New Expiry Date", updated_server.expires_at.format("%Y-%m-%d %H:%M UTC").to_string(), true)
-                                            .field("Remaining Coins", format!("{} coins", user.coins - renewal_cost), true)
+                                            .description(description)
+                                            .field("Cost", cost, true)
+                                            .field("New Expiry Date", renewal.expires_at.format("%Y-%m-%d %H:%M UTC").to_string(), true)
+                                            .field("Remaining Coins", format!("{} coins", renewal.balance), true)
                                             .color(0x00ff00)
                                     })
                                     .ephemeral(true)
                             })
                     }).await?;
                 }
+                Err(BotError::InsufficientCoins { needed, available }) => {
+                    command.create_interaction_response(&ctx.http, |response| {
+                        response
+                            .kind(InteractionResponseType::ChannelMessageWithSource)
+                            .interaction_response_data(|message| {
+                                message.content(format!("❌ Insufficient coins! Renewing for {} days costs {} coins. You have {} coins.", duration, needed, available)).ephemeral(true)
+                            })
+                    }).await?;
+                }
                 Err(e) => {
                     command.create_interaction_response(&ctx.http, |response| {
                         response
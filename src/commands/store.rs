@@ -1,8 +1,15 @@
 use anyhow::Result;
 use serenity::prelude::*;
-use serenity::all::{ApplicationCommandInteraction, InteractionResponseType};
-use crate::services::Database;
-use crate::config::Config;
+use serenity::all::{ActionRowComponent, ApplicationCommandInteraction, InputTextStyle, InteractionResponseType, ModalSubmitInteraction};
+use crate::services::{Database, QueueService, QueueJob, JobType};
+use crate::config::{Config, ServerTemplate, StoreItem, StoreItemKind};
+use crate::errors::BotError;
+use crate::commands::servers::{format_position, format_wait};
+use crate::models::{CoinChange, ResourceGrant, Server, TransactionReason};
+
+/// Prefix of the custom ID of the modal asking for a store-bought server's name,
+/// followed by `<item_id>:<template_id>`.
+pub const SERVER_MODAL_PREFIX: &str = "store_server:";
 
 pub async fn handle_store(ctx: &Context, command: &ApplicationCommandInteraction, database: &Database, config: &Config) -> Result<()> {
     let action = command.data.options.get(0)
@@ -172,8 +179,7 @@ async fn buy_store_item(ctx: &Context, command: &ApplicationCommandInteraction,
     let store_item = store_item.unwrap();
 
     // Get user
-    let user = database.get_user(discord_id).await?;
-    if user.is_none() {
+    if database.get_user(discord_id).await?.is_none() {
         command.create_interaction_response(&ctx.http, |response| {
             response
                 .kind(InteractionResponseType::ChannelMessageWithSource)
@@ -184,57 +190,86 @@ async fn buy_store_item(ctx: &Context, command: &ApplicationCommandInteraction,
         return Ok(());
     }
 
-    let mut user = user.unwrap();
+    // Servers are named before anything is charged; the modal submit does the rest
+    if store_item.kind == StoreItemKind::Server {
+        let software = command.data.options.iter()
+            .find(|opt| opt.name == "software")
+            .and_then(|opt| opt.value.as_ref())
+            .and_then(|val| val.as_str());
+        let template = match server_template(config, store_item, software) {
+            Ok(template) => template,
+            Err(error) => {
+                command.create_interaction_response(&ctx.http, |response| {
+                    response
+                        .kind(InteractionResponseType::ChannelMessageWithSource)
+                        .interaction_response_data(|message| {
+                            message.content(error).ephemeral(true)
+                        })
+                }).await?;
+                return Ok(());
+            }
+        };
 
-    // Check if user can afford it
-    if let Err(e) = user.deduct_coins(store_item.price as i64) {
+        let title: String = format!("Buy {}", store_item.name).chars().take(45).collect();
         command.create_interaction_response(&ctx.http, |response| {
             response
-                .kind(InteractionResponseType::ChannelMessageWithSource)
-                .interaction_response_data(|message| {
-                    message.content(format!("❌ Purchase failed: {}", e)).ephemeral(true)
+                .kind(InteractionResponseType::Modal)
+                .interaction_response_data(|modal| {
+                    modal
+                        .custom_id(format!("{}{}:{}", SERVER_MODAL_PREFIX, store_item.id, template.id))
+                        .title(title)
+                        .components(|components| {
+                            components.create_action_row(|row| {
+                                row.create_input_text(|input| {
+                                    input
+                                        .custom_id("name")
+                                        .label("Server name")
+                                        .style(InputTextStyle::Short)
+                                        .min_length(1)
+                                        .max_length(100)
+                                        .required(true)
+                                })
+                            })
+                        })
                 })
         }).await?;
         return Ok(());
     }
 
-    // Apply the resource update if available
-    if let Some(resources) = &store_item.resources {
-        user.resources.ram += resources.ram as i64;
-        user.resources.cpu += resources.cpu as i64;
-        user.resources.disk += resources.disk as i64;
-        user.resources.databases += resources.databases as i64;
-        user.resources.allocations += resources.allocations as i64;
-        user.resources.backups += resources.backups as i64;
-    }
-
-    // Update user in database
-    database.update_user(&user).await?;
-
-    let duration_text = if let Some(days) = store_item.duration_days {
-        format!(" for {} days", days)
-    } else {
-        " permanently".to_string()
+    // Charge the price and add the resources in one update; timed items also
+    // record a grant so the resources are taken back when it runs out
+    let resources = store_item.resources.as_ref()
+        .map(|resources| resources.to_resources())
+        .unwrap_or_default();
+    let change = CoinChange::new(TransactionReason::Store).reference(store_item.id.clone());
+    let grant = store_item.duration_days
+        .map(|days| ResourceGrant::new(discord_id, store_item.id.clone(), resources.clone(), days));
+    let purchase = match &grant {
+        Some(grant) => database.purchase_grant(discord_id, store_item.price as i64, grant, change).await,
+        None => database.purchase_resources(discord_id, store_item.price as i64, &resources, change).await,
     };
-
-    let resource_text = if let Some(resources) = &store_item.resources {
-        let mut parts = Vec::new();
-        if resources.ram > 0 { parts.push(format!("{}MB RAM", resources.ram)); }
-        if resources.cpu > 0 { parts.push(format!("{}% CPU", resources.cpu)); }
-        if resources.disk > 0 { parts.push(format!("{}MB Disk", resources.disk)); }
-        if resources.databases > 0 { parts.push(format!("{} Database(s)", resources.databases)); }
-        if resources.allocations > 0 { parts.push(format!("{} Allocation(s)", resources.allocations)); }
-        if resources.backups > 0 { parts.push(format!("{} Backup(s)", resources.backups)); }
-        
-        if parts.is_empty() {
-            "No resources".to_string()
-        } else {
-            parts.join(", ")
+    let balance = match purchase {
+        Ok(balance) => balance,
+        Err(e @ BotError::InsufficientCoins { .. }) => {
+            command.create_interaction_response(&ctx.http, |response| {
+                response
+                    .kind(InteractionResponseType::ChannelMessageWithSource)
+                    .interaction_response_data(|message| {
+                        message.content(format!("❌ Purchase failed: {}", e)).ephemeral(true)
+                    })
+            }).await?;
+            return Ok(());
         }
-    } else {
-        "No resources".to_string()
+        Err(e) => return Err(e.into()),
+    };
+
+    let duration_text = match (&grant, store_item.duration_days) {
+        (Some(grant), Some(days)) => format!(" for {} days, until {}", days, grant.expires_at.format("%Y-%m-%d %H:%M UTC")),
+        _ => " permanently".to_string(),
     };
 
+    let resource_text = resources.summary();
+
     command.create_interaction_response(&ctx.http, |response| {
         response
             .kind(InteractionResponseType::ChannelMessageWithSource)
@@ -246,7 +281,7 @@ async fn buy_store_item(ctx: &Context, command: &ApplicationCommandInteraction,
                             .description(format!("Successfully purchased **{}**{}", store_item.name, duration_text))
                             .field("Cost", format!("{} coins", store_item.price), true)
                             .field("Resources Added", resource_text, true)
-                            .field("Remaining Coins", format!("{} coins", user.coins), true)
+                            .field("Remaining Coins", format!("{} coins", balance), true)
                             .color(0x00ff00)
                     })
                     .ephemeral(true)
@@ -255,3 +290,148 @@ async fn buy_store_item(ctx: &Context, command: &ApplicationCommandInteraction,
 
     Ok(())
 }
+
+/// The software a server item is bought with: `software` if its plan allows it,
+/// otherwise the plan's default. The error is the message to show the buyer.
+fn server_template<'a>(config: &'a Config, store_item: &StoreItem, software: Option<&str>) -> Result<&'a ServerTemplate, String> {
+    let plan = store_item.plan.as_deref()
+        .and_then(|plan_id| config.store_config.plan(plan_id))
+        .ok_or_else(|| "❌ This item is no longer available. Use `/store list` to see available items.".to_string())?;
+
+    let software = software.unwrap_or_else(|| plan.default_template(&config.template_config));
+    match config.template_config.get(software) {
+        Some(template) if plan.allows_template(&template.id) => Ok(template),
+        Some(_) => Err(format!("❌ The {} plan doesn't support '{}'", plan.name, software)),
+        None => Err(format!("❌ Invalid software '{}'", software)),
+    }
+}
+
+/// Finishes buying a server item once the user has named it: charges the
+/// item's price, saves the server and queues its creation on the software
+/// chosen with `/store buy`. The queue worker refunds the price if the server
+/// can't be created.
+pub async fn handle_store_modal(ctx: &Context, modal: &ModalSubmitInteraction, database: &Database, config: &Config) -> Result<()> {
+    let discord_id = modal.user.id.0;
+
+    let (item_id, template_id) = modal.data.custom_id.strip_prefix(SERVER_MODAL_PREFIX)
+        .and_then(|ids| ids.rsplit_once(':'))
+        .unwrap_or_default();
+    let store_item = config.store_config.items.iter()
+        .find(|item| item.id == item_id && item.enabled)
+        .filter(|item| item.kind == StoreItemKind::Server);
+    let plan = store_item
+        .and_then(|item| item.plan.as_deref())
+        .and_then(|plan_id| config.store_config.plan(plan_id));
+    // The plan's software may have changed since the modal was shown
+    let template = plan
+        .and_then(|plan| config.template_config.get(template_id).filter(|template| plan.allows_template(&template.id)));
+
+    let name = modal.data.components.iter()
+        .flat_map(|row| row.components.iter())
+        .find_map(|component| match component {
+            ActionRowComponent::InputText(input) if input.custom_id == "name" => Some(input.value.trim().to_string()),
+            _ => None,
+        })
+        .unwrap_or_default();
+
+    let error = match (store_item, plan, template) {
+        (Some(_), Some(_), Some(_)) if name.is_empty() => Some("❌ Please give your server a name.".to_string()),
+        (Some(_), Some(_), Some(_)) => None,
+        _ => Some("❌ This item is no longer available. Use `/store list` to see available items.".to_string()),
+    };
+    if let Some(error) = error {
+        modal.create_interaction_response(&ctx.http, |response| {
+            response
+                .kind(InteractionResponseType::ChannelMessageWithSource)
+                .interaction_response_data(|message| {
+                    message.content(error).ephemeral(true)
+                })
+        }).await?;
+        return Ok(());
+    }
+    let (store_item, plan, template) = (store_item.unwrap(), plan.unwrap(), template.unwrap());
+
+    let resources = store_item.resources.as_ref()
+        .map(|resources| resources.to_resources())
+        .unwrap_or_else(|| plan.resources.to_resources());
+    let mut server = Server::new(
+        discord_id,
+        name.clone(),
+        plan.id.clone(),
+        template.id.clone(),
+        resources,
+        store_item.duration_days.unwrap_or(plan.duration_days),
+    );
+    server.item_id = Some(store_item.id.clone());
+    server.prepaid_coins = store_item.price as i64;
+
+    let change = CoinChange::new(TransactionReason::Store).reference(store_item.id.clone());
+    let balance = match database.purchase_server(&server, store_item.price as i64, plan.max_per_user, change).await {
+        Ok(balance) => balance,
+        Err(e @ (BotError::InsufficientCoins { .. } | BotError::UserNotFound | BotError::PlanLimitReached(_))) => {
+            modal.create_interaction_response(&ctx.http, |response| {
+                response
+                    .kind(InteractionResponseType::ChannelMessageWithSource)
+                    .interaction_response_data(|message| {
+                        message.content(format!("❌ Purchase failed: {}", e)).ephemeral(true)
+                    })
+            }).await?;
+            return Ok(());
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    // Respond before enqueuing: the worker edits this message as soon as it
+    // picks the job up, and can't edit a response that doesn't exist yet
+    let queue_service = QueueService::new(database.clone());
+    let position = queue_service.get_queue_length().await? + 1;
+    let wait = queue_service.estimate_wait(position, config.queue_workers).await?;
+
+    modal.create_interaction_response(&ctx.http, |response| {
+        response
+            .kind(InteractionResponseType::ChannelMessageWithSource)
+            .interaction_response_data(|message| {
+                message
+                    .embed(|embed| {
+                        embed
+                            .title("🛒 Server Purchased!")
+                            .description(format!(
+                                "**{}** has been queued for creation!\n\nServer ID: `{}`\nPlan: {}\nSoftware: {}\n\nIf the server can't be created, your coins are refunded.",
+                                name, server.id, plan.name, template.name
+                            ))
+                            .field("Cost", format!("{} coins", store_item.price), true)
+                            .field("Remaining Coins", format!("{} coins", balance), true)
+                            .field("Expires", server.expires_at.format("%Y-%m-%d %H:%M UTC").to_string(), true)
+                            .field("Queue Position", format_position(Some(position), position), true)
+                            .field("Estimated Wait", format_wait(Some(wait)), true)
+                            .color(0x00ff00)
+                    })
+                    .ephemeral(true)
+            })
+    }).await?;
+
+    let job = QueueJob::new(
+        JobType::CreateServer,
+        discord_id,
+        serde_json::json!({
+            "server_id": server.id.to_string(),
+            "interaction_token": modal.token,
+        })
+    );
+    if let Err(e) = queue_service.enqueue(job).await {
+        // Nothing will create the server, so undo the purchase
+        database.cancel_server_creation(&server).await?;
+        // The queue error is the one worth reporting, not a failed edit
+        modal.edit_original_interaction_response(&ctx.http, |response| {
+            response.embed(|embed| {
+                embed
+                    .title("❌ Purchase Failed")
+                    .description(format!("**{}** couldn't be queued for creation. Your coins have been refunded, please try again later.", name))
+                    .color(0xff0000)
+            })
+        }).await.ok();
+        return Err(e.into());
+    }
+
+    Ok(())
+}
@@ -1,8 +1,10 @@
 use anyhow::{Result, Context};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::env;
 use std::fs;
 use std::path::Path;
+use crate::models::Resources;
 
 #[derive(Debug, Clone)]
 pub struct Config {
@@ -10,23 +12,76 @@ pub struct Config {
     pub discord_app_id: u64,
     pub pterodactyl_url: String,
     pub pterodactyl_api_key: String,
+    pub pterodactyl_client_api_key: String, // empty disables power actions
     pub mongodb_uri: String,
     pub redis_uri: String,
     pub stripe_secret_key: String,
     pub stripe_public_key: String,
+    pub stripe_api_url: String,
+    pub stripe_webhook_secret: String,
+    pub paypal_api_url: String,
+    pub paypal_client_id: String,
+    pub paypal_client_secret: String,
+    pub paypal_webhook_id: String,
+    pub manual_payment_secret: String,
+    pub payment_providers: Vec<String>,
+    pub payment_currency: String,
+    pub payment_success_url: String,
+    pub payment_cancel_url: String,
+    pub webhook_bind_addr: String,
+    pub order_reconcile_after_minutes: i64,
+    pub order_reconcile_interval_secs: u64,
     pub afk_page_url: String,
     pub linkvertise_verify_url: String,
     pub admin_discord_ids: Vec<u64>,
     pub enable_transfer: bool,
     pub enable_renew: bool,
     pub enable_delete: bool,
+    pub refund_unused_days: bool,
+    pub queue_workers: usize,
+    pub queue_max_attempts: u32,
+    pub queue_retry_base_secs: u64,
+    pub expiry_warning_days: i64,
+    pub expiry_grace_days: i64,
+    pub over_allocation_grace_days: i64,
+    pub expiry_sweep_interval_secs: u64,
+    pub console_tail_secs: u64,
+    pub backup_schedule_interval_secs: u64,
+    pub pterodactyl_user_email_domain: String,
+    pub pterodactyl_send_reset_link: bool,
+    pub node_selection: NodeSelectionPolicy,
+    pub node_locations: Vec<i64>,
+    pub node_pins: HashMap<String, i64>,
     pub store_config: StoreConfig,
+    pub template_config: TemplateConfig,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeSelectionPolicy {
+    LeastLoaded,
+    RoundRobin,
+}
+
+impl std::str::FromStr for NodeSelectionPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "least-loaded" => Ok(NodeSelectionPolicy::LeastLoaded),
+            "round-robin" => Ok(NodeSelectionPolicy::RoundRobin),
+            other => anyhow::bail!("Unknown node selection policy '{}'", other),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct StoreConfig {
     pub items: Vec<StoreItem>,
     pub categories: Vec<String>,
+    #[serde(default)]
+    pub plans: Vec<Plan>,
+    #[serde(default)]
+    pub coin_packages: Vec<CoinPackage>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -34,13 +89,174 @@ pub struct StoreItem {
     pub id: String,
     pub name: String,
     pub description: String,
+    #[serde(default)]
     pub price: u64, // in coins
     pub category: String,
     pub resources: Option<StoreResources>,
     pub duration_days: Option<u32>, // for temporary items
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub plan: Option<String>, // price, resources and duration come from this plan
+    #[serde(default)]
+    pub kind: StoreItemKind,
+    pub enabled: bool,
+}
+
+/// What buying a store item gives the user.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StoreItemKind {
+    /// Adds the item's resources to the user's pool.
+    #[default]
+    Resources,
+    /// Creates a server on the item's plan, with its own resources.
+    Server,
+}
+
+/// A server plan. Server creation and the store both read plans from here so
+/// their resources and prices cannot drift apart.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Plan {
+    pub id: String,
+    pub name: String,
+    pub description: String,
+    pub price: u64, // in coins
+    pub duration_days: u32,
+    #[serde(default)]
+    pub renewal_price: Option<u64>, // per duration_days, defaults to price
+    #[serde(default)]
+    pub renewal_discounts: Vec<RenewalDiscount>,
+    pub resources: StoreResources,
+    #[serde(default)]
+    pub templates: Vec<String>, // allowed server templates, empty allows all
+    pub max_per_user: Option<u32>,
+    pub visible: bool,
+    #[serde(default)]
+    pub console: ConsolePermissions,
+}
+
+/// What owners of a plan's servers may do through `/servers console` and `/servers logs`.
+/// Everything is off unless the plan turns it on.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ConsolePermissions {
+    #[serde(default)]
+    pub commands: bool,
+    #[serde(default)]
+    pub logs: bool,
+    #[serde(default)]
+    pub allowed_commands: Vec<String>, // first words of commands that may be sent, empty allows all
+}
+
+impl ConsolePermissions {
+    pub fn allows_command(&self, command: &str) -> bool {
+        if !self.commands {
+            return false;
+        }
+        if self.allowed_commands.is_empty() {
+            return true;
+        }
+
+        let name = command.split_whitespace().next().unwrap_or_default().trim_start_matches('/');
+        self.allowed_commands.iter().any(|allowed| allowed.eq_ignore_ascii_case(name))
+    }
+}
+
+/// Discount for renewing several plan periods at once, e.g. 3 periods at 10% off.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RenewalDiscount {
+    pub periods: u32,
+    pub percent_off: u32,
+}
+
+/// Coins sold for real money through `/coins buy`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CoinPackage {
+    pub id: String,
+    pub name: String,
+    pub coins: i64,
+    pub price_cents: i64,
     pub enabled: bool,
 }
 
+impl Plan {
+    /// The template a server on this plan uses when the user doesn't pick one.
+    pub fn default_template<'a>(&'a self, templates: &'a TemplateConfig) -> &'a str {
+        self.templates.first().map(String::as_str).unwrap_or(&templates.default_template)
+    }
+
+    pub fn renewal_price(&self) -> u64 {
+        self.renewal_price.unwrap_or(self.price)
+    }
+
+    /// The best discount that applies when renewing `periods` periods at once.
+    pub fn renewal_discount(&self, periods: u32) -> u32 {
+        self.renewal_discounts.iter()
+            .filter(|discount| discount.periods <= periods)
+            .map(|discount| discount.percent_off.min(100))
+            .max()
+            .unwrap_or(0)
+    }
+
+    pub fn renewal_cost(&self, periods: u32) -> u64 {
+        let full = self.renewal_price() * periods as u64;
+        full - full * self.renewal_discount(periods) as u64 / 100
+    }
+
+    /// Coins refunded for `remaining` unused time, at the cheapest per-day rate the
+    /// plan offers so discounted renewals can't be refunded at a profit.
+    pub fn prorated_refund(&self, remaining: chrono::Duration) -> u64 {
+        let max_discount = self.renewal_discounts.iter()
+            .map(|discount| discount.percent_off.min(100))
+            .max()
+            .unwrap_or(0) as u64;
+        let period_secs = self.duration_days as i64 * 86_400;
+        if period_secs == 0 || remaining <= chrono::Duration::zero() {
+            return 0;
+        }
+
+        let price = self.renewal_price() * (100 - max_discount) / 100;
+        (price as i128 * remaining.num_seconds() as i128 / period_secs as i128) as u64
+    }
+
+    pub fn allows_template(&self, template_id: &str) -> bool {
+        self.templates.is_empty() || self.templates.iter().any(|id| id == template_id)
+    }
+}
+
+impl StoreConfig {
+    pub fn plan(&self, id: &str) -> Option<&Plan> {
+        self.plans.iter().find(|plan| plan.id == id)
+    }
+
+    pub fn coin_package(&self, id: &str) -> Option<&CoinPackage> {
+        self.coin_packages.iter().find(|package| package.id == id && package.enabled)
+    }
+
+    /// Fills plan-backed items from their plan and checks that every plan exists.
+    fn apply_plans(&mut self) -> Result<()> {
+        let plans = self.plans.clone();
+
+        for item in self.items.iter_mut() {
+            let plan_id = match &item.plan {
+                Some(plan_id) => plan_id,
+                None if item.kind == StoreItemKind::Server => {
+                    anyhow::bail!("Store item '{}' creates a server but has no plan", item.id);
+                }
+                None => continue,
+            };
+
+            let plan = plans.iter()
+                .find(|plan| &plan.id == plan_id)
+                .with_context(|| format!("Store item '{}' references unknown plan '{}'", item.id, plan_id))?;
+
+            item.price = plan.price;
+            item.resources = Some(plan.resources.clone());
+            item.duration_days = Some(plan.duration_days);
+        }
+
+        Ok(())
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct StoreResources {
     pub ram: u32,
@@ -51,6 +267,72 @@ pub struct StoreResources {
     pub backups: u32,
 }
 
+impl StoreResources {
+    pub fn to_resources(&self) -> Resources {
+        Resources {
+            ram: self.ram as i64,
+            cpu: self.cpu as i64,
+            disk: self.disk as i64,
+            databases: self.databases as i64,
+            allocations: self.allocations as i64,
+            backups: self.backups as i64,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TemplateConfig {
+    pub default_template: String,
+    pub templates: Vec<ServerTemplate>,
+}
+
+/// Software that users can pick when creating a server. The egg definition is
+/// fetched from the panel; any field set here overrides the panel's value.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServerTemplate {
+    pub id: String,
+    pub name: String,
+    pub description: String,
+    pub nest: i64,
+    pub egg: i64,
+    pub docker_image: Option<String>,
+    pub startup: Option<String>,
+    #[serde(default)]
+    pub environment: HashMap<String, String>,
+    pub enabled: bool,
+}
+
+impl TemplateConfig {
+    pub fn get(&self, id: &str) -> Option<&ServerTemplate> {
+        self.templates.iter().find(|template| template.id == id && template.enabled)
+    }
+}
+
+impl Default for TemplateConfig {
+    fn default() -> Self {
+        Self {
+            default_template: "minecraft".to_string(),
+            templates: vec![
+                ServerTemplate {
+                    id: "minecraft".to_string(),
+                    name: "Minecraft (Paper)".to_string(),
+                    description: "High performance Minecraft server".to_string(),
+                    nest: 1,
+                    egg: 3,
+                    docker_image: None,
+                    startup: None,
+                    environment: HashMap::from([
+                        ("MINECRAFT_VERSION".to_string(), "latest".to_string()),
+                        ("BUILD_NUMBER".to_string(), "latest".to_string()),
+                        ("SERVER_JARFILE".to_string(), "server.jar".to_string()),
+                    ]),
+                    enabled: true,
+                },
+            ],
+        }
+    }
+}
+
 impl Default for StoreConfig {
     fn default() -> Self {
         Self {
@@ -75,23 +357,77 @@ impl Default for StoreConfig {
                         backups: 0,
                     }),
                     duration_days: None,
+                    plan: None,
+                    kind: StoreItemKind::Resources,
                     enabled: true,
                 },
                 StoreItem {
                     id: "basic_server".to_string(),
                     name: "Basic Server Plan".to_string(),
                     description: "1GB RAM, 100% CPU, 2GB Disk".to_string(),
-                    price: 500,
+                    price: 0,
                     category: "Servers".to_string(),
-                    resources: Some(StoreResources {
+                    resources: None,
+                    duration_days: None,
+                    plan: Some("basic".to_string()),
+                    kind: StoreItemKind::Server,
+                    enabled: true,
+                },
+            ],
+            plans: vec![
+                Plan {
+                    id: "free".to_string(),
+                    name: "Free".to_string(),
+                    description: "512MB RAM, 50% CPU, 1GB Disk".to_string(),
+                    price: 0,
+                    duration_days: 30,
+                    renewal_price: None,
+                    renewal_discounts: Vec::new(),
+                    resources: StoreResources {
+                        ram: 512,
+                        cpu: 50,
+                        disk: 1024,
+                        databases: 1,
+                        allocations: 1,
+                        backups: 1,
+                    },
+                    templates: Vec::new(),
+                    max_per_user: Some(1),
+                    visible: true,
+                    console: ConsolePermissions::default(),
+                },
+                Plan {
+                    id: "basic".to_string(),
+                    name: "Basic".to_string(),
+                    description: "1GB RAM, 100% CPU, 2GB Disk".to_string(),
+                    price: 500,
+                    duration_days: 30,
+                    renewal_price: None,
+                    renewal_discounts: Vec::new(),
+                    resources: StoreResources {
                         ram: 1024,
                         cpu: 100,
                         disk: 2048,
                         databases: 2,
                         allocations: 2,
                         backups: 2,
-                    }),
-                    duration_days: Some(30),
+                    },
+                    templates: Vec::new(),
+                    max_per_user: None,
+                    visible: true,
+                    console: ConsolePermissions {
+                        commands: true,
+                        logs: true,
+                        allowed_commands: vec!["say".to_string(), "list".to_string(), "whitelist".to_string()],
+                    },
+                },
+            ],
+            coin_packages: vec![
+                CoinPackage {
+                    id: "coins_500".to_string(),
+                    name: "500 Coins".to_string(),
+                    coins: 500,
+                    price_cents: 499,
                     enabled: true,
                 },
             ],
@@ -109,7 +445,42 @@ impl Config {
             .filter_map(|s| s.trim().parse().ok())
             .collect();
 
+        let node_locations = env::var("NODE_LOCATIONS")
+            .unwrap_or_default()
+            .split(',')
+            .filter_map(|s| s.trim().parse().ok())
+            .collect();
+
+        // Pins look like "plan:node_id,plan:node_id"
+        let node_pins = env::var("NODE_PINS")
+            .unwrap_or_default()
+            .split(',')
+            .filter_map(|pin| {
+                let (plan, node) = pin.split_once(':')?;
+                Some((plan.trim().to_string(), node.trim().parse().ok()?))
+            })
+            .collect();
+
+        let payment_providers: Vec<String> = env::var("PAYMENT_PROVIDERS")
+            .unwrap_or_else(|_| "stripe".to_string())
+            .split(',')
+            .map(|name| name.trim().to_lowercase())
+            .filter(|name| !name.is_empty())
+            .collect();
+
+        let required = [
+            ("stripe", "STRIPE_SECRET_KEY"),
+            ("paypal", "PAYPAL_CLIENT_ID"),
+            ("paypal", "PAYPAL_CLIENT_SECRET"),
+        ];
+        for (provider, var) in required {
+            if payment_providers.iter().any(|name| name == provider) && env::var(var).unwrap_or_default().is_empty() {
+                anyhow::bail!("{} not set, but the {} payment provider is enabled", var, provider);
+            }
+        }
+
         let store_config = Self::load_store_config()?;
+        let template_config = Self::load_template_config()?;
 
         Ok(Config {
             discord_token: env::var("DISCORD_TOKEN").context("DISCORD_TOKEN not set")?,
@@ -119,17 +490,51 @@ impl Config {
                 .context("Invalid DISCORD_APP_ID")?,
             pterodactyl_url: env::var("PTERODACTYL_URL").context("PTERODACTYL_URL not set")?,
             pterodactyl_api_key: env::var("PTERODACTYL_API_KEY").context("PTERODACTYL_API_KEY not set")?,
+            pterodactyl_client_api_key: env::var("PTERODACTYL_CLIENT_API_KEY").unwrap_or_default(),
             mongodb_uri: env::var("MONGODB_URI").context("MONGODB_URI not set")?,
             redis_uri: env::var("REDIS_URI").context("REDIS_URI not set")?,
-            stripe_secret_key: env::var("STRIPE_SECRET_KEY").context("STRIPE_SECRET_KEY not set")?,
-            stripe_public_key: env::var("STRIPE_PUBLIC_KEY").context("STRIPE_PUBLIC_KEY not set")?,
+            stripe_secret_key: env::var("STRIPE_SECRET_KEY").unwrap_or_default(),
+            stripe_public_key: env::var("STRIPE_PUBLIC_KEY").unwrap_or_default(),
+            stripe_api_url: env::var("STRIPE_API_URL").unwrap_or_else(|_| "https://api.stripe.com".to_string()),
+            stripe_webhook_secret: env::var("STRIPE_WEBHOOK_SECRET").unwrap_or_default(),
+            paypal_api_url: env::var("PAYPAL_API_URL").unwrap_or_else(|_| "https://api-m.paypal.com".to_string()),
+            paypal_client_id: env::var("PAYPAL_CLIENT_ID").unwrap_or_default(),
+            paypal_client_secret: env::var("PAYPAL_CLIENT_SECRET").unwrap_or_default(),
+            paypal_webhook_id: env::var("PAYPAL_WEBHOOK_ID").unwrap_or_default(),
+            manual_payment_secret: env::var("MANUAL_PAYMENT_SECRET").unwrap_or_default(),
+            payment_providers,
+            payment_currency: env::var("PAYMENT_CURRENCY").unwrap_or_else(|_| "usd".to_string()),
+            payment_success_url: env::var("PAYMENT_SUCCESS_URL").unwrap_or_else(|_| "https://example.com/payment/success".to_string()),
+            payment_cancel_url: env::var("PAYMENT_CANCEL_URL").unwrap_or_else(|_| "https://example.com/payment/cancel".to_string()),
+            webhook_bind_addr: env::var("WEBHOOK_BIND_ADDR").unwrap_or_else(|_| "0.0.0.0:8080".to_string()),
+            order_reconcile_after_minutes: env::var("ORDER_RECONCILE_AFTER_MINUTES").unwrap_or_else(|_| "30".to_string()).parse().unwrap_or(30),
+            order_reconcile_interval_secs: env::var("ORDER_RECONCILE_INTERVAL_SECS").unwrap_or_else(|_| "600".to_string()).parse().unwrap_or(600),
             afk_page_url: env::var("AFK_PAGE_URL").unwrap_or_else(|_| "https://example.com/afk".to_string()),
             linkvertise_verify_url: env::var("LINKVERTISE_VERIFY_URL").unwrap_or_else(|_| "https://example.com/verify".to_string()),
             admin_discord_ids,
             enable_transfer: env::var("ENABLE_TRANSFER").unwrap_or_else(|_| "true".to_string()).parse().unwrap_or(true),
             enable_renew: env::var("ENABLE_RENEW").unwrap_or_else(|_| "true".to_string()).parse().unwrap_or(true),
             enable_delete: env::var("ENABLE_DELETE").unwrap_or_else(|_| "true".to_string()).parse().unwrap_or(true),
+            refund_unused_days: env::var("REFUND_UNUSED_DAYS").unwrap_or_else(|_| "false".to_string()).parse().unwrap_or(false),
+            queue_workers: env::var("QUEUE_WORKERS").unwrap_or_else(|_| "2".to_string()).parse().unwrap_or(2),
+            queue_max_attempts: env::var("QUEUE_MAX_ATTEMPTS").unwrap_or_else(|_| "5".to_string()).parse().unwrap_or(5),
+            queue_retry_base_secs: env::var("QUEUE_RETRY_BASE_SECS").unwrap_or_else(|_| "10".to_string()).parse().unwrap_or(10),
+            expiry_warning_days: env::var("EXPIRY_WARNING_DAYS").unwrap_or_else(|_| "3".to_string()).parse().unwrap_or(3),
+            expiry_grace_days: env::var("EXPIRY_GRACE_DAYS").unwrap_or_else(|_| "7".to_string()).parse().unwrap_or(7),
+            over_allocation_grace_days: env::var("OVER_ALLOCATION_GRACE_DAYS").unwrap_or_else(|_| "3".to_string()).parse().unwrap_or(3),
+            expiry_sweep_interval_secs: env::var("EXPIRY_SWEEP_INTERVAL_SECS").unwrap_or_else(|_| "300".to_string()).parse().unwrap_or(300),
+            console_tail_secs: env::var("CONSOLE_TAIL_SECS").unwrap_or_else(|_| "10".to_string()).parse().unwrap_or(10),
+            backup_schedule_interval_secs: env::var("BACKUP_SCHEDULE_INTERVAL_SECS").unwrap_or_else(|_| "900".to_string()).parse().unwrap_or(900),
+            pterodactyl_user_email_domain: env::var("PTERODACTYL_USER_EMAIL_DOMAIN").unwrap_or_else(|_| "users.shaden-rs.local".to_string()),
+            pterodactyl_send_reset_link: env::var("PTERODACTYL_SEND_RESET_LINK").unwrap_or_else(|_| "true".to_string()).parse().unwrap_or(true),
+            node_selection: env::var("NODE_SELECTION")
+                .unwrap_or_else(|_| "least-loaded".to_string())
+                .parse()
+                .context("Invalid NODE_SELECTION")?,
+            node_locations,
+            node_pins,
             store_config,
+            template_config,
         })
     }
 
@@ -139,20 +544,47 @@ impl Config {
         if Path::new(&config_path).exists() {
             let content = fs::read_to_string(&config_path)
                 .context("Failed to read store config file")?;
-            let config: StoreConfig = serde_json::from_str(&content)
+            let mut config: StoreConfig = serde_json::from_str(&content)
                 .context("Failed to parse store config JSON")?;
+            config.apply_plans()?;
             Ok(config)
         } else {
             // Create default config file
-            let default_config = StoreConfig::default();
+            let mut default_config = StoreConfig::default();
             let json = serde_json::to_string_pretty(&default_config)
                 .context("Failed to serialize default store config")?;
             fs::write(&config_path, json)
                 .context("Failed to write default store config file")?;
+            default_config.apply_plans()?;
             Ok(default_config)
         }
     }
 
+    fn load_template_config() -> Result<TemplateConfig> {
+        let config_path = env::var("SERVER_TEMPLATES_PATH").unwrap_or_else(|_| "server_templates.json".to_string());
+
+        let config = if Path::new(&config_path).exists() {
+            let content = fs::read_to_string(&config_path)
+                .context("Failed to read server templates file")?;
+            serde_json::from_str(&content)
+                .context("Failed to parse server templates JSON")?
+        } else {
+            // Create default config file
+            let default_config = TemplateConfig::default();
+            let json = serde_json::to_string_pretty(&default_config)
+                .context("Failed to serialize default server templates")?;
+            fs::write(&config_path, json)
+                .context("Failed to write default server templates file")?;
+            default_config
+        };
+
+        if config.get(&config.default_template).is_none() {
+            anyhow::bail!("Default server template '{}' is missing or disabled", config.default_template);
+        }
+
+        Ok(config)
+    }
+
     pub fn is_admin(&self, user_id: u64) -> bool {
         self.admin_discord_ids.contains(&user_id)
     }
@@ -17,9 +17,15 @@ pub enum BotError {
     #[error("Stripe error: {0}")]
     Stripe(String),
     
+    #[error("PayPal error: {0}")]
+    PayPal(String),
+    
     #[error("Pterodactyl API error: {0}")]
     Pterodactyl(String),
     
+    #[error("Pterodactyl API error ({status}): {message}")]
+    PterodactylApi { status: u16, message: String },
+    
     #[error("User not found")]
     UserNotFound,
     
@@ -29,6 +35,15 @@ pub enum BotError {
     #[error("Server not found")]
     ServerNotFound,
     
+    #[error("Insufficient resources: not enough free {}", .0.join(", "))]
+    InsufficientResources(Vec<&'static str>),
+    
+    #[error("Plan limit reached: at most {0} server(s) on this plan")]
+    PlanLimitReached(u32),
+    
+    #[error("No node has capacity for a server with {ram}MB RAM and {disk}MB disk")]
+    NoNodeCapacity { ram: i64, disk: i64 },
+    
     #[error("Coupon not found or expired")]
     CouponInvalid,
     
@@ -39,4 +54,15 @@ pub enum BotError {
     InvalidInput(String),
 }
 
+impl BotError {
+    /// Whether retrying the same operation later could reasonably succeed.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            BotError::Database(_) | BotError::Redis(_) | BotError::Http(_) => true,
+            BotError::PterodactylApi { status, .. } => *status >= 500 || *status == 429,
+            _ => false,
+        }
+    }
+}
+
 pub type BotResult<T> = Result<T, BotError>;
@@ -2,7 +2,7 @@ use anyhow::Result;
 use serenity::prelude::*;
 use serenity::framework::standard::StandardFramework;
 use serenity::model::gateway::Ready;
-use serenity::all::{Interaction, Command, InteractionResponseType};
+use serenity::all::{Interaction, Command, ApplicationCommandInteraction, InteractionResponseType};
 use tracing::{info, error};
 
 mod config;
@@ -10,6 +10,9 @@ mod models;
 mod services;
 mod commands;
 mod errors;
+mod workers;
+#[cfg(test)]
+mod test_support;
 
 use config::Config;
 use services::db::Database;
@@ -31,33 +34,75 @@ impl EventHandler for Handler {
     }
 
     async fn interaction_create(&self, ctx: Context, interaction: Interaction) {
-        if let Interaction::Command(command) = interaction {
-            let result = match command.data.name.as_str() {
-                "login" => commands::coins::login(&ctx, &command, &self.database).await,
-                "coins" => commands::coins::handle_coins(&ctx, &command, &self.database).await,
-                "coupons" => commands::coupons::handle_coupons(&ctx, &command, &self.database).await,
-                "servers" => commands::servers::handle_servers(&ctx, &command, &self.database, &self.config).await,
-                "store" => commands::store::handle_store(&ctx, &command, &self.database, &self.config).await,
-                "renew" => commands::servers::renew_server(&ctx, &command, &self.database, &self.config).await,
-                "transfer" => commands::coins::transfer_coins(&ctx, &command, &self.database, &self.config).await,
-                "delete" => commands::servers::delete_server_command(&ctx, &command, &self.database, &self.config).await,
-                "join-for-reward" => commands::join_rewards::handle_join_rewards(&ctx, &command, &self.database).await,
-                "admin" => commands::admin::handle_admin(&ctx, &command, &self.database, &self.config).await,
-                "help" => commands::help(&ctx, &command).await,
-                _ => {
-                    command.create_response(&ctx.http, |response| {
-                        response
-                            .kind(InteractionResponseType::ChannelMessageWithSource)
-                            .interaction_response_data(|message| {
-                                message.content("Unknown command").ephemeral(true)
-                            })
-                    }).await
+        match interaction {
+            Interaction::Command(command) => self.handle_command(ctx, command).await,
+            Interaction::ModalSubmit(modal) => {
+                let result = if modal.data.custom_id.starts_with(commands::store::SERVER_MODAL_PREFIX) {
+                    commands::store::handle_store_modal(&ctx, &modal, &self.database, &self.config).await
+                } else {
+                    Ok(())
+                };
+
+                if let Err(e) = result {
+                    error!("Error handling modal {}: {}", modal.data.custom_id, e);
+                }
+            }
+            Interaction::Component(component) => {
+                let result = if component.data.custom_id.starts_with(commands::servers::POWER_BUTTON_PREFIX) {
+                    commands::servers::handle_power_button(&ctx, &component, &self.database, &self.config).await
+                } else if component.data.custom_id.starts_with(commands::servers::REFRESH_BUTTON_PREFIX) {
+                    commands::servers::handle_refresh_button(&ctx, &component, &self.database, &self.config).await
+                } else {
+                    Ok(())
+                };
+
+                if let Err(e) = result {
+                    error!("Error handling component {}: {}", component.data.custom_id, e);
+                }
+            }
+            Interaction::Autocomplete(autocomplete) => {
+                let result = match autocomplete.data.name.as_str() {
+                    "servers" => commands::servers::autocomplete_plans(&ctx, &autocomplete, &self.config).await,
+                    _ => Ok(()),
+                };
+
+                if let Err(e) = result {
+                    error!("Error handling autocomplete for {}: {}", autocomplete.data.name, e);
                 }
-            };
+            }
+            _ => {}
+        }
+    }
+}
 
-            if let Err(e) = result {
-                error!("Error handling command {}: {}", command.data.name, e);
+impl Handler {
+    async fn handle_command(&self, ctx: Context, command: ApplicationCommandInteraction) {
+        let result = match command.data.name.as_str() {
+            "login" => commands::coins::login(&ctx, &command, &self.database, &self.config).await,
+            "coins" => commands::coins::handle_coins(&ctx, &command, &self.database, &self.config).await,
+            "coupons" => commands::coupons::handle_coupons(&ctx, &command, &self.database).await,
+            "servers" => commands::servers::handle_servers(&ctx, &command, &self.database, &self.config).await,
+            "store" => commands::store::handle_store(&ctx, &command, &self.database, &self.config).await,
+            "renew" => commands::servers::renew_server(&ctx, &command, &self.database, &self.config).await,
+            "transfer" => commands::coins::transfer_coins(&ctx, &command, &self.database, &self.config).await,
+            "delete" => commands::servers::delete_server_command(&ctx, &command, &self.database, &self.config).await,
+            "orders" => commands::orders::handle_orders(&ctx, &command, &self.database, &self.config).await,
+            "join-for-reward" => commands::join_rewards::handle_join_rewards(&ctx, &command, &self.database).await,
+            "admin" => commands::admin::handle_admin(&ctx, &command, &self.database, &self.config).await,
+            "help" => commands::help(&ctx, &command).await,
+            _ => {
+                command.create_response(&ctx.http, |response| {
+                    response
+                        .kind(InteractionResponseType::ChannelMessageWithSource)
+                        .interaction_response_data(|message| {
+                            message.content("Unknown command").ephemeral(true)
+                        })
+                }).await
             }
+        };
+
+        if let Err(e) = result {
+            error!("Error handling command {}: {}", command.data.name, e);
         }
     }
 }
@@ -68,11 +113,17 @@ async fn main() -> Result<()> {
 
     let config = Config::load()?;
     let database = Database::new(&config).await?;
+    let migrated = database.migrate_timestamps().await?;
+    if migrated > 0 {
+        info!("Converted timestamps of {} documents to dates", migrated);
+    }
 
     let mut client = Client::builder(&config.discord_token, GatewayIntents::empty())
-        .event_handler(Handler { database, config })
+        .event_handler(Handler { database: database.clone(), config: config.clone() })
         .await?;
 
+    workers::spawn_all(database, config, client.cache_and_http.http.clone());
+
     info!("Starting shaden-rs bot...");
     client.start().await?;
 
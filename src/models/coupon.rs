@@ -10,7 +10,9 @@ pub struct Coupon {
     pub max_uses: Option<i64>,
     pub used_count: i64,
     pub used_by: HashSet<u64>,
+    #[serde(default, with = "super::timestamp::option")]
     pub expires_at: Option<DateTime<Utc>>,
+    #[serde(with = "super::timestamp")]
     pub created_at: DateTime<Utc>,
     pub created_by: u64,
 }
@@ -52,14 +54,4 @@ impl Coupon {
 
         true
     }
-
-    pub fn use_coupon(&mut self, user_id: u64) -> bool {
-        if !self.can_be_used(user_id) {
-            return false;
-        }
-
-        self.used_by.insert(user_id);
-        self.used_count += 1;
-        true
-    }
 }
@@ -0,0 +1,34 @@
+use serde::{Deserialize, Serialize};
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+/// Resources bought from a store item with a `duration_days`. They're part of
+/// the owner's `User.resources` until `expires_at`, when they're taken back out.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResourceGrant {
+    pub id: Uuid,
+    pub discord_id: u64,
+    pub item_id: String,
+    pub resources: super::Resources,
+    #[serde(with = "super::timestamp")]
+    pub created_at: DateTime<Utc>,
+    #[serde(with = "super::timestamp")]
+    pub expires_at: DateTime<Utc>,
+    #[serde(default, with = "super::timestamp::option")]
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+impl ResourceGrant {
+    pub fn new(discord_id: u64, item_id: String, resources: super::Resources, duration_days: u32) -> Self {
+        let now = Utc::now();
+        Self {
+            id: Uuid::new_v4(),
+            discord_id,
+            item_id,
+            resources,
+            created_at: now,
+            expires_at: now + chrono::Duration::days(duration_days as i64),
+            revoked_at: None,
+        }
+    }
+}
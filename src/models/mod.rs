@@ -2,8 +2,15 @@ pub mod user;
 pub mod server;
 pub mod coupon;
 pub mod order;
+pub mod server_event;
+pub mod transaction;
+pub mod grant;
+pub mod timestamp;
 
 pub use user::*;
 pub use server::*;
 pub use coupon::*;
 pub use order::*;
+pub use server_event::*;
+pub use transaction::*;
+pub use grant::*;
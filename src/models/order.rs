@@ -6,12 +6,17 @@ use uuid::Uuid;
 pub struct Order {
     pub id: Uuid,
     pub discord_id: u64,
-    pub stripe_session_id: String,
+    pub provider: String,
+    pub checkout_id: String,
+    pub package_id: String,
     pub amount_cents: i64,
     pub coins: i64,
     pub status: OrderStatus,
+    #[serde(with = "super::timestamp")]
     pub created_at: DateTime<Utc>,
+    #[serde(default, with = "super::timestamp::option")]
     pub completed_at: Option<DateTime<Utc>>,
+    pub payment_id: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -23,16 +28,19 @@ pub enum OrderStatus {
 }
 
 impl Order {
-    pub fn new(discord_id: u64, stripe_session_id: String, amount_cents: i64, coins: i64) -> Self {
+    pub fn new(discord_id: u64, provider: String, package_id: String, amount_cents: i64, coins: i64) -> Self {
         Self {
             id: Uuid::new_v4(),
             discord_id,
-            stripe_session_id,
+            provider,
+            checkout_id: String::new(),
+            package_id,
             amount_cents,
             coins,
             status: OrderStatus::Pending,
             created_at: Utc::now(),
             completed_at: None,
+            payment_id: None,
         }
     }
 
@@ -41,3 +49,12 @@ impl Order {
         self.completed_at = Some(Utc::now());
     }
 }
+
+/// Filters for searching orders; fields left as `None` match every order.
+#[derive(Debug, Clone, Default)]
+pub struct OrderQuery {
+    pub discord_id: Option<u64>,
+    pub status: Option<OrderStatus>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+}
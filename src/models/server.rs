@@ -7,12 +7,35 @@ pub struct Server {
     pub id: Uuid,
     pub discord_id: u64,
     pub pterodactyl_id: Option<i64>,
+    #[serde(default)]
+    pub pterodactyl_identifier: Option<String>, // short id the Client API addresses servers by
     pub name: String,
     pub plan: String,
+    #[serde(default)]
+    pub software: Option<String>,
     pub resources: super::Resources,
     pub status: ServerStatus,
+    #[serde(with = "super::timestamp")]
     pub expires_at: DateTime<Utc>,
+    #[serde(default, with = "super::timestamp::option")]
+    pub expiry_warned_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub auto_renew: bool,
+    #[serde(default)]
+    pub prepaid_coins: i64, // paid for renewals, the most a refund can return
+    #[serde(default)]
+    pub over_allocated: bool, // its owner's pool no longer covers it
+    #[serde(default, with = "super::timestamp::option")]
+    pub over_allocated_at: Option<DateTime<Utc>>, // when it was flagged
+    #[serde(default)]
+    pub item_id: Option<String>, // store item it was bought as; brings its own resources
+    #[serde(default)]
+    pub backup_interval_hours: Option<u32>, // automatic backups, off when unset
+    #[serde(default, with = "super::timestamp::option")]
+    pub last_auto_backup_at: Option<DateTime<Utc>>,
+    #[serde(with = "super::timestamp")]
     pub created_at: DateTime<Utc>,
+    #[serde(with = "super::timestamp")]
     pub updated_at: DateTime<Utc>,
 }
 
@@ -23,25 +46,56 @@ pub enum ServerStatus {
     Stopped,
     Suspended,
     Deleted,
+    Failed,
 }
 
 impl Server {
-    pub fn new(discord_id: u64, name: String, plan: String, resources: super::Resources) -> Self {
+    pub fn new(discord_id: u64, name: String, plan: String, software: String, resources: super::Resources, duration_days: u32) -> Self {
         let now = Utc::now();
         Self {
             id: Uuid::new_v4(),
             discord_id,
             pterodactyl_id: None,
+            pterodactyl_identifier: None,
             name,
             plan,
+            software: Some(software),
             resources,
             status: ServerStatus::Creating,
-            expires_at: now + chrono::Duration::days(30), // Default 30 days
+            expires_at: now + chrono::Duration::days(duration_days as i64),
+            expiry_warned_at: None,
+            auto_renew: false,
+            prepaid_coins: 0,
+            over_allocated: false,
+            over_allocated_at: None,
+            item_id: None,
+            backup_interval_hours: None,
+            last_auto_backup_at: None,
             created_at: now,
             updated_at: now,
         }
     }
 
+    /// Whether the server's resources are still reserved from its owner's pool.
+    /// Servers bought from the store never are.
+    pub fn holds_resources(&self) -> bool {
+        self.item_id.is_none() && self.is_renewable()
+    }
+
+    /// Whether the server still exists, so it can be renewed.
+    pub fn is_renewable(&self) -> bool {
+        !matches!(self.status, ServerStatus::Failed | ServerStatus::Deleted)
+    }
+
+    /// Whether an automatic backup is due by `now`.
+    pub fn backup_due(&self, now: DateTime<Utc>) -> bool {
+        match (self.backup_interval_hours, self.last_auto_backup_at) {
+            (Some(_), None) => true,
+            (Some(hours), Some(last)) => now >= last + chrono::Duration::hours(hours as i64),
+            (None, _) => false,
+        }
+    }
+
     pub fn is_expired(&self) -> bool {
         Utc::now() > self.expires_at
     }
@@ -0,0 +1,39 @@
+use serde::{Deserialize, Serialize};
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+use super::Server;
+
+/// Audit record of a lifecycle change made to a server.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServerEvent {
+    pub id: Uuid,
+    pub server_id: Uuid,
+    pub discord_id: u64,
+    pub kind: ServerEventKind,
+    pub detail: Option<String>,
+    #[serde(with = "super::timestamp")]
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ServerEventKind {
+    ExpiryWarning,
+    Renewed,
+    AutoRenewed,
+    Suspended,
+    Unsuspended,
+    Deleted,
+}
+
+impl ServerEvent {
+    pub fn new(server: &Server, kind: ServerEventKind, detail: Option<String>) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            server_id: server.id,
+            discord_id: server.discord_id,
+            kind,
+            detail,
+            created_at: Utc::now(),
+        }
+    }
+}
@@ -0,0 +1,49 @@
+//! Stores `DateTime<Utc>` fields as BSON dates so MongoDB compares and sorts
+//! them chronologically. Use with `#[serde(with = "timestamp")]`, or
+//! `timestamp::option` for optional fields.
+//!
+//! Older documents kept RFC 3339 strings; those still load, and
+//! `Database::migrate_timestamps` rewrites them as dates.
+
+use chrono::{DateTime, Utc};
+use mongodb::bson::{self, Bson};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+/// The BSON date for `value`, for building filters and updates.
+pub fn to_bson(value: &DateTime<Utc>) -> bson::DateTime {
+    bson::DateTime::from_millis(value.timestamp_millis())
+}
+
+fn from_bson<E: de::Error>(value: Bson) -> Result<Option<DateTime<Utc>>, E> {
+    match value {
+        Bson::DateTime(value) => DateTime::<Utc>::from_timestamp_millis(value.timestamp_millis())
+            .map(Some)
+            .ok_or_else(|| E::custom("date out of range")),
+        Bson::String(value) => DateTime::parse_from_rfc3339(&value)
+            .map(|value| Some(value.with_timezone(&Utc)))
+            .map_err(E::custom),
+        Bson::Null => Ok(None),
+        other => Err(E::custom(format!("expected a date, found {}", other))),
+    }
+}
+
+pub fn serialize<S: Serializer>(value: &DateTime<Utc>, serializer: S) -> Result<S::Ok, S::Error> {
+    to_bson(value).serialize(serializer)
+}
+
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<DateTime<Utc>, D::Error> {
+    from_bson(Bson::deserialize(deserializer)?)?
+        .ok_or_else(|| de::Error::custom("expected a date, found null"))
+}
+
+pub mod option {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(value: &Option<DateTime<Utc>>, serializer: S) -> Result<S::Ok, S::Error> {
+        value.as_ref().map(to_bson).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<DateTime<Utc>>, D::Error> {
+        from_bson(Bson::deserialize(deserializer)?)
+    }
+}
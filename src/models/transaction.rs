@@ -0,0 +1,97 @@
+use serde::{Deserialize, Serialize};
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+/// Ledger entry for a single change to a user's coin balance.
+///
+/// Entries are only ever inserted, in the same database transaction as the
+/// balance change, so the sum of a user's entries equals their balance.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CoinTransaction {
+    pub id: Uuid,
+    pub discord_id: u64,
+    pub amount: i64,
+    pub balance_after: i64,
+    pub reason: TransactionReason,
+    pub counterparty: Option<u64>,
+    pub reference: Option<String>,
+    #[serde(with = "super::timestamp")]
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum TransactionReason {
+    Store,
+    Coupon,
+    Afk,
+    Linkvertise,
+    JoinReward,
+    Transfer,
+    Renewal,
+    Refund,
+    Admin,
+    Payment,
+    Opening,
+}
+
+impl TransactionReason {
+    pub fn label(&self) -> &'static str {
+        match self {
+            TransactionReason::Store => "Store purchase",
+            TransactionReason::Coupon => "Coupon",
+            TransactionReason::Afk => "AFK reward",
+            TransactionReason::Linkvertise => "Linkvertise reward",
+            TransactionReason::JoinReward => "Join reward",
+            TransactionReason::Transfer => "Transfer",
+            TransactionReason::Renewal => "Server renewal",
+            TransactionReason::Refund => "Refund",
+            TransactionReason::Admin => "Admin adjustment",
+            TransactionReason::Payment => "Payment",
+            TransactionReason::Opening => "Opening balance",
+        }
+    }
+}
+
+/// Why a balance is being changed; turned into a `CoinTransaction` once the
+/// new balance is known.
+#[derive(Debug, Clone)]
+pub struct CoinChange {
+    pub reason: TransactionReason,
+    pub counterparty: Option<u64>,
+    pub reference: Option<String>,
+}
+
+impl CoinChange {
+    pub fn new(reason: TransactionReason) -> Self {
+        Self {
+            reason,
+            counterparty: None,
+            reference: None,
+        }
+    }
+
+    pub fn counterparty(mut self, discord_id: u64) -> Self {
+        self.counterparty = Some(discord_id);
+        self
+    }
+
+    pub fn reference(mut self, reference: impl Into<String>) -> Self {
+        self.reference = Some(reference.into());
+        self
+    }
+}
+
+impl CoinTransaction {
+    pub fn new(discord_id: u64, amount: i64, balance_after: i64, change: &CoinChange) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            discord_id,
+            amount,
+            balance_after,
+            reason: change.reason.clone(),
+            counterparty: change.counterparty,
+            reference: change.reference.clone(),
+            created_at: Utc::now(),
+        }
+    }
+}
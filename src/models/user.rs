@@ -6,9 +6,15 @@ use std::collections::HashMap;
 pub struct User {
     pub discord_id: u64,
     pub coins: i64,
-    pub resources: Resources,
+    pub resources: Resources, // permanent resources plus active timed grants
+    #[serde(default)]
+    pub allocated: Resources, // reserved by the user's servers
     pub pterodactyl_api_key: Option<String>,
+    #[serde(default)]
+    pub pterodactyl_user_id: Option<i64>,
+    #[serde(with = "super::timestamp")]
     pub created_at: DateTime<Utc>,
+    #[serde(with = "super::timestamp")]
     pub updated_at: DateTime<Utc>,
 }
 
@@ -22,6 +28,59 @@ pub struct Resources {
     pub backups: i64,
 }
 
+impl Resources {
+    pub fn add(&self, other: &Resources) -> Resources {
+        Resources {
+            ram: self.ram + other.ram,
+            cpu: self.cpu + other.cpu,
+            disk: self.disk + other.disk,
+            databases: self.databases + other.databases,
+            allocations: self.allocations + other.allocations,
+            backups: self.backups + other.backups,
+        }
+    }
+
+    pub fn saturating_sub(&self, other: &Resources) -> Resources {
+        Resources {
+            ram: (self.ram - other.ram).max(0),
+            cpu: (self.cpu - other.cpu).max(0),
+            disk: (self.disk - other.disk).max(0),
+            databases: (self.databases - other.databases).max(0),
+            allocations: (self.allocations - other.allocations).max(0),
+            backups: (self.backups - other.backups).max(0),
+        }
+    }
+
+    /// The non-zero dimensions, e.g. "512MB RAM, 1 Backup(s)".
+    pub fn summary(&self) -> String {
+        let mut parts = Vec::new();
+        if self.ram > 0 { parts.push(format!("{}MB RAM", self.ram)); }
+        if self.cpu > 0 { parts.push(format!("{}% CPU", self.cpu)); }
+        if self.disk > 0 { parts.push(format!("{}MB Disk", self.disk)); }
+        if self.databases > 0 { parts.push(format!("{} Database(s)", self.databases)); }
+        if self.allocations > 0 { parts.push(format!("{} Allocation(s)", self.allocations)); }
+        if self.backups > 0 { parts.push(format!("{} Backup(s)", self.backups)); }
+
+        if parts.is_empty() {
+            "No resources".to_string()
+        } else {
+            parts.join(", ")
+        }
+    }
+
+    /// Names of the dimensions where `self` needs more than `available` has.
+    pub fn shortfall(&self, available: &Resources) -> Vec<&'static str> {
+        let mut missing = Vec::new();
+        if self.ram > available.ram { missing.push("RAM"); }
+        if self.cpu > available.cpu { missing.push("CPU"); }
+        if self.disk > available.disk { missing.push("Disk"); }
+        if self.databases > available.databases { missing.push("Databases"); }
+        if self.allocations > available.allocations { missing.push("Allocations"); }
+        if self.backups > available.backups { missing.push("Backups"); }
+        missing
+    }
+}
+
 impl User {
     pub fn new(discord_id: u64) -> Self {
         let now = Utc::now();
@@ -29,30 +88,21 @@ impl User {
             discord_id,
             coins: 0,
             resources: Resources::default(),
+            allocated: Resources::default(),
             pterodactyl_api_key: None,
+            pterodactyl_user_id: None,
             created_at: now,
             updated_at: now,
         }
     }
 
-    pub fn can_afford(&self, cost: i64) -> bool {
-        self.coins >= cost
-    }
-
-    pub fn deduct_coins(&mut self, amount: i64) -> Result<(), crate::errors::BotError> {
-        if !self.can_afford(amount) {
-            return Err(crate::errors::BotError::InsufficientCoins {
-                needed: amount,
-                available: self.coins,
-            });
-        }
-        self.coins -= amount;
-        self.updated_at = Utc::now();
-        Ok(())
+    pub fn free_resources(&self) -> Resources {
+        self.resources.saturating_sub(&self.allocated)
     }
 
-    pub fn add_coins(&mut self, amount: i64) {
-        self.coins += amount;
-        self.updated_at = Utc::now();
+    /// Whether the user's servers reserve more than their pool holds, which
+    /// happens when a timed grant runs out.
+    pub fn is_over_allocated(&self) -> bool {
+        !self.allocated.shortfall(&self.resources).is_empty()
     }
 }
@@ -0,0 +1,113 @@
+use tracing::{info, warn};
+use crate::errors::{BotError, BotResult};
+use crate::services::{Database, PterodactylClient, PterodactylUser, CreateUserRequest};
+
+/// Links Discord users to Pterodactyl panel accounts.
+pub struct AccountService {
+    database: Database,
+    pterodactyl: PterodactylClient,
+    email_domain: String,
+}
+
+pub struct PanelLink {
+    pub panel_user: PterodactylUser,
+    pub created: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct ReconcileReport {
+    pub linked: usize,
+    pub created: usize,
+    pub failed: usize,
+}
+
+impl AccountService {
+    pub fn new(database: Database, pterodactyl: PterodactylClient, email_domain: String) -> Self {
+        Self {
+            database,
+            pterodactyl,
+            email_domain,
+        }
+    }
+
+    /// Finds or creates the panel account for a Discord user and stores its id.
+    ///
+    /// Accounts are matched on `external_id` first. Matching an existing account by
+    /// email is only allowed when `match_email` is set, since anyone can type an
+    /// address into `/login`; admins use it to reconcile pre-existing accounts.
+    pub async fn link(&self, discord_id: u64, display_name: &str, email: Option<&str>, match_email: bool) -> BotResult<PanelLink> {
+        let external_id = discord_id.to_string();
+        let email = email
+            .map(|email| email.trim().to_lowercase())
+            .unwrap_or_else(|| format!("{}@{}", discord_id, self.email_domain));
+
+        if !email.contains('@') {
+            return Err(BotError::InvalidInput("Invalid email address".to_string()));
+        }
+
+        let (panel_user, created) = if let Some(user) = self.pterodactyl.get_user_by_external_id(&external_id).await? {
+            (user, false)
+        } else if let Some(user) = self.find_claimable_by_email(&email, match_email).await? {
+            (user, false)
+        } else {
+            let request = CreateUserRequest {
+                external_id,
+                email,
+                username: format!("discord_{}", discord_id),
+                first_name: display_name.to_string(),
+                last_name: "Discord".to_string(),
+            };
+            (self.pterodactyl.create_user(&request).await?, true)
+        };
+
+        self.database.set_pterodactyl_user_id(discord_id, panel_user.id).await?;
+        info!("Linked Discord user {} to panel user {}", discord_id, panel_user.id);
+
+        Ok(PanelLink { panel_user, created })
+    }
+
+    async fn find_claimable_by_email(&self, email: &str, match_email: bool) -> BotResult<Option<PterodactylUser>> {
+        if !match_email {
+            return Ok(None);
+        }
+
+        match self.pterodactyl.find_user_by_email(email).await? {
+            Some(user) if user.root_admin || user.external_id.is_some() => {
+                warn!("Refusing to link panel user {} by email", user.id);
+                Ok(None)
+            }
+            user => Ok(user),
+        }
+    }
+
+    /// Returns the panel user id for a Discord user, linking the account if needed.
+    pub async fn ensure_linked(&self, discord_id: u64) -> BotResult<i64> {
+        let user = self.database.get_user(discord_id).await?
+            .ok_or(BotError::UserNotFound)?;
+
+        if let Some(panel_user_id) = user.pterodactyl_user_id {
+            return Ok(panel_user_id);
+        }
+
+        let link = self.link(discord_id, &format!("User {}", discord_id), None, false).await?;
+        Ok(link.panel_user.id)
+    }
+
+    /// Links every user that registered before panel accounts existed.
+    pub async fn reconcile(&self) -> BotResult<ReconcileReport> {
+        let mut report = ReconcileReport::default();
+
+        for user in self.database.get_unlinked_users().await? {
+            match self.link(user.discord_id, &format!("User {}", user.discord_id), None, true).await {
+                Ok(link) if link.created => report.created += 1,
+                Ok(_) => report.linked += 1,
+                Err(e) => {
+                    warn!("Failed to link Discord user {}: {}", user.discord_id, e);
+                    report.failed += 1;
+                }
+            }
+        }
+
+        Ok(report)
+    }
+}
@@ -0,0 +1,157 @@
+use chrono::{DateTime, Utc};
+use tracing::info;
+use crate::errors::{BotError, BotResult};
+use crate::models::{Server, ServerStatus};
+use crate::services::{Database, PowerService, PterodactylBackup, PterodactylClient};
+
+/// Manages a server's backups through the Client API, within the backup
+/// limit the panel has for it.
+pub struct BackupService {
+    database: Database,
+    pterodactyl: PterodactylClient,
+    power: PowerService,
+}
+
+/// What an automatic backup run did.
+#[derive(Debug, Clone)]
+pub struct AutoBackup {
+    pub created: PterodactylBackup,
+    pub rotated: Option<PterodactylBackup>,
+}
+
+impl BackupService {
+    pub fn new(database: Database, pterodactyl: PterodactylClient) -> Self {
+        Self {
+            power: PowerService::new(database.clone(), pterodactyl.clone()),
+            database,
+            pterodactyl,
+        }
+    }
+
+    /// The server's backups, newest first.
+    pub async fn list(&self, server: &Server) -> BotResult<Vec<PterodactylBackup>> {
+        let identifier = self.identifier(server).await?;
+        let mut backups = self.pterodactyl.list_backups(&identifier).await?;
+        backups.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+        Ok(backups)
+    }
+
+    /// How many backups the panel lets the server keep (its `feature_limits.backups`).
+    pub async fn limit(&self, server: &Server) -> BotResult<i64> {
+        let pterodactyl_id = server.pterodactyl_id
+            .ok_or_else(|| BotError::InvalidInput("This server hasn't been created on the panel yet".to_string()))?;
+        Ok(self.pterodactyl.get_server(pterodactyl_id).await?.feature_limits.backups)
+    }
+
+    pub async fn create(&self, server: &Server, name: Option<&str>) -> BotResult<PterodactylBackup> {
+        let limit = self.limit(server).await?;
+        if limit <= 0 {
+            return Err(BotError::InvalidInput("This server has no backup slots".to_string()));
+        }
+
+        let used = self.list(server).await?.iter().filter(|backup| backup.uses_slot()).count() as i64;
+        if used >= limit {
+            return Err(BotError::InvalidInput(format!(
+                "All {} backup slot(s) are in use; delete one or buy an extra backup slot",
+                limit,
+            )));
+        }
+
+        let name = name.map(|name| name.to_string())
+            .unwrap_or_else(|| format!("Backup {}", Utc::now().format("%Y-%m-%d %H:%M UTC")));
+        let identifier = self.identifier(server).await?;
+        let backup = self.pterodactyl.create_backup(&identifier, &name).await?;
+        info!("Started backup {} of server {}", backup.uuid, server.id);
+        Ok(backup)
+    }
+
+    /// Finds a backup by its UUID or a unique prefix of it, as shown in the list.
+    pub async fn find(&self, server: &Server, backup_id: &str) -> BotResult<PterodactylBackup> {
+        let backup_id = backup_id.trim().to_lowercase();
+        let mut matches: Vec<PterodactylBackup> = self.list(server).await?
+            .into_iter()
+            .filter(|backup| backup.uuid.starts_with(&backup_id))
+            .collect();
+
+        match matches.len() {
+            1 => Ok(matches.remove(0)),
+            0 => Err(BotError::InvalidInput("Backup not found".to_string())),
+            _ => Err(BotError::InvalidInput("That ID matches several backups; use more of it".to_string())),
+        }
+    }
+
+    pub async fn download_url(&self, server: &Server, backup: &PterodactylBackup) -> BotResult<String> {
+        if !backup.is_successful {
+            return Err(BotError::InvalidInput("Only finished backups can be downloaded".to_string()));
+        }
+        let identifier = self.identifier(server).await?;
+        self.pterodactyl.backup_download_url(&identifier, &backup.uuid).await
+    }
+
+    pub async fn restore(&self, server: &Server, backup: &PterodactylBackup, truncate: bool) -> BotResult<()> {
+        if !backup.is_successful {
+            return Err(BotError::InvalidInput("Only finished backups can be restored".to_string()));
+        }
+        let identifier = self.identifier(server).await?;
+        self.pterodactyl.restore_backup(&identifier, &backup.uuid, truncate).await?;
+        info!("Restoring backup {} onto server {} (truncate: {})", backup.uuid, server.id, truncate);
+        Ok(())
+    }
+
+    pub async fn toggle_lock(&self, server: &Server, backup: &PterodactylBackup) -> BotResult<PterodactylBackup> {
+        let identifier = self.identifier(server).await?;
+        self.pterodactyl.toggle_backup_lock(&identifier, &backup.uuid).await
+    }
+
+    pub async fn delete(&self, server: &Server, backup: &PterodactylBackup) -> BotResult<()> {
+        if backup.is_locked {
+            return Err(BotError::InvalidInput("Locked backups can't be deleted; unlock it first".to_string()));
+        }
+        let identifier = self.identifier(server).await?;
+        self.pterodactyl.delete_backup(&identifier, &backup.uuid).await?;
+        info!("Deleted backup {} of server {}", backup.uuid, server.id);
+        Ok(())
+    }
+
+    /// Takes a scheduled backup, deleting the oldest unlocked one first when
+    /// every slot is in use. Returns `None` if another run got there first.
+    pub async fn run_scheduled(&self, server: &Server, now: DateTime<Utc>) -> BotResult<Option<AutoBackup>> {
+        if !self.database.claim_auto_backup(&server.id.to_string(), server.last_auto_backup_at, now).await? {
+            return Ok(None);
+        }
+
+        let limit = self.limit(server).await?;
+        if limit <= 0 {
+            return Err(BotError::InvalidInput("This server has no backup slots".to_string()));
+        }
+
+        let backups = self.list(server).await?;
+        let used = backups.iter().filter(|backup| backup.uses_slot()).count() as i64;
+        let identifier = self.identifier(server).await?;
+
+        let mut rotated = None;
+        if used >= limit {
+            // Newest first, so the last finished, unlocked one is the oldest
+            let oldest = backups.into_iter()
+                .filter(|backup| backup.is_successful && !backup.is_locked)
+                .last()
+                .ok_or_else(|| BotError::InvalidInput("Every backup slot holds a locked or running backup".to_string()))?;
+            self.pterodactyl.delete_backup(&identifier, &oldest.uuid).await?;
+            info!("Rotated out backup {} of server {}", oldest.uuid, server.id);
+            rotated = Some(oldest);
+        }
+
+        let name = format!("Automatic backup {}", now.format("%Y-%m-%d %H:%M UTC"));
+        let created = self.pterodactyl.create_backup(&identifier, &name).await?;
+        info!("Started automatic backup {} of server {}", created.uuid, server.id);
+
+        Ok(Some(AutoBackup { created, rotated }))
+    }
+
+    async fn identifier(&self, server: &Server) -> BotResult<String> {
+        if !matches!(server.status, ServerStatus::Running | ServerStatus::Stopped) {
+            return Err(BotError::InvalidInput("Backups are only available for running or stopped servers".to_string()));
+        }
+        self.power.identifier(server).await
+    }
+}
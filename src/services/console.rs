@@ -0,0 +1,134 @@
+use std::time::Duration;
+use futures::{SinkExt, StreamExt};
+use tokio_tungstenite::connect_async;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::http::HeaderValue;
+use tokio_tungstenite::tungstenite::Message;
+use tracing::{debug, info};
+use crate::errors::{BotError, BotResult};
+use crate::models::{Server, ServerStatus};
+use crate::services::{Database, PowerService, PterodactylClient};
+
+/// Most console lines kept from one tail; older ones are dropped first.
+const MAX_TAIL_LINES: usize = 1000;
+
+/// Sends console commands and reads console output over the Client API.
+pub struct ConsoleService {
+    pterodactyl: PterodactylClient,
+    power: PowerService,
+}
+
+impl ConsoleService {
+    pub fn new(database: Database, pterodactyl: PterodactylClient) -> Self {
+        Self {
+            power: PowerService::new(database, pterodactyl.clone()),
+            pterodactyl,
+        }
+    }
+
+    pub async fn send_command(&self, server: &Server, command: &str) -> BotResult<()> {
+        ensure_active(server)?;
+        let identifier = self.power.identifier(server).await?;
+        self.pterodactyl.send_command(&identifier, command).await?;
+        info!("User {} sent `{}` to server {}", server.discord_id, command, server.id);
+        Ok(())
+    }
+
+    /// Connects to the server's console and collects its output for `window`,
+    /// starting with the recent history the daemon replays on connect.
+    pub async fn tail(&self, server: &Server, window: Duration) -> BotResult<Vec<String>> {
+        ensure_active(server)?;
+        let identifier = self.power.identifier(server).await?;
+        let credentials = self.pterodactyl.get_websocket_credentials(&identifier).await?;
+
+        let mut request = credentials.socket.as_str().into_client_request()
+            .map_err(|e| BotError::Pterodactyl(format!("Invalid console socket URL: {}", e)))?;
+        let origin = HeaderValue::from_str(self.pterodactyl.base_url())
+            .map_err(|e| BotError::Pterodactyl(format!("Invalid panel URL: {}", e)))?;
+        request.headers_mut().insert("Origin", origin);
+
+        let (mut socket, _) = connect_async(request).await
+            .map_err(|e| BotError::Pterodactyl(format!("Failed to connect to the console: {}", e)))?;
+
+        send_event(&mut socket, "auth", serde_json::json!([credentials.token])).await?;
+
+        let mut lines = Vec::new();
+        let deadline = tokio::time::Instant::now() + window;
+        loop {
+            let message = match tokio::time::timeout_at(deadline, socket.next()).await {
+                Ok(Some(Ok(message))) => message,
+                Ok(Some(Err(e))) => return Err(BotError::Pterodactyl(format!("Console connection failed: {}", e))),
+                Ok(None) | Err(_) => break,
+            };
+
+            let text = match message {
+                Message::Text(text) => text,
+                Message::Close(_) => break,
+                _ => continue,
+            };
+
+            let event: serde_json::Value = match serde_json::from_str(&text) {
+                Ok(event) => event,
+                Err(_) => continue,
+            };
+
+            match event["event"].as_str().unwrap_or_default() {
+                "auth success" => send_event(&mut socket, "send logs", serde_json::json!([null])).await?,
+                "console output" | "install output" | "daemon message" => {
+                    let output = event["args"][0].as_str().unwrap_or_default();
+                    lines.extend(output.lines().map(strip_ansi));
+                    if lines.len() > MAX_TAIL_LINES {
+                        lines.drain(..lines.len() - MAX_TAIL_LINES);
+                    }
+                }
+                "jwt error" | "daemon error" => {
+                    let reason = event["args"][0].as_str().unwrap_or("unknown error");
+                    return Err(BotError::Pterodactyl(format!("Console refused the connection: {}", reason)));
+                }
+                other => debug!("Ignoring console event {} for server {}", other, server.id),
+            }
+        }
+
+        let _ = socket.close(None).await;
+        Ok(lines)
+    }
+}
+
+fn ensure_active(server: &Server) -> BotResult<()> {
+    if !matches!(server.status, ServerStatus::Running | ServerStatus::Stopped) || server.pterodactyl_id.is_none() {
+        return Err(BotError::InvalidInput("The console is only available for running or stopped servers".to_string()));
+    }
+    Ok(())
+}
+
+async fn send_event<S>(socket: &mut S, event: &str, args: serde_json::Value) -> BotResult<()>
+where
+    S: SinkExt<Message> + Unpin,
+    S::Error: std::fmt::Display,
+{
+    let payload = serde_json::json!({ "event": event, "args": args }).to_string();
+    socket.send(Message::Text(payload)).await
+        .map_err(|e| BotError::Pterodactyl(format!("Failed to talk to the console: {}", e)))
+}
+
+/// Removes the terminal colour codes the daemon and most games write to the console.
+fn strip_ansi(line: &str) -> String {
+    let mut stripped = String::with_capacity(line.len());
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\u{1b}' {
+            stripped.push(c);
+            continue;
+        }
+        if chars.peek() == Some(&'[') {
+            chars.next();
+            // Parameters run until the final byte, which is in '@'..='~'
+            for c in chars.by_ref() {
+                if ('@'..='~').contains(&c) {
+                    break;
+                }
+            }
+        }
+    }
+    stripped.trim_end_matches('\r').to_string()
+}
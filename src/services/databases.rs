@@ -0,0 +1,86 @@
+use tracing::info;
+use crate::errors::{BotError, BotResult};
+use crate::models::{Server, ServerStatus};
+use crate::services::{Database, PowerService, PterodactylClient, PterodactylDatabase};
+
+/// Manages a server's MySQL databases through the Client API, within the
+/// number of databases its resources include.
+pub struct ServerDatabaseService {
+    pterodactyl: PterodactylClient,
+    power: PowerService,
+}
+
+impl ServerDatabaseService {
+    pub fn new(database: Database, pterodactyl: PterodactylClient) -> Self {
+        Self {
+            power: PowerService::new(database, pterodactyl.clone()),
+            pterodactyl,
+        }
+    }
+
+    /// The server's databases, with their passwords.
+    pub async fn list(&self, server: &Server) -> BotResult<Vec<PterodactylDatabase>> {
+        let identifier = self.identifier(server).await?;
+        self.pterodactyl.list_databases(&identifier).await
+    }
+
+    pub async fn create(&self, server: &Server, name: &str, remote: &str) -> BotResult<PterodactylDatabase> {
+        if name.is_empty() || name.len() > 48 || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+            return Err(BotError::InvalidInput("Database names may only use letters, numbers, _ and -, up to 48 characters".to_string()));
+        }
+        if remote.is_empty() || remote.len() > 15 || !remote.chars().all(|c| c.is_ascii_digit() || c == '.' || c == '%') {
+            return Err(BotError::InvalidInput("Remote must be an IP address pattern such as % or 192.168.%".to_string()));
+        }
+
+        let limit = server.resources.databases;
+        if limit <= 0 {
+            return Err(BotError::InvalidInput("This server has no database slots".to_string()));
+        }
+        let used = self.list(server).await?.len() as i64;
+        if used >= limit {
+            return Err(BotError::InvalidInput(format!(
+                "All {} database slot(s) are in use; delete one or buy an extra database",
+                limit,
+            )));
+        }
+
+        let identifier = self.identifier(server).await?;
+        let database = self.pterodactyl.create_database(&identifier, name, remote).await?;
+        info!("Created database {} for server {}", database.name, server.id);
+        Ok(database)
+    }
+
+    /// Finds a database by its ID or its name, with or without the panel's prefix.
+    pub async fn find(&self, server: &Server, database_id: &str) -> BotResult<PterodactylDatabase> {
+        let database_id = database_id.trim();
+        self.list(server).await?
+            .into_iter()
+            .find(|database| {
+                database.id == database_id
+                    || database.name == database_id
+                    || database.name.split_once('_').map(|(_, name)| name) == Some(database_id)
+            })
+            .ok_or_else(|| BotError::InvalidInput("Database not found".to_string()))
+    }
+
+    pub async fn rotate_password(&self, server: &Server, database: &PterodactylDatabase) -> BotResult<PterodactylDatabase> {
+        let identifier = self.identifier(server).await?;
+        let database = self.pterodactyl.rotate_database_password(&identifier, &database.id).await?;
+        info!("Rotated the password of database {} for server {}", database.name, server.id);
+        Ok(database)
+    }
+
+    pub async fn delete(&self, server: &Server, database: &PterodactylDatabase) -> BotResult<()> {
+        let identifier = self.identifier(server).await?;
+        self.pterodactyl.delete_database(&identifier, &database.id).await?;
+        info!("Deleted database {} for server {}", database.name, server.id);
+        Ok(())
+    }
+
+    async fn identifier(&self, server: &Server) -> BotResult<String> {
+        if !matches!(server.status, ServerStatus::Running | ServerStatus::Stopped) {
+            return Err(BotError::InvalidInput("Databases are only available for running or stopped servers".to_string()));
+        }
+        self.power.identifier(server).await
+    }
+}
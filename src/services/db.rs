@@ -1,14 +1,19 @@
 use anyhow::Result;
-use mongodb::{Client, Database as MongoDatabase, Collection};
+use mongodb::{Client, ClientSession, Database as MongoDatabase, Collection};
+use mongodb::error::{TRANSIENT_TRANSACTION_ERROR, UNKNOWN_TRANSACTION_COMMIT_RESULT};
+use mongodb::options::{FindOneAndUpdateOptions, ReturnDocument};
 use redis::{Client as RedisClient, aio::Connection as RedisConnection};
 use crate::config::Config;
 use crate::models::*;
+use crate::models::timestamp;
 use crate::errors::{BotError, BotResult};
 use mongodb::bson::{doc, Document};
 use futures::TryStreamExt;
+use futures::future::BoxFuture;
 
 #[derive(Clone)]
 pub struct Database {
+    mongo_client: Client,
     mongo: MongoDatabase,
     redis_client: RedisClient,
 }
@@ -21,11 +26,46 @@ impl Database {
         let redis_client = RedisClient::open(config.redis_uri.as_str())?;
 
         Ok(Self {
+            mongo_client,
             mongo,
             redis_client,
         })
     }
 
+    /// Rewrites timestamps older versions stored as RFC 3339 strings as BSON
+    /// dates, so range queries compare them chronologically. Safe to run on
+    /// every start; documents already migrated don't match.
+    pub async fn migrate_timestamps(&self) -> BotResult<u64> {
+        const FIELDS: &[(&str, &[&str])] = &[
+            ("users", &["created_at", "updated_at"]),
+            ("servers", &["expires_at", "expiry_warned_at", "last_auto_backup_at", "created_at", "updated_at"]),
+            ("resource_grants", &["created_at", "expires_at", "revoked_at"]),
+            ("orders", &["created_at", "completed_at"]),
+            ("server_events", &["created_at"]),
+            ("coupons", &["expires_at", "created_at"]),
+            ("transactions", &["created_at"]),
+        ];
+
+        let mut migrated = 0;
+        for (collection, fields) in FIELDS {
+            let collection = self.mongo.collection::<Document>(collection);
+            for field in *fields {
+                let result = collection
+                    .update_many(
+                        doc! { *field: { "$type": "string" } },
+                        // Anything that won't parse is left as it was rather than failing startup
+                        vec![doc! { "$set": { *field: {
+                            "$dateFromString": { "dateString": format!("${}", field), "onError": format!("${}", field) },
+                        } } }],
+                        None,
+                    )
+                    .await?;
+                migrated += result.modified_count;
+            }
+        }
+        Ok(migrated)
+    }
+
     pub async fn get_redis_connection(&self) -> BotResult<RedisConnection> {
         Ok(self.redis_client.get_async_connection().await?)
     }
@@ -48,13 +88,6 @@ impl Database {
         Ok(user)
     }
 
-    pub async fn update_user(&self, user: &User) -> BotResult<()> {
-        self.users()
-            .replace_one(doc! { "discord_id": user.discord_id as i64 }, user, None)
-            .await?;
-        Ok(())
-    }
-
     pub async fn get_or_create_user(&self, discord_id: u64) -> BotResult<User> {
         if let Some(user) = self.get_user(discord_id).await? {
             Ok(user)
@@ -63,6 +96,446 @@ impl Database {
         }
     }
 
+    pub async fn set_pterodactyl_user_id(&self, discord_id: u64, pterodactyl_user_id: i64) -> BotResult<()> {
+        self.users()
+            .update_one(
+                doc! { "discord_id": discord_id as i64 },
+                doc! { "$set": { "pterodactyl_user_id": pterodactyl_user_id, "updated_at": mongodb::bson::DateTime::now() } },
+                None,
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Returns resources reserved by a server to its owner's pool.
+    pub async fn release_resources(&self, discord_id: u64, resources: &Resources) -> BotResult<()> {
+        self.users()
+            .update_one(
+                doc! { "discord_id": discord_id as i64 },
+                doc! {
+                    "$inc": resource_delta("allocated", resources, -1),
+                    "$set": { "updated_at": mongodb::bson::DateTime::now() },
+                },
+                None,
+            )
+            .await?;
+        Ok(())
+    }
+
+    pub async fn get_unlinked_users(&self) -> BotResult<Vec<User>> {
+        let cursor = self.users()
+            .find(doc! { "pterodactyl_user_id": null }, None)
+            .await?;
+        let users: Vec<User> = cursor.try_collect().await?;
+        Ok(users)
+    }
+
+    // Coin operations
+    //
+    // Balances are only changed through conditional `$inc`s, never by replacing
+    // the whole user, so concurrent commands can't lose updates or double-spend.
+    // Every change is written to the ledger in the same transaction.
+
+    /// Adds coins to a user's balance and returns the new balance.
+    pub async fn add_coins(&self, discord_id: u64, amount: i64, change: CoinChange) -> BotResult<i64> {
+        require_positive(amount)?;
+        self.with_transaction(move |db, session| {
+            let change = change.clone();
+            Box::pin(async move { db.change_coins_in(session, discord_id, amount, Document::new(), &change).await })
+        }).await
+    }
+
+    /// Takes coins from a user if they can afford it and returns the new balance.
+    pub async fn deduct_coins(&self, discord_id: u64, amount: i64, change: CoinChange) -> BotResult<i64> {
+        require_positive(amount)?;
+        self.with_transaction(move |db, session| {
+            let change = change.clone();
+            Box::pin(async move { db.change_coins_in(session, discord_id, -amount, Document::new(), &change).await })
+        }).await
+    }
+
+    /// Sets a user's balance, recording the difference on the ledger.
+    pub async fn set_coins(&self, discord_id: u64, amount: i64, change: CoinChange) -> BotResult<()> {
+        self.with_transaction(move |db, session| {
+            let change = change.clone();
+            Box::pin(async move {
+                let previous = db.users()
+                    .find_one_and_update_with_session(
+                        doc! { "discord_id": discord_id as i64 },
+                        doc! { "$set": { "coins": amount, "updated_at": mongodb::bson::DateTime::now() } },
+                        None,
+                        session,
+                    )
+                    .await?
+                    .ok_or(BotError::UserNotFound)?;
+
+                db.record_transaction_in(session, CoinTransaction::new(discord_id, amount - previous.coins, amount, &change)).await
+            })
+        }).await
+    }
+
+    /// Charges `price` and adds `resources` to the user's pool in a single update.
+    /// Returns the new balance.
+    pub async fn purchase_resources(&self, discord_id: u64, price: i64, resources: &Resources, change: CoinChange) -> BotResult<i64> {
+        let inc = resource_delta("resources", resources, 1);
+        self.with_transaction(move |db, session| {
+            let (inc, change) = (inc.clone(), change.clone());
+            Box::pin(async move { db.change_coins_in(session, discord_id, -price, inc, &change).await })
+        }).await
+    }
+
+    /// Charges `price` and adds a timed grant's resources to the user's pool,
+    /// recording the grant so it can be taken back when it expires. Returns the
+    /// new balance.
+    pub async fn purchase_grant(&self, discord_id: u64, price: i64, grant: &ResourceGrant, change: CoinChange) -> BotResult<i64> {
+        let inc = resource_delta("resources", &grant.resources, 1);
+        let grant = grant.clone();
+        self.with_transaction(move |db, session| {
+            let (inc, grant, change) = (inc.clone(), grant.clone(), change.clone());
+            Box::pin(async move {
+                let balance = db.change_coins_in(session, discord_id, -price, inc, &change).await?;
+                db.grants().insert_one_with_session(&grant, None, session).await?;
+                Ok(balance)
+            })
+        }).await
+    }
+
+    /// Charges `price` for a server bought from the store and saves it, together,
+    /// refusing once the user has `max_per_user` servers on its plan.
+    /// Returns the new balance.
+    pub async fn purchase_server(&self, server: &Server, price: i64, max_per_user: Option<u32>, change: CoinChange) -> BotResult<i64> {
+        let server = server.clone();
+        self.with_transaction(move |db, session| {
+            let (server, change) = (server.clone(), change.clone());
+            Box::pin(async move {
+                db.check_plan_limit_in(session, &server, max_per_user).await?;
+                let balance = db.change_coins_in(session, server.discord_id, -price, Document::new(), &change).await?;
+                db.servers().insert_one_with_session(&server, None, session).await?;
+                Ok(balance)
+            })
+        }).await
+    }
+
+    /// Creates a server on a plan: checks `max_per_user`, reserves the plan's
+    /// resources, charges `price` and saves the server in one transaction.
+    /// The reservation writes the owner's user document, so concurrent creates
+    /// conflict and retry instead of both passing the limit.
+    pub async fn create_plan_server(&self, server: &Server, price: i64, max_per_user: Option<u32>, change: CoinChange) -> BotResult<()> {
+        let server = server.clone();
+        self.with_transaction(move |db, session| {
+            let (server, change) = (server.clone(), change.clone());
+            Box::pin(async move {
+                db.check_plan_limit_in(session, &server, max_per_user).await?;
+
+                db.reserve_in(session, &server).await?;
+
+                if price > 0 {
+                    db.change_coins_in(session, server.discord_id, -price, Document::new(), &change).await?;
+                }
+                db.servers().insert_one_with_session(&server, None, session).await?;
+                Ok(())
+            })
+        }).await
+    }
+
+    /// Moves a failed server back to `Creating` for another attempt: reserves its
+    /// resources again and charges `price`, which `on_failure` refunded, in one
+    /// transaction. Returns false if the server was no longer failed.
+    pub async fn restart_failed_server(&self, server: &Server, price: i64, change: CoinChange) -> BotResult<bool> {
+        let server = server.clone();
+        self.with_transaction(move |db, session| {
+            let (server, change) = (server.clone(), change.clone());
+            Box::pin(async move {
+                let restarted = db.servers()
+                    .update_one_with_session(
+                        doc! { "id": server.id.to_string(), "status": "Failed" },
+                        doc! { "$set": {
+                            "status": "Creating",
+                            "prepaid_coins": price,
+                            "updated_at": mongodb::bson::DateTime::now(),
+                        } },
+                        None,
+                        session,
+                    )
+                    .await?;
+                if restarted.matched_count == 0 {
+                    return Ok(false);
+                }
+
+                db.reserve_in(session, &server).await?;
+
+                if price > 0 {
+                    db.change_coins_in(session, server.discord_id, -price, Document::new(), &change).await?;
+                }
+                Ok(true)
+            })
+        }).await
+    }
+
+    /// Undoes a server creation that never reached the queue: deletes the server,
+    /// returns its resources to the owner's pool and refunds its `prepaid_coins`
+    /// in one transaction. Returns false if the server was already gone.
+    pub async fn cancel_server_creation(&self, server: &Server) -> BotResult<bool> {
+        let server = server.clone();
+        self.with_transaction(move |db, session| {
+            let server = server.clone();
+            Box::pin(async move {
+                let deleted = db.servers()
+                    .delete_one_with_session(doc! { "id": server.id.to_string() }, None, session)
+                    .await?;
+                if deleted.deleted_count == 0 {
+                    return Ok(false);
+                }
+
+                let inc = if server.holds_resources() {
+                    resource_delta("allocated", &server.resources, -1)
+                } else {
+                    Document::new()
+                };
+                let change = CoinChange::new(TransactionReason::Refund).reference(server.id.to_string());
+                db.change_coins_in(session, server.discord_id, server.prepaid_coins, inc, &change).await?;
+                Ok(true)
+            })
+        }).await
+    }
+
+    /// Reserves `server`'s resources from its owner's pool, failing with what's
+    /// missing if any dimension would go over.
+    async fn reserve_in(&self, session: &mut ClientSession, server: &Server) -> BotResult<()> {
+        let reserved = self.users()
+            .update_one_with_session(
+                reserve_filter(server.discord_id, &server.resources),
+                reserve_update(&server.resources),
+                None,
+                session,
+            )
+            .await?;
+        if reserved.modified_count != 1 {
+            let user = self.users()
+                .find_one_with_session(doc! { "discord_id": server.discord_id as i64 }, None, session)
+                .await?
+                .ok_or(BotError::UserNotFound)?;
+            return Err(BotError::InsufficientResources(server.resources.shortfall(&user.free_resources())));
+        }
+        Ok(())
+    }
+
+    /// Fails if the owner already has `max_per_user` servers on `server`'s plan.
+    /// The callers also debit the owner's balance in the same transaction, so
+    /// two concurrent creates conflict on the user document and one retries.
+    async fn check_plan_limit_in(&self, session: &mut ClientSession, server: &Server, max_per_user: Option<u32>) -> BotResult<()> {
+        if let Some(max_per_user) = max_per_user {
+            let count = self.servers()
+                .count_documents_with_session(plan_servers_filter(server.discord_id, &server.plan), None, session)
+                .await?;
+            if count >= max_per_user as u64 {
+                return Err(BotError::PlanLimitReached(max_per_user));
+            }
+        }
+        Ok(())
+    }
+
+    /// Moves coins from one user to another and returns the sender's new balance.
+    pub async fn transfer_coins(&self, from: u64, to: u64, amount: i64) -> BotResult<i64> {
+        require_positive(amount)?;
+        self.with_transaction(move |db, session| {
+            Box::pin(async move {
+                let change = CoinChange::new(TransactionReason::Transfer);
+                let balance = db.change_coins_in(session, from, -amount, Document::new(), &change.clone().counterparty(to)).await?;
+                db.change_coins_in(session, to, amount, Document::new(), &change.counterparty(from)).await?;
+                Ok(balance)
+            })
+        }).await
+    }
+
+    /// Applies `amount` (and any extra `$inc` fields) to a user's balance inside a
+    /// transaction and writes the ledger entry. Negative amounts require the user
+    /// to have enough coins.
+    async fn change_coins_in(&self, session: &mut ClientSession, discord_id: u64, amount: i64, inc: Document, change: &CoinChange) -> BotResult<i64> {
+        self.apply_coins_in(session, discord_id, amount, inc, change, false).await
+    }
+
+    /// Like `change_coins_in`, but with `overdraft` the balance may go negative,
+    /// e.g. when taking back coins from a refunded payment that were already spent.
+    async fn apply_coins_in(&self, session: &mut ClientSession, discord_id: u64, amount: i64, mut inc: Document, change: &CoinChange, overdraft: bool) -> BotResult<i64> {
+        let mut filter = doc! { "discord_id": discord_id as i64 };
+        if amount < 0 && !overdraft {
+            filter.insert("coins", doc! { "$gte": -amount });
+        }
+        inc.insert("coins", amount);
+
+        let user = self.users()
+            .find_one_and_update_with_session(
+                filter,
+                doc! {
+                    "$inc": inc,
+                    "$set": { "updated_at": mongodb::bson::DateTime::now() },
+                },
+                return_updated(),
+                session,
+            )
+            .await?;
+
+        let user = match user {
+            Some(user) => user,
+            None if amount < 0 && !overdraft => return Err(self.insufficient_coins(discord_id, -amount).await),
+            None => return Err(BotError::UserNotFound),
+        };
+
+        self.record_transaction_in(session, CoinTransaction::new(discord_id, amount, user.coins, change)).await?;
+        Ok(user.coins)
+    }
+
+    async fn insufficient_coins(&self, discord_id: u64, needed: i64) -> BotError {
+        match self.get_user(discord_id).await {
+            Ok(Some(user)) => BotError::InsufficientCoins { needed, available: user.coins },
+            Ok(None) => BotError::UserNotFound,
+            Err(e) => e,
+        }
+    }
+
+    /// Runs `body` in a transaction, retrying it on transient errors.
+    async fn with_transaction<T, F>(&self, mut body: F) -> BotResult<T>
+    where
+        F: for<'s> FnMut(Database, &'s mut ClientSession) -> BoxFuture<'s, BotResult<T>>,
+    {
+        let mut session = self.mongo_client.start_session(None).await?;
+
+        loop {
+            session.start_transaction(None).await?;
+
+            let value = match body(self.clone(), &mut session).await {
+                Ok(value) => value,
+                Err(e) => {
+                    let _ = session.abort_transaction().await;
+                    if is_transient_transaction_error(&e) {
+                        continue;
+                    }
+                    return Err(e);
+                }
+            };
+
+            match commit(&mut session).await {
+                Ok(()) => return Ok(value),
+                Err(e) if e.contains_label(TRANSIENT_TRANSACTION_ERROR) => continue,
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    // Ledger operations
+    pub fn transactions(&self) -> Collection<CoinTransaction> {
+        self.mongo.collection("transactions")
+    }
+
+    async fn record_transaction_in(&self, session: &mut ClientSession, transaction: CoinTransaction) -> BotResult<()> {
+        // Nothing changed hands, e.g. a free store item or a coins-less coupon
+        if transaction.amount == 0 {
+            return Ok(());
+        }
+
+        self.transactions().insert_one_with_session(&transaction, None, session).await?;
+        Ok(())
+    }
+
+    /// A page of a user's ledger, newest first, and the total number of entries.
+    pub async fn get_transactions(&self, discord_id: u64, page: u64, per_page: i64) -> BotResult<(Vec<CoinTransaction>, u64)> {
+        let filter = doc! { "discord_id": discord_id as i64 };
+        let options = mongodb::options::FindOptions::builder()
+            .sort(doc! { "created_at": -1 })
+            .skip(page * per_page as u64)
+            .limit(per_page)
+            .build();
+
+        let total = self.transactions().count_documents(filter.clone(), None).await?;
+        let cursor = self.transactions().find(filter, options).await?;
+        let transactions: Vec<CoinTransaction> = cursor.try_collect().await?;
+        Ok((transactions, total))
+    }
+
+    /// Sum of a user's ledger entries, which should equal their balance.
+    pub async fn get_ledger_total(&self, discord_id: u64) -> BotResult<i64> {
+        let totals = self.ledger_totals(doc! { "discord_id": discord_id as i64 }).await?;
+        Ok(totals.get(&discord_id).copied().unwrap_or(0))
+    }
+
+    /// Users whose balance doesn't match their ledger, as `(discord_id, coins, ledger_total)`.
+    pub async fn get_ledger_mismatches(&self) -> BotResult<Vec<(u64, i64, i64)>> {
+        let totals = self.ledger_totals(Document::new()).await?;
+
+        let mut cursor = self.users().find(None, None).await?;
+        let mut mismatches = Vec::new();
+        while let Some(user) = cursor.try_next().await? {
+            let total = totals.get(&user.discord_id).copied().unwrap_or(0);
+            if total != user.coins {
+                mismatches.push((user.discord_id, user.coins, total));
+            }
+        }
+        Ok(mismatches)
+    }
+
+    /// Records an opening entry for the difference between every mismatched user's
+    /// balance and their ledger, e.g. balances from before the ledger existed.
+    /// Returns how many were opened.
+    pub async fn open_ledger_balances(&self) -> BotResult<u64> {
+        let mut opened = 0;
+        for (discord_id, _, _) in self.get_ledger_mismatches().await? {
+            if self.open_ledger_balance(discord_id).await? {
+                opened += 1;
+            }
+        }
+        Ok(opened)
+    }
+
+    /// Reads the balance and the ledger and records the difference in one
+    /// transaction, so a concurrent coin change is counted on both sides or neither.
+    async fn open_ledger_balance(&self, discord_id: u64) -> BotResult<bool> {
+        self.with_transaction(move |db, session| {
+            Box::pin(async move {
+                let user = db.users()
+                    .find_one_with_session(doc! { "discord_id": discord_id as i64 }, None, session)
+                    .await?
+                    .ok_or(BotError::UserNotFound)?;
+
+                let pipeline = vec![
+                    doc! { "$match": { "discord_id": discord_id as i64 } },
+                    doc! { "$group": { "_id": null, "total": { "$sum": "$amount" } } },
+                ];
+                let mut cursor = db.transactions().aggregate_with_session(pipeline, None, session).await?;
+                let total = match cursor.next(session).await.transpose()? {
+                    Some(row) => row.get_i64("total").map_err(|e| BotError::InvalidInput(e.to_string()))?,
+                    None => 0,
+                };
+                drop(cursor);
+
+                let difference = user.coins - total;
+                if difference == 0 {
+                    return Ok(false);
+                }
+
+                let change = CoinChange::new(TransactionReason::Opening);
+                db.record_transaction_in(session, CoinTransaction::new(discord_id, difference, user.coins, &change)).await?;
+                Ok(true)
+            })
+        }).await
+    }
+
+    async fn ledger_totals(&self, filter: Document) -> BotResult<std::collections::HashMap<u64, i64>> {
+        let pipeline = vec![
+            doc! { "$match": filter },
+            doc! { "$group": { "_id": "$discord_id", "total": { "$sum": "$amount" } } },
+        ];
+
+        let mut cursor = self.transactions().aggregate(pipeline, None).await?;
+        let mut totals = std::collections::HashMap::new();
+        while let Some(row) = cursor.try_next().await? {
+            let discord_id = row.get_i64("_id").map_err(|e| BotError::InvalidInput(e.to_string()))?;
+            let total = row.get_i64("total").map_err(|e| BotError::InvalidInput(e.to_string()))?;
+            totals.insert(discord_id as u64, total);
+        }
+        Ok(totals)
+    }
+
     // Server operations
     pub fn servers(&self) -> Collection<Server> {
         self.mongo.collection("servers")
@@ -91,23 +564,394 @@ impl Database {
         Ok(server)
     }
 
-    pub async fn update_server(&self, server: &Server) -> BotResult<()> {
-        self.servers()
-            .replace_one(doc! { "id": server.id.to_string() }, server, None)
+    /// Records the panel server a `Creating` server was provisioned as and marks
+    /// it running. Only those fields are written, so changes made while the
+    /// panel was busy survive. Returns false if the server is gone or no longer
+    /// `Creating`.
+    pub async fn mark_server_provisioned(&self, server_id: &str, pterodactyl_id: i64, pterodactyl_identifier: &str) -> BotResult<bool> {
+        let result = self.servers()
+            .update_one(
+                doc! { "id": server_id, "status": "Creating" },
+                doc! { "$set": {
+                    "pterodactyl_id": pterodactyl_id,
+                    "pterodactyl_identifier": pterodactyl_identifier,
+                    "status": "Running",
+                    "updated_at": mongodb::bson::DateTime::now(),
+                } },
+                None,
+            )
             .await?;
-        Ok(())
+        Ok(result.matched_count == 1)
     }
 
-    pub async fn delete_server(&self, server_id: &str) -> BotResult<()> {
+    /// Deletes a server and returns it, or `None` if it was already gone.
+    pub async fn delete_server(&self, server_id: &str) -> BotResult<Option<Server>> {
         let uuid = uuid::Uuid::parse_str(server_id)
             .map_err(|_| BotError::InvalidInput("Invalid server ID".to_string()))?;
         
+        let server = self.servers()
+            .find_one_and_delete(doc! { "id": uuid.to_string() }, None)
+            .await?;
+        Ok(server)
+    }
+
+    /// Moves a server into `status`, returning it only if it was in one of `from`.
+    /// Used to make sure a status change that releases resources happens once.
+    pub async fn transition_server(&self, server_id: &str, from: &[ServerStatus], status: ServerStatus) -> BotResult<Option<Server>> {
+        self.transition_server_where(server_id, from, status, doc! {}).await
+    }
+
+    async fn transition_server_where(&self, server_id: &str, from: &[ServerStatus], status: ServerStatus, filter: Document) -> BotResult<Option<Server>> {
+        let from = from.iter()
+            .map(to_bson_value)
+            .collect::<BotResult<Vec<_>>>()?;
+
+        let mut filter = filter;
+        filter.insert("id", server_id);
+        filter.insert("status", doc! { "$in": from });
+
+        let server = self.servers()
+            .find_one_and_update(
+                filter,
+                doc! { "$set": { "status": to_bson_value(&status)?, "updated_at": mongodb::bson::DateTime::now() } },
+                None,
+            )
+            .await?;
+        Ok(server)
+    }
+
+    /// Active servers expiring before `cutoff` whose owner hasn't been warned yet.
+    pub async fn get_servers_to_warn(&self, cutoff: chrono::DateTime<chrono::Utc>) -> BotResult<Vec<Server>> {
+        let cursor = self.servers()
+            .find(doc! {
+                "status": { "$in": ["Running", "Stopped"] },
+                "expires_at": { "$gt": mongodb::bson::DateTime::now(), "$lte": timestamp::to_bson(&cutoff) },
+                "expiry_warned_at": null,
+            }, None)
+            .await?;
+        let servers: Vec<Server> = cursor.try_collect().await?;
+        Ok(servers)
+    }
+
+    /// Marks a server's expiry warning as sent. Returns false if it already was.
+    pub async fn claim_expiry_warning(&self, server_id: &str) -> BotResult<bool> {
+        let result = self.servers()
+            .update_one(
+                doc! { "id": server_id, "expiry_warned_at": null },
+                doc! { "$set": { "expiry_warned_at": mongodb::bson::DateTime::now() } },
+                None,
+            )
+            .await?;
+        Ok(result.modified_count == 1)
+    }
+
+    /// Servers in one of `statuses` that expired before `cutoff`.
+    pub async fn get_expired_servers(&self, statuses: &[ServerStatus], cutoff: chrono::DateTime<chrono::Utc>) -> BotResult<Vec<Server>> {
+        let statuses = statuses.iter()
+            .map(to_bson_value)
+            .collect::<BotResult<Vec<_>>>()?;
+
+        let cursor = self.servers()
+            .find(doc! {
+                "status": { "$in": statuses },
+                "expires_at": { "$lte": timestamp::to_bson(&cutoff) },
+            }, None)
+            .await?;
+        let servers: Vec<Server> = cursor.try_collect().await?;
+        Ok(servers)
+    }
+
+    /// Suspends a server, but only if it is active and still expired at `now`.
+    pub async fn claim_expired_server(&self, server_id: &str, now: chrono::DateTime<chrono::Utc>) -> BotResult<Option<Server>> {
+        self.transition_server_where(
+            server_id,
+            &[ServerStatus::Running, ServerStatus::Stopped],
+            ServerStatus::Suspended,
+            doc! { "expires_at": { "$lte": timestamp::to_bson(&now) } },
+        ).await
+    }
+
+    /// Marks an active server as suspended if it has been over-allocated since before `cutoff`.
+    pub async fn claim_over_allocated_server(&self, server_id: &str, cutoff: chrono::DateTime<chrono::Utc>) -> BotResult<Option<Server>> {
+        self.transition_server_where(
+            server_id,
+            &[ServerStatus::Running, ServerStatus::Stopped],
+            ServerStatus::Suspended,
+            doc! { "over_allocated": true, "over_allocated_at": { "$lte": timestamp::to_bson(&cutoff) } },
+        ).await
+    }
+
+    /// Marks a suspended server as running again, unless it is still expired or over-allocated.
+    pub async fn claim_unsuspension(&self, server_id: &str) -> BotResult<Option<Server>> {
+        self.transition_server_where(
+            server_id,
+            &[ServerStatus::Suspended],
+            ServerStatus::Running,
+            doc! { "expires_at": { "$gt": mongodb::bson::DateTime::now() }, "over_allocated": { "$ne": true } },
+        ).await
+    }
+
+    /// Marks a suspended server as deleted if it expired before `cutoff`.
+    pub async fn claim_server_deletion(&self, server_id: &str, cutoff: chrono::DateTime<chrono::Utc>) -> BotResult<Option<Server>> {
+        self.transition_server_where(
+            server_id,
+            &[ServerStatus::Suspended],
+            ServerStatus::Deleted,
+            doc! { "expires_at": { "$lte": timestamp::to_bson(&cutoff) } },
+        ).await
+    }
+
+    /// Moves a server's expiry from `previous` to `expires_at` and charges its owner
+    /// `cost` for it in one transaction, adding the cost to the coins prepaid for it.
+    /// Fails without changing anything if the expiry moved meanwhile or the owner
+    /// can't pay. Returns the new balance.
+    pub async fn renew_server(&self, server: &Server, previous: chrono::DateTime<chrono::Utc>, expires_at: chrono::DateTime<chrono::Utc>, cost: i64, change: CoinChange) -> BotResult<i64> {
+        let (server_id, discord_id) = (server.id.to_string(), server.discord_id);
+        self.with_transaction(move |db, session| {
+            let (server_id, change) = (server_id.clone(), change.clone());
+            Box::pin(async move {
+                let result = db.servers()
+                    .update_one_with_session(
+                        doc! { "id": &server_id, "expires_at": timestamp::to_bson(&previous) },
+                        doc! {
+                            "$set": {
+                                "expires_at": timestamp::to_bson(&expires_at),
+                                "expiry_warned_at": null,
+                                "updated_at": mongodb::bson::DateTime::now(),
+                            },
+                            "$inc": { "prepaid_coins": cost },
+                        },
+                        None,
+                        session,
+                    )
+                    .await?;
+                if result.modified_count != 1 {
+                    return Err(BotError::InvalidInput("The server was renewed at the same time, please try again".to_string()));
+                }
+
+                if cost > 0 {
+                    return db.change_coins_in(session, discord_id, -cost, Document::new(), &change).await;
+                }
+                let user = db.users()
+                    .find_one_with_session(doc! { "discord_id": discord_id as i64 }, None, session)
+                    .await?
+                    .ok_or(BotError::UserNotFound)?;
+                Ok(user.coins)
+            })
+        }).await
+    }
+
+    pub async fn set_auto_renew(&self, server_id: &str, enabled: bool) -> BotResult<()> {
         self.servers()
-            .delete_one(doc! { "id": uuid.to_string() }, None)
+            .update_one(
+                doc! { "id": server_id },
+                doc! { "$set": { "auto_renew": enabled, "updated_at": mongodb::bson::DateTime::now() } },
+                None,
+            )
             .await?;
         Ok(())
     }
 
+    pub async fn set_backup_schedule(&self, server_id: &str, interval_hours: Option<u32>) -> BotResult<()> {
+        self.servers()
+            .update_one(
+                doc! { "id": server_id },
+                doc! { "$set": { "backup_interval_hours": interval_hours, "updated_at": mongodb::bson::DateTime::now() } },
+                None,
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Active servers with automatic backups turned on.
+    pub async fn get_backup_scheduled_servers(&self) -> BotResult<Vec<Server>> {
+        let cursor = self.servers()
+            .find(doc! {
+                "status": { "$in": ["Running", "Stopped"] },
+                "backup_interval_hours": { "$ne": null },
+            }, None)
+            .await?;
+        let servers: Vec<Server> = cursor.try_collect().await?;
+        Ok(servers)
+    }
+
+    /// Records an automatic backup run, unless another run already did since `previous`.
+    pub async fn claim_auto_backup(&self, server_id: &str, previous: Option<chrono::DateTime<chrono::Utc>>, at: chrono::DateTime<chrono::Utc>) -> BotResult<bool> {
+        let result = self.servers()
+            .update_one(
+                doc! { "id": server_id, "last_auto_backup_at": previous.as_ref().map(timestamp::to_bson) },
+                doc! { "$set": { "last_auto_backup_at": timestamp::to_bson(&at), "updated_at": mongodb::bson::DateTime::now() } },
+                None,
+            )
+            .await?;
+        Ok(result.modified_count == 1)
+    }
+
+    /// Auto-renewing servers that expire before `cutoff`, including suspended ones.
+    pub async fn get_auto_renew_due(&self, cutoff: chrono::DateTime<chrono::Utc>) -> BotResult<Vec<Server>> {
+        let cursor = self.servers()
+            .find(doc! {
+                "auto_renew": true,
+                "status": { "$in": ["Running", "Stopped", "Suspended"] },
+                "expires_at": { "$lte": timestamp::to_bson(&cutoff) },
+            }, None)
+            .await?;
+        let servers: Vec<Server> = cursor.try_collect().await?;
+        Ok(servers)
+    }
+
+    pub async fn get_auto_renew_servers(&self) -> BotResult<Vec<Server>> {
+        let options = mongodb::options::FindOptions::builder()
+            .sort(doc! { "expires_at": 1 })
+            .build();
+
+        let cursor = self.servers()
+            .find(doc! { "auto_renew": true, "status": { "$nin": ["Deleted", "Failed"] } }, options)
+            .await?;
+        let servers: Vec<Server> = cursor.try_collect().await?;
+        Ok(servers)
+    }
+
+    /// Servers left in `Deleted` by a deletion that didn't finish.
+    pub async fn get_deleted_servers(&self) -> BotResult<Vec<Server>> {
+        let cursor = self.servers()
+            .find(doc! { "status": "Deleted" }, None)
+            .await?;
+        let servers: Vec<Server> = cursor.try_collect().await?;
+        Ok(servers)
+    }
+
+    /// Flags or unflags a server as over-allocated, stamping when it was flagged.
+    pub async fn set_server_over_allocated(&self, server_id: &str, over_allocated: bool) -> BotResult<()> {
+        let flagged_at = over_allocated.then(mongodb::bson::DateTime::now);
+        self.servers()
+            .update_one(
+                doc! { "id": server_id },
+                doc! { "$set": { "over_allocated": over_allocated, "over_allocated_at": flagged_at, "updated_at": mongodb::bson::DateTime::now() } },
+                None,
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Servers flagged as over-allocated, longest flagged first.
+    pub async fn get_over_allocated_servers(&self) -> BotResult<Vec<Server>> {
+        let options = mongodb::options::FindOptions::builder()
+            .sort(doc! { "over_allocated_at": 1 })
+            .build();
+
+        let cursor = self.servers()
+            .find(doc! { "over_allocated": true, "status": { "$nin": ["Deleted", "Failed"] } }, options)
+            .await?;
+        let servers: Vec<Server> = cursor.try_collect().await?;
+        Ok(servers)
+    }
+
+    /// Active servers flagged as over-allocated since before `cutoff`.
+    pub async fn get_over_allocation_due(&self, cutoff: chrono::DateTime<chrono::Utc>) -> BotResult<Vec<Server>> {
+        let cursor = self.servers()
+            .find(doc! {
+                "over_allocated": true,
+                "status": { "$in": ["Running", "Stopped"] },
+                "over_allocated_at": { "$lte": timestamp::to_bson(&cutoff) },
+            }, None)
+            .await?;
+        let servers: Vec<Server> = cursor.try_collect().await?;
+        Ok(servers)
+    }
+
+    pub async fn set_server_identifier(&self, server_id: &str, identifier: &str) -> BotResult<()> {
+        self.servers()
+            .update_one(
+                doc! { "id": server_id },
+                doc! { "$set": { "pterodactyl_identifier": identifier, "updated_at": mongodb::bson::DateTime::now() } },
+                None,
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Owners of servers flagged as over-allocated.
+    pub async fn get_over_allocated_owners(&self) -> BotResult<Vec<u64>> {
+        let owners = self.servers()
+            .distinct("discord_id", doc! { "over_allocated": true }, None)
+            .await?;
+        Ok(owners.iter().filter_map(|id| id.as_i64()).map(|id| id as u64).collect())
+    }
+
+    // Resource grant operations
+    pub fn grants(&self) -> Collection<ResourceGrant> {
+        self.mongo.collection("resource_grants")
+    }
+
+    /// A user's grants that haven't been revoked, soonest to expire first.
+    pub async fn get_active_grants(&self, discord_id: u64) -> BotResult<Vec<ResourceGrant>> {
+        let options = mongodb::options::FindOptions::builder()
+            .sort(doc! { "expires_at": 1 })
+            .build();
+
+        let cursor = self.grants()
+            .find(doc! { "discord_id": discord_id as i64, "revoked_at": null }, options)
+            .await?;
+        let grants: Vec<ResourceGrant> = cursor.try_collect().await?;
+        Ok(grants)
+    }
+
+    /// Grants that expired at or before `now` and still need revoking.
+    pub async fn get_expired_grants(&self, now: chrono::DateTime<chrono::Utc>) -> BotResult<Vec<ResourceGrant>> {
+        let cursor = self.grants()
+            .find(doc! { "revoked_at": null, "expires_at": { "$lte": timestamp::to_bson(&now) } }, None)
+            .await?;
+        let grants: Vec<ResourceGrant> = cursor.try_collect().await?;
+        Ok(grants)
+    }
+
+    /// Marks a grant revoked and takes its resources out of the owner's pool,
+    /// together. Returns false if it was already revoked.
+    pub async fn revoke_grant(&self, grant: &ResourceGrant) -> BotResult<bool> {
+        let grant = grant.clone();
+        self.with_transaction(move |db, session| {
+            let grant = grant.clone();
+            Box::pin(async move {
+                let now = chrono::Utc::now();
+                let result = db.grants()
+                    .update_one_with_session(
+                        doc! { "id": grant.id.to_string(), "revoked_at": null },
+                        doc! { "$set": { "revoked_at": timestamp::to_bson(&now) } },
+                        None,
+                        session,
+                    )
+                    .await?;
+                if result.matched_count == 0 {
+                    return Ok(false);
+                }
+
+                db.users()
+                    .update_one_with_session(
+                        doc! { "discord_id": grant.discord_id as i64 },
+                        doc! {
+                            "$inc": resource_delta("resources", &grant.resources, -1),
+                            "$set": { "updated_at": timestamp::to_bson(&now) },
+                        },
+                        None,
+                        session,
+                    )
+                    .await?;
+                Ok(true)
+            })
+        }).await
+    }
+
+    // Server event operations
+    pub fn server_events(&self) -> Collection<ServerEvent> {
+        self.mongo.collection("server_events")
+    }
+
+    pub async fn record_server_event(&self, event: &ServerEvent) -> BotResult<()> {
+        self.server_events().insert_one(event, None).await?;
+        Ok(())
+    }
+
     // Coupon operations
     pub fn coupons(&self) -> Collection<Coupon> {
         self.mongo.collection("coupons")
@@ -125,11 +969,46 @@ impl Database {
         Ok(())
     }
 
-    pub async fn update_coupon(&self, coupon: &Coupon) -> BotResult<()> {
-        self.coupons()
-            .replace_one(doc! { "code": &coupon.code }, coupon, None)
-            .await?;
-        Ok(())
+    /// Marks a coupon as used by a user and credits its coins and resources,
+    /// all in one transaction. Returns the coupon as it was before redemption.
+    pub async fn redeem_coupon(&self, code: &str, discord_id: u64) -> BotResult<Coupon> {
+        let code = code.to_string();
+        self.with_transaction(move |db, session| {
+            let code = code.clone();
+            Box::pin(async move { db.redeem_coupon_in(session, &code, discord_id).await })
+        }).await
+    }
+
+    async fn redeem_coupon_in(&self, session: &mut ClientSession, code: &str, discord_id: u64) -> BotResult<Coupon> {
+        // Same checks as `Coupon::can_be_used`, done by the update itself
+        let coupon = self.coupons()
+            .find_one_and_update_with_session(
+                doc! {
+                    "code": code,
+                    "used_by": { "$ne": discord_id as i64 },
+                    "$and": [
+                        { "$or": [{ "max_uses": null }, { "$expr": { "$lt": ["$used_count", "$max_uses"] } }] },
+                        { "$or": [{ "expires_at": null }, { "expires_at": { "$gt": mongodb::bson::DateTime::now() } }] },
+                    ],
+                },
+                doc! {
+                    "$addToSet": { "used_by": discord_id as i64 },
+                    "$inc": { "used_count": 1 },
+                },
+                None,
+                session,
+            )
+            .await?
+            .ok_or(BotError::CouponInvalid)?;
+
+        let inc = match &coupon.resources {
+            Some(resources) => resource_delta("resources", resources, 1),
+            None => Document::new(),
+        };
+        let change = CoinChange::new(TransactionReason::Coupon).reference(coupon.code.clone());
+        self.change_coins_in(session, discord_id, coupon.coins, inc, &change).await?;
+
+        Ok(coupon)
     }
 
     pub async fn delete_coupon(&self, code: &str) -> BotResult<()> {
@@ -149,31 +1028,235 @@ impl Database {
         Ok(())
     }
 
-    pub async fn get_order_by_session(&self, session_id: &str) -> BotResult<Option<Order>> {
+    pub async fn get_order(&self, order_id: &str) -> BotResult<Option<Order>> {
         let order = self.orders()
-            .find_one(doc! { "stripe_session_id": session_id }, None)
+            .find_one(doc! { "id": order_id }, None)
             .await?;
         Ok(order)
     }
 
-    pub async fn update_order(&self, order: &Order) -> BotResult<()> {
-        self.orders()
-            .replace_one(doc! { "id": order.id.to_string() }, order, None)
+    /// Orders matching `query`, newest first, with the total number of matches.
+    pub async fn find_orders(&self, query: &OrderQuery, page: u64, per_page: i64) -> BotResult<(Vec<Order>, u64)> {
+        let mut filter = Document::new();
+        if let Some(discord_id) = query.discord_id {
+            filter.insert("discord_id", discord_id as i64);
+        }
+        if let Some(status) = &query.status {
+            filter.insert("status", to_bson_value(status)?);
+        }
+        let mut created_at = Document::new();
+        if let Some(from) = &query.from {
+            created_at.insert("$gte", timestamp::to_bson(from));
+        }
+        if let Some(to) = &query.to {
+            created_at.insert("$lt", timestamp::to_bson(to));
+        }
+        if !created_at.is_empty() {
+            filter.insert("created_at", created_at);
+        }
+
+        let options = mongodb::options::FindOptions::builder()
+            .sort(doc! { "created_at": -1 })
+            .skip(page * per_page as u64)
+            .limit(per_page)
+            .build();
+
+        let total = self.orders().count_documents(filter.clone(), None).await?;
+        let cursor = self.orders().find(filter, options).await?;
+        let orders: Vec<Order> = cursor.try_collect().await?;
+        Ok((orders, total))
+    }
+
+    /// Pending orders created before `before`, oldest first.
+    pub async fn get_stale_orders(&self, before: chrono::DateTime<chrono::Utc>) -> BotResult<Vec<Order>> {
+        let options = mongodb::options::FindOptions::builder()
+            .sort(doc! { "created_at": 1 })
+            .build();
+        let cursor = self.orders()
+            .find(doc! { "status": "Pending", "created_at": { "$lt": timestamp::to_bson(&before) } }, options)
             .await?;
-        Ok(())
+        let orders: Vec<Order> = cursor.try_collect().await?;
+        Ok(orders)
     }
 
-    pub async fn renew_server(&self, server_id: &str, duration_days: u32, cost: i64) -> BotResult<()> {
-        let uuid = uuid::Uuid::parse_str(server_id)
-            .map_err(|_| BotError::InvalidInput("Invalid server ID".to_string()))?;
-        
-        let mut server = self.get_server(server_id).await?
-            .ok_or(BotError::ServerNotFound)?;
-        
-        // Extend the server expiration
-        server.expires_at = server.expires_at + chrono::Duration::days(duration_days as i64);
-        
-        self.update_server(&server).await?;
-        Ok(())
+    pub async fn get_order_by_checkout(&self, provider: &str, checkout_id: &str) -> BotResult<Option<Order>> {
+        let order = self.orders()
+            .find_one(doc! { "provider": provider, "checkout_id": checkout_id }, None)
+            .await?;
+        Ok(order)
+    }
+
+    pub async fn get_order_by_payment(&self, provider: &str, payment_id: &str) -> BotResult<Option<Order>> {
+        let order = self.orders()
+            .find_one(doc! { "provider": provider, "payment_id": payment_id }, None)
+            .await?;
+        Ok(order)
+    }
+
+    /// Saves a completed order and credits its coins, if it was still pending.
+    /// Returns false if it had already been completed or closed, so webhooks
+    /// delivered more than once only credit the coins once.
+    pub async fn complete_order(&self, order: &Order) -> BotResult<bool> {
+        let order = order.clone();
+        self.with_transaction(move |db, session| {
+            let order = order.clone();
+            Box::pin(async move {
+                let result = db.orders()
+                    .replace_one_with_session(
+                        doc! { "id": order.id.to_string(), "status": "Pending" },
+                        &order,
+                        None,
+                        session,
+                    )
+                    .await?;
+                if result.matched_count == 0 {
+                    return Ok(false);
+                }
+
+                let change = CoinChange::new(TransactionReason::Payment).reference(order.id.to_string());
+                db.change_coins_in(session, order.discord_id, order.coins, Document::new(), &change).await?;
+                Ok(true)
+            })
+        }).await
+    }
+
+    /// Moves an order from one of `from` to `to` without touching coins.
+    pub async fn transition_order(&self, order_id: &str, from: &[OrderStatus], to: OrderStatus) -> BotResult<bool> {
+        let from = from.iter()
+            .map(|status| to_bson_value(status))
+            .collect::<BotResult<Vec<_>>>()?;
+
+        let result = self.orders()
+            .update_one(
+                doc! { "id": order_id, "status": { "$in": from } },
+                doc! { "$set": { "status": to_bson_value(&to)? } },
+                None,
+            )
+            .await?;
+        Ok(result.modified_count > 0)
     }
+
+    /// Flags a cancelled order that was paid anyway as `Failed`, saving the payment
+    /// so it can be refunded. Returns false if the order was no longer cancelled.
+    pub async fn flag_paid_order(&self, order_id: &str, payment_id: &str) -> BotResult<bool> {
+        let result = self.orders()
+            .update_one(
+                doc! { "id": order_id, "status": "Cancelled" },
+                doc! { "$set": { "status": "Failed", "payment_id": payment_id } },
+                None,
+            )
+            .await?;
+        Ok(result.modified_count > 0)
+    }
+
+    /// Marks a completed order as `to` and takes its coins back, even if that
+    /// leaves the user with a negative balance. Returns false if the order
+    /// wasn't completed (never paid, or already reversed).
+    pub async fn reverse_order(&self, order: &Order, to: OrderStatus) -> BotResult<bool> {
+        let order = order.clone();
+        self.with_transaction(move |db, session| {
+            let (order, to) = (order.clone(), to.clone());
+            Box::pin(async move {
+                let result = db.orders()
+                    .update_one_with_session(
+                        doc! { "id": order.id.to_string(), "status": "Completed" },
+                        doc! { "$set": { "status": to_bson_value(&to)? } },
+                        None,
+                        session,
+                    )
+                    .await?;
+                if result.matched_count == 0 {
+                    return Ok(false);
+                }
+
+                let change = CoinChange::new(TransactionReason::Payment).reference(order.id.to_string());
+                db.apply_coins_in(session, order.discord_id, -order.coins, Document::new(), &change, true).await?;
+                Ok(true)
+            })
+        }).await
+    }
+}
+
+/// Rejects amounts that would move coins the opposite way to what the caller
+/// asked for, or write an empty ledger entry.
+fn require_positive(amount: i64) -> BotResult<()> {
+    if amount <= 0 {
+        return Err(BotError::InvalidInput(format!("Coin amounts must be positive, got {}", amount)));
+    }
+    Ok(())
+}
+
+/// Matches the user only if every dimension of `resources` fits in what's free.
+fn reserve_filter(discord_id: u64, resources: &Resources) -> Document {
+    let fits = |field: &str, amount: i64| doc! {
+        "$gte": [
+            { "$subtract": [format!("$resources.{}", field), { "$ifNull": [format!("$allocated.{}", field), 0] }] },
+            amount,
+        ]
+    };
+
+    doc! {
+        "discord_id": discord_id as i64,
+        "$expr": { "$and": [
+            fits("ram", resources.ram),
+            fits("cpu", resources.cpu),
+            fits("disk", resources.disk),
+            fits("databases", resources.databases),
+            fits("allocations", resources.allocations),
+            fits("backups", resources.backups),
+        ] },
+    }
+}
+
+fn reserve_update(resources: &Resources) -> Document {
+    doc! {
+        "$inc": resource_delta("allocated", resources, 1),
+        "$set": { "updated_at": mongodb::bson::DateTime::now() },
+    }
+}
+
+/// A user's servers on a plan that count towards `max_per_user`, ignoring
+/// ones that were deleted or never created.
+fn plan_servers_filter(discord_id: u64, plan: &str) -> Document {
+    doc! {
+        "discord_id": discord_id as i64,
+        "plan": plan,
+        "status": { "$nin": ["Deleted", "Failed"] },
+    }
+}
+
+/// `$inc` fields adding `sign` times `resources` to the embedded resources at `field`.
+fn resource_delta(field: &str, resources: &Resources, sign: i64) -> Document {
+    doc! {
+        format!("{}.ram", field): resources.ram * sign,
+        format!("{}.cpu", field): resources.cpu * sign,
+        format!("{}.disk", field): resources.disk * sign,
+        format!("{}.databases", field): resources.databases * sign,
+        format!("{}.allocations", field): resources.allocations * sign,
+        format!("{}.backups", field): resources.backups * sign,
+    }
+}
+
+fn return_updated() -> FindOneAndUpdateOptions {
+    FindOneAndUpdateOptions::builder()
+        .return_document(ReturnDocument::After)
+        .build()
+}
+
+fn is_transient_transaction_error(error: &BotError) -> bool {
+    matches!(error, BotError::Database(e) if e.contains_label(TRANSIENT_TRANSACTION_ERROR))
+}
+
+/// Commits a transaction, retrying while the outcome of the commit is unknown.
+async fn commit(session: &mut ClientSession) -> mongodb::error::Result<()> {
+    loop {
+        match session.commit_transaction().await {
+            Err(e) if e.contains_label(UNKNOWN_TRANSACTION_COMMIT_RESULT) => continue,
+            result => return result,
+        }
+    }
+}
+
+fn to_bson_value<T: serde::Serialize>(value: T) -> BotResult<mongodb::bson::Bson> {
+    mongodb::bson::to_bson(&value).map_err(|e| BotError::InvalidInput(e.to_string()))
 }
@@ -0,0 +1,106 @@
+use chrono::{DateTime, Utc};
+use tracing::{info, warn};
+use crate::errors::BotResult;
+use crate::models::{ResourceGrant, Resources, Server};
+use crate::services::Database;
+
+/// Revokes expired timed grants and flags servers their owner's pool no
+/// longer covers; `GrantSweeper` suspends ones that stay flagged.
+pub struct GrantService {
+    database: Database,
+}
+
+/// Servers whose over-allocation flag a check changed, and the resources
+/// their owner is short.
+#[derive(Debug, Clone)]
+pub struct AllocationChange {
+    pub discord_id: u64,
+    pub flagged: Vec<Server>,
+    pub cleared: Vec<Server>,
+    pub shortfall: Vec<&'static str>,
+}
+
+impl GrantService {
+    pub fn new(database: Database) -> Self {
+        Self { database }
+    }
+
+    /// Revokes every grant that expired by `now`, returning the ones this call revoked.
+    pub async fn revoke_expired(&self, now: DateTime<Utc>) -> BotResult<Vec<ResourceGrant>> {
+        let mut revoked = Vec::new();
+
+        for grant in self.database.get_expired_grants(now).await? {
+            match self.database.revoke_grant(&grant).await {
+                Ok(true) => {
+                    info!("Revoked expired grant {} ({}) from user {}", grant.id, grant.item_id, grant.discord_id);
+                    revoked.push(grant);
+                }
+                Ok(false) => {}
+                Err(e) => warn!("Failed to revoke grant {}: {}", grant.id, e),
+            }
+        }
+
+        Ok(revoked)
+    }
+
+    /// Re-checks which of a user's servers their pool covers.
+    ///
+    /// Servers are covered oldest first; the ones that don't fit in what's left
+    /// are flagged, and flags are cleared once the pool covers them again.
+    /// Returns the servers this call newly flagged or cleared, if any.
+    pub async fn check_allocation(&self, discord_id: u64) -> BotResult<Option<AllocationChange>> {
+        let user = match self.database.get_user(discord_id).await? {
+            Some(user) => user,
+            None => return Ok(None),
+        };
+
+        let mut servers: Vec<Server> = self.database.get_user_servers(discord_id).await?
+            .into_iter()
+            .filter(|server| server.holds_resources())
+            .collect();
+        servers.sort_by_key(|server| server.created_at);
+
+        let mut covered = Resources::default();
+        let mut flagged = Vec::new();
+        let mut cleared = Vec::new();
+        for mut server in servers {
+            let with_server = covered.add(&server.resources);
+            let fits = with_server.shortfall(&user.resources).is_empty();
+            if fits {
+                covered = with_server;
+            }
+
+            let server_id = server.id.to_string();
+            match (fits, server.over_allocated) {
+                (false, false) => {
+                    self.database.set_server_over_allocated(&server_id, true).await?;
+                    flagged.push(server);
+                }
+                (true, true) => {
+                    self.database.set_server_over_allocated(&server_id, false).await?;
+                    server.over_allocated = false;
+                    cleared.push(server);
+                }
+                // Flagged before flags were timestamped; start its notice period now
+                (false, true) if server.over_allocated_at.is_none() => {
+                    self.database.set_server_over_allocated(&server_id, true).await?;
+                }
+                _ => {}
+            }
+        }
+
+        if flagged.is_empty() && cleared.is_empty() {
+            return Ok(None);
+        }
+
+        if !flagged.is_empty() {
+            warn!("User {} is over-allocated, flagged {} server(s)", discord_id, flagged.len());
+        }
+        Ok(Some(AllocationChange {
+            discord_id,
+            flagged,
+            cleared,
+            shortfall: user.allocated.shortfall(&user.resources),
+        }))
+    }
+}
@@ -0,0 +1,263 @@
+use chrono::{DateTime, Utc};
+use tracing::{info, warn};
+use crate::errors::{BotError, BotResult};
+use crate::config::Plan;
+use crate::models::{CoinChange, Server, ServerEvent, ServerEventKind, ServerStatus, TransactionReason};
+use crate::services::{Database, JobType, PterodactylClient, QueueJob, QueueService};
+
+/// Suspends, unsuspends and deletes servers as they expire and get renewed.
+///
+/// Each change is claimed with an atomic status transition before anything is
+/// done on the panel, so running it twice (or after a restart) is harmless.
+pub struct LifecycleService {
+    database: Database,
+    pterodactyl: PterodactylClient,
+}
+
+/// Receipt for a server renewed from its owner's balance.
+#[derive(Debug, Clone)]
+pub struct Renewal {
+    pub cost: u64,
+    pub days: u32,
+    pub discount_percent: u32,
+    pub expires_at: DateTime<Utc>,
+    pub balance: i64,
+    pub unsuspended: bool,
+}
+
+impl LifecycleService {
+    pub fn new(database: Database, pterodactyl: PterodactylClient) -> Self {
+        Self {
+            database,
+            pterodactyl,
+        }
+    }
+
+    /// Claims the expiry warning for a server. Returns false if it was already sent.
+    pub async fn claim_warning(&self, server: &Server) -> BotResult<bool> {
+        if !self.database.claim_expiry_warning(&server.id.to_string()).await? {
+            return Ok(false);
+        }
+
+        let detail = format!("Expires at {}", server.expires_at.to_rfc3339());
+        self.record(server, ServerEventKind::ExpiryWarning, Some(detail)).await;
+        Ok(true)
+    }
+
+    /// Renews a server for `periods` plan periods, paid from its owner's balance.
+    ///
+    /// The expiry is moved with a compare-and-set in the same transaction that
+    /// takes the coins, so the charge and the extension go together. A server
+    /// that has already expired is renewed from now, so the time it spent
+    /// expired or suspended isn't billed.
+    pub async fn renew(&self, server: &Server, plan: &Plan, periods: u32) -> BotResult<Renewal> {
+        self.renew_as(server, plan, periods, ServerEventKind::Renewed).await
+    }
+
+    /// Auto-renews a server for one plan period. Returns `None` if the owner can't
+    /// afford it or the server was renewed meanwhile.
+    pub async fn auto_renew(&self, server: &Server, plan: &Plan) -> BotResult<Option<Renewal>> {
+        match self.renew_as(server, plan, 1, ServerEventKind::AutoRenewed).await {
+            Ok(renewal) => Ok(Some(renewal)),
+            Err(BotError::InsufficientCoins { .. }) | Err(BotError::InvalidInput(_)) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn renew_as(&self, server: &Server, plan: &Plan, periods: u32, kind: ServerEventKind) -> BotResult<Renewal> {
+        let server_id = server.id.to_string();
+        let days = plan.duration_days * periods;
+        let cost = plan.renewal_cost(periods);
+
+        let mut renewed = server.clone();
+        renewed.expires_at = renewed.expires_at.max(Utc::now());
+        renewed.extend_expiry(days as i64);
+
+        let change = CoinChange::new(TransactionReason::Renewal).reference(server_id);
+        let balance = self.database.renew_server(server, server.expires_at, renewed.expires_at, cost as i64, change).await?;
+
+        info!("Renewed server {} for {} days ({} coins)", server.id, days, cost);
+        self.record(server, kind, Some(format!("{} days for {} coins", days, cost))).await;
+
+        let unsuspended = if matches!(server.status, ServerStatus::Suspended) {
+            self.unsuspend(&renewed).await?
+        } else {
+            false
+        };
+
+        Ok(Renewal {
+            cost,
+            days,
+            discount_percent: plan.renewal_discount(periods),
+            expires_at: renewed.expires_at,
+            balance,
+            unsuspended,
+        })
+    }
+
+    /// Deletes a server on the user's request, refunding its unused time if `refund` is set.
+    /// Returns the coins refunded, or `None` if the server was already gone.
+    pub async fn delete(&self, server: &Server, plan: Option<&Plan>, refund: bool) -> BotResult<Option<u64>> {
+        let deleted = match self.database.delete_server(&server.id.to_string()).await? {
+            Some(deleted) => deleted,
+            None => return Ok(None),
+        };
+
+        if deleted.holds_resources() {
+            self.database.release_resources(deleted.discord_id, &deleted.resources).await?;
+        }
+
+        // Only time paid for in coins is refunded; the unpaid part of a server's life
+        // is always used up first, so capping at what was prepaid is enough
+        let refunded = match plan {
+            Some(plan) if refund && deleted.prepaid_coins > 0 && !matches!(deleted.status, ServerStatus::Suspended | ServerStatus::Failed | ServerStatus::Deleted) => {
+                plan.prorated_refund(deleted.expires_at - Utc::now()).min(deleted.prepaid_coins as u64)
+            }
+            _ => 0,
+        };
+        if refunded > 0 {
+            let change = CoinChange::new(TransactionReason::Refund).reference(deleted.id.to_string());
+            self.database.add_coins(deleted.discord_id, refunded as i64, change).await?;
+        }
+
+        let detail = if refunded > 0 { format!("Deleted by owner, refunded {} coins", refunded) } else { "Deleted by owner".to_string() };
+        self.record(&deleted, ServerEventKind::Deleted, Some(detail)).await;
+
+        if deleted.pterodactyl_id.is_some() {
+            let job = QueueJob::new(
+                JobType::DeleteServer,
+                deleted.discord_id,
+                serde_json::json!({
+                    "server_id": deleted.id.to_string(),
+                    "pterodactyl_id": deleted.pterodactyl_id,
+                })
+            );
+            QueueService::new(self.database.clone()).enqueue(job).await?;
+        }
+
+        Ok(Some(refunded))
+    }
+
+    /// Suspends an expired server. Returns false if it was renewed or already suspended.
+    pub async fn suspend_expired(&self, server: &Server, now: DateTime<Utc>) -> BotResult<bool> {
+        let server_id = server.id.to_string();
+        let previous = match self.database.claim_expired_server(&server_id, now).await? {
+            Some(previous) => previous,
+            None => return Ok(false),
+        };
+
+        if let Some(pterodactyl_id) = previous.pterodactyl_id {
+            if let Err(e) = self.pterodactyl.suspend_server(pterodactyl_id).await {
+                // Hand the server back so the next sweep tries again
+                self.database.transition_server(&server_id, &[ServerStatus::Suspended], previous.status.clone()).await?;
+                return Err(e);
+            }
+        }
+
+        info!("Suspended expired server {}", server.id);
+        self.record(server, ServerEventKind::Suspended, Some("Expired".to_string())).await;
+        Ok(true)
+    }
+
+    /// Suspends a server that has been over-allocated since before `cutoff`. Returns
+    /// false if its flag cleared meanwhile or it isn't active.
+    pub async fn suspend_over_allocated(&self, server: &Server, cutoff: DateTime<Utc>) -> BotResult<bool> {
+        let server_id = server.id.to_string();
+        let previous = match self.database.claim_over_allocated_server(&server_id, cutoff).await? {
+            Some(previous) => previous,
+            None => return Ok(false),
+        };
+
+        if let Some(pterodactyl_id) = previous.pterodactyl_id {
+            if let Err(e) = self.pterodactyl.suspend_server(pterodactyl_id).await {
+                self.database.transition_server(&server_id, &[ServerStatus::Suspended], previous.status.clone()).await?;
+                return Err(e);
+            }
+        }
+
+        info!("Suspended over-allocated server {}", server.id);
+        self.record(server, ServerEventKind::Suspended, Some("Over its owner's resources".to_string())).await;
+        Ok(true)
+    }
+
+    /// Unsuspends a server after it has been renewed past its expiry.
+    pub async fn unsuspend(&self, server: &Server) -> BotResult<bool> {
+        self.unsuspend_as(server, "Renewed").await
+    }
+
+    /// Unsuspends a server whose owner's pool covers it again.
+    pub async fn unsuspend_covered(&self, server: &Server) -> BotResult<bool> {
+        self.unsuspend_as(server, "Resources cover it again").await
+    }
+
+    /// Servers stay suspended while they are expired or over-allocated,
+    /// whichever of the two suspended them.
+    async fn unsuspend_as(&self, server: &Server, reason: &str) -> BotResult<bool> {
+        if server.is_expired() {
+            return Ok(false);
+        }
+
+        let server_id = server.id.to_string();
+        if self.database.claim_unsuspension(&server_id).await?.is_none() {
+            return Ok(false);
+        }
+
+        if let Some(pterodactyl_id) = server.pterodactyl_id {
+            if let Err(e) = self.pterodactyl.unsuspend_server(pterodactyl_id).await {
+                self.database.transition_server(&server_id, &[ServerStatus::Running], ServerStatus::Suspended).await?;
+                return Err(e);
+            }
+        }
+
+        info!("Unsuspended server {} ({})", server.id, reason);
+        self.record(server, ServerEventKind::Unsuspended, Some(reason.to_string())).await;
+        Ok(true)
+    }
+
+    /// Deletes a server whose grace period ended before `cutoff`.
+    pub async fn delete_expired(&self, server: &Server, cutoff: DateTime<Utc>) -> BotResult<bool> {
+        let claimed = match self.database.claim_server_deletion(&server.id.to_string(), cutoff).await? {
+            Some(claimed) => claimed,
+            None => return Ok(false),
+        };
+
+        if claimed.holds_resources() {
+            self.database.release_resources(claimed.discord_id, &claimed.resources).await?;
+        }
+        self.record(server, ServerEventKind::Deleted, Some("Grace period ended".to_string())).await;
+        self.finish_deletion(server).await?;
+        Ok(true)
+    }
+
+    /// Queues the panel deletion of a server in `Deleted` and drops its document.
+    ///
+    /// Also used to finish deletions interrupted by a restart; the panel treats
+    /// deleting a missing server as success, so queueing it twice is harmless.
+    pub async fn finish_deletion(&self, server: &Server) -> BotResult<()> {
+        let server_id = server.id.to_string();
+
+        if server.pterodactyl_id.is_some() {
+            let job = QueueJob::new(
+                JobType::DeleteServer,
+                server.discord_id,
+                serde_json::json!({
+                    "server_id": server_id,
+                    "pterodactyl_id": server.pterodactyl_id,
+                    // The sweeper tells the owner itself
+                    "quiet": true,
+                })
+            );
+            QueueService::new(self.database.clone()).enqueue(job).await?;
+        }
+
+        self.database.delete_server(&server_id).await?;
+        Ok(())
+    }
+
+    async fn record(&self, server: &Server, kind: ServerEventKind, detail: Option<String>) {
+        let event = ServerEvent::new(server, kind, detail);
+        if let Err(e) = self.database.record_server_event(&event).await {
+            warn!("Failed to record {:?} event for server {}: {}", event.kind, server.id, e);
+        }
+    }
+}
@@ -0,0 +1,172 @@
+use hyper::HeaderMap;
+use serde::Deserialize;
+use crate::config::{CoinPackage, Config};
+use crate::errors::{BotError, BotResult};
+use crate::services::{verify_hmac_sha256, Checkout, PaymentEvent, PaymentProvider, PaymentStatus};
+
+/// Payments settled outside the bot, e.g. bank transfers or crypto paid to an
+/// admin, and a network-free provider for testing the purchase flow.
+///
+/// Nothing is charged at checkout. Whoever confirms the payment posts a webhook
+/// to `/webhooks/manual` signed with `MANUAL_PAYMENT_SECRET`:
+///
+/// ```text
+/// X-Signature: hex(HMAC-SHA256(MANUAL_PAYMENT_SECRET, body))
+/// {"event": "paid", "checkout_id": "manual-<order id>"}
+/// ```
+///
+/// `event` is one of `paid`, `closed`, `refunded` or `disputed`; the last two
+/// take the `payment_id`, which defaults to the checkout ID for `paid`.
+/// Refunds issued from `/admin orders` only reverse the coins; returning the
+/// money is left to whoever took it.
+#[derive(Debug, Clone)]
+pub struct ManualPaymentProvider {
+    secret: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ManualEvent {
+    event: String,
+    checkout_id: Option<String>,
+    payment_id: Option<String>,
+}
+
+impl ManualPaymentProvider {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            secret: config.manual_payment_secret.clone(),
+        }
+    }
+}
+
+#[serenity::async_trait]
+impl PaymentProvider for ManualPaymentProvider {
+    fn name(&self) -> &'static str {
+        "manual"
+    }
+
+    async fn create_checkout(&self, _package: &CoinPackage, _discord_id: u64, reference: &str) -> BotResult<Checkout> {
+        Ok(Checkout {
+            id: format!("manual-{}", reference),
+            url: None,
+        })
+    }
+
+    async fn verify_webhook(&self, headers: &HeaderMap, payload: &[u8]) -> BotResult<PaymentEvent> {
+        if self.secret.is_empty() {
+            return Err(BotError::InvalidInput("MANUAL_PAYMENT_SECRET is not set".to_string()));
+        }
+
+        let signature = headers.get("X-Signature")
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default();
+        if !verify_hmac_sha256(&self.secret, payload, signature) {
+            return Err(BotError::PermissionDenied);
+        }
+
+        let event: ManualEvent = serde_json::from_slice(payload)
+            .map_err(|e| BotError::InvalidInput(format!("Failed to parse webhook event: {}", e)))?;
+        let missing = |field: &str| BotError::InvalidInput(format!("'{}' event needs a {}", event.event, field));
+
+        match event.event.as_str() {
+            "paid" => {
+                let checkout_id = event.checkout_id.clone().ok_or_else(|| missing("checkout_id"))?;
+                let payment_id = event.payment_id.clone().unwrap_or_else(|| checkout_id.clone());
+                Ok(PaymentEvent::Paid { checkout_id, payment_id: Some(payment_id) })
+            }
+            "closed" => Ok(PaymentEvent::Closed {
+                checkout_id: event.checkout_id.clone().ok_or_else(|| missing("checkout_id"))?,
+            }),
+            "refunded" => Ok(PaymentEvent::Refunded {
+                payment_id: event.payment_id.clone().ok_or_else(|| missing("payment_id"))?,
+            }),
+            "disputed" => Ok(PaymentEvent::Disputed {
+                payment_id: event.payment_id.clone().ok_or_else(|| missing("payment_id"))?,
+            }),
+            _ => Ok(PaymentEvent::Ignored),
+        }
+    }
+
+    async fn get_payment(&self, _checkout_id: &str) -> BotResult<PaymentStatus> {
+        // There's no processor to ask; these orders only move through webhooks
+        Ok(PaymentStatus::Pending)
+    }
+
+    async fn refund(&self, _payment_id: &str) -> BotResult<()> {
+        // The money goes back the way it came in, outside the bot
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{self, manual_signature};
+
+    fn provider() -> ManualPaymentProvider {
+        ManualPaymentProvider::new(&test_support::config())
+    }
+
+    async fn verify(payload: &str) -> BotResult<PaymentEvent> {
+        provider().verify_webhook(&manual_signature(payload), payload.as_bytes()).await
+    }
+
+    #[tokio::test]
+    async fn paid_defaults_payment_to_checkout() {
+        match verify(r#"{"event":"paid","checkout_id":"manual-1"}"#).await.unwrap() {
+            PaymentEvent::Paid { checkout_id, payment_id } => {
+                assert_eq!(checkout_id, "manual-1");
+                assert_eq!(payment_id.as_deref(), Some("manual-1"));
+            }
+            other => panic!("expected a paid event, got {:?}", other),
+        }
+
+        match verify(r#"{"event":"paid","checkout_id":"manual-1","payment_id":"bank-77"}"#).await.unwrap() {
+            PaymentEvent::Paid { payment_id, .. } => assert_eq!(payment_id.as_deref(), Some("bank-77")),
+            other => panic!("expected a paid event, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn events_map_to_payment_events() {
+        let event = verify(r#"{"event":"closed","checkout_id":"manual-1"}"#).await.unwrap();
+        assert!(matches!(event, PaymentEvent::Closed { checkout_id } if checkout_id == "manual-1"));
+
+        let event = verify(r#"{"event":"refunded","payment_id":"manual-1"}"#).await.unwrap();
+        assert!(matches!(event, PaymentEvent::Refunded { payment_id } if payment_id == "manual-1"));
+
+        let event = verify(r#"{"event":"disputed","payment_id":"manual-1"}"#).await.unwrap();
+        assert!(matches!(event, PaymentEvent::Disputed { payment_id } if payment_id == "manual-1"));
+
+        let event = verify(r#"{"event":"noted"}"#).await.unwrap();
+        assert!(matches!(event, PaymentEvent::Ignored));
+    }
+
+    #[tokio::test]
+    async fn missing_fields_are_rejected() {
+        assert!(verify(r#"{"event":"paid"}"#).await.is_err());
+        assert!(verify(r#"{"event":"refunded","checkout_id":"manual-1"}"#).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn bad_signatures_are_rejected() {
+        let payload = r#"{"event":"paid","checkout_id":"manual-1"}"#;
+        let tampered = r#"{"event":"paid","checkout_id":"manual-2"}"#;
+
+        let error = provider().verify_webhook(&manual_signature(payload), tampered.as_bytes()).await.unwrap_err();
+        assert!(matches!(error, BotError::PermissionDenied));
+
+        let error = provider().verify_webhook(&HeaderMap::new(), payload.as_bytes()).await.unwrap_err();
+        assert!(matches!(error, BotError::PermissionDenied));
+    }
+
+    #[tokio::test]
+    async fn unset_secret_rejects_everything() {
+        let mut config = test_support::config();
+        config.manual_payment_secret = String::new();
+        let payload = r#"{"event":"paid","checkout_id":"manual-1"}"#;
+
+        let result = ManualPaymentProvider::new(&config).verify_webhook(&manual_signature(payload), payload.as_bytes()).await;
+        assert!(result.is_err());
+    }
+}
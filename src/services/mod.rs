@@ -1,9 +1,33 @@
 pub mod db;
 pub mod pterodactyl;
 pub mod stripe;
+pub mod paypal;
+pub mod manual_payment;
+pub mod payments;
+pub mod orders;
 pub mod queue;
+pub mod accounts;
+pub mod nodes;
+pub mod lifecycle;
+pub mod grants;
+pub mod power;
+pub mod console;
+pub mod backups;
+pub mod databases;
 
 pub use db::*;
 pub use pterodactyl::*;
 pub use stripe::*;
+pub use paypal::*;
+pub use manual_payment::*;
+pub use payments::*;
+pub use orders::*;
 pub use queue::*;
+pub use accounts::*;
+pub use nodes::*;
+pub use lifecycle::*;
+pub use grants::*;
+pub use power::*;
+pub use console::*;
+pub use backups::*;
+pub use databases::*;
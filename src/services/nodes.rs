@@ -0,0 +1,77 @@
+use redis::AsyncCommands;
+use tracing::info;
+use crate::config::{Config, NodeSelectionPolicy};
+use crate::errors::{BotError, BotResult};
+use crate::models::Server;
+use crate::services::{Database, PterodactylClient, PterodactylNode};
+
+const ROUND_ROBIN_KEY: &str = "nodes:round_robin";
+
+/// Where a new server should be placed on the panel.
+#[derive(Debug, Clone)]
+pub struct Placement {
+    pub node_id: i64,
+    pub allocation_id: i64,
+}
+
+/// Picks a node and a free allocation for new servers.
+pub struct NodeSelector {
+    database: Database,
+    pterodactyl: PterodactylClient,
+    config: Config,
+}
+
+impl NodeSelector {
+    pub fn new(database: Database, pterodactyl: PterodactylClient, config: Config) -> Self {
+        Self {
+            database,
+            pterodactyl,
+            config,
+        }
+    }
+
+    pub async fn select(&self, server: &Server) -> BotResult<Placement> {
+        let pinned = self.config.node_pins.get(&server.plan).copied();
+
+        let mut candidates: Vec<PterodactylNode> = self.pterodactyl.list_nodes().await?
+            .into_iter()
+            .filter(|node| !node.maintenance_mode)
+            .filter(|node| match pinned {
+                Some(node_id) => node.id == node_id,
+                None => self.config.node_locations.is_empty() || self.config.node_locations.contains(&node.location_id),
+            })
+            .filter(|node| node.free_memory().map_or(true, |free| free >= server.resources.ram))
+            .filter(|node| node.free_disk().map_or(true, |free| free >= server.resources.disk))
+            .collect();
+
+        match self.config.node_selection {
+            NodeSelectionPolicy::LeastLoaded => {
+                candidates.sort_by(|a, b| a.memory_usage().total_cmp(&b.memory_usage()));
+            }
+            NodeSelectionPolicy::RoundRobin if !candidates.is_empty() => {
+                candidates.sort_by_key(|node| node.id);
+                let mut conn = self.database.get_redis_connection().await?;
+                let counter: u64 = conn.incr(ROUND_ROBIN_KEY, 1).await?;
+                let start = (counter % candidates.len() as u64) as usize;
+                candidates.rotate_left(start);
+            }
+            NodeSelectionPolicy::RoundRobin => {}
+        }
+
+        // Nodes with spare memory may still have run out of ports
+        for node in candidates {
+            if let Some(allocation) = self.pterodactyl.find_free_allocation(node.id).await? {
+                info!("Placing server {} on node {} ({}:{})", server.id, node.name, allocation.ip, allocation.port);
+                return Ok(Placement {
+                    node_id: node.id,
+                    allocation_id: allocation.id,
+                });
+            }
+        }
+
+        Err(BotError::NoNodeCapacity {
+            ram: server.resources.ram,
+            disk: server.resources.disk,
+        })
+    }
+}
@@ -0,0 +1,373 @@
+use chrono::Utc;
+use tracing::{info, warn};
+use crate::config::Config;
+use crate::errors::{BotError, BotResult};
+use crate::models::{Order, OrderStatus};
+use crate::services::{Database, PaymentProviders, PaymentStatus};
+
+/// Completes, cancels, refunds and reconciles coin orders.
+///
+/// Every change goes through a conditional status transition, so webhooks,
+/// the reconciler and admins acting on the same order at once can't credit or
+/// take back its coins twice.
+#[derive(Clone)]
+pub struct OrderService {
+    database: Database,
+    providers: PaymentProviders,
+}
+
+/// What happened to the orders a reconcile run looked at.
+#[derive(Debug, Clone, Default)]
+pub struct OrderReconcileReport {
+    pub completed: Vec<Order>,
+    pub cancelled: usize,
+    pub pending: usize,
+    pub failed: usize,
+}
+
+impl OrderService {
+    pub fn new(database: Database, config: &Config) -> Self {
+        Self {
+            database,
+            providers: PaymentProviders::new(config),
+        }
+    }
+
+    pub fn providers(&self) -> &PaymentProviders {
+        &self.providers
+    }
+
+    /// Completes a pending order and credits its coins. Returns false if the
+    /// order was no longer pending.
+    pub async fn complete(&self, order: &Order, payment_id: Option<String>) -> BotResult<bool> {
+        if let (OrderStatus::Cancelled, Some(payment_id)) = (&order.status, &payment_id) {
+            self.refund_late_payment(order, payment_id).await?;
+            return Ok(false);
+        }
+        if !matches!(order.status, OrderStatus::Pending) {
+            return Ok(false);
+        }
+
+        let mut order = order.clone();
+        order.complete();
+        order.payment_id = payment_id.or(order.payment_id.take());
+
+        let completed = self.database.complete_order(&order).await?;
+        if completed {
+            info!("Completed order {} for {} coins", order.id, order.coins);
+        }
+        Ok(completed)
+    }
+
+    /// Captures an approved payment for a pending order and completes it.
+    /// Returns false if the order wasn't pending or the capture is still
+    /// settling; the provider reports it as paid once it has.
+    pub async fn capture(&self, order: &Order) -> BotResult<bool> {
+        if !matches!(order.status, OrderStatus::Pending) {
+            return Ok(false);
+        }
+        let provider = self.providers.get(&order.provider)
+            .ok_or_else(|| BotError::InvalidInput(format!("Payment provider '{}' is not enabled", order.provider)))?;
+
+        match provider.capture(&order.checkout_id).await? {
+            PaymentStatus::Paid { payment_id } => self.complete(order, payment_id).await,
+            PaymentStatus::Closed => {
+                self.cancel(order).await?;
+                Ok(false)
+            }
+            PaymentStatus::Pending | PaymentStatus::Approved => Ok(false),
+        }
+    }
+
+    /// Refunds a payment that arrived after its order was cancelled instead of
+    /// crediting it. The order is flagged `Failed` with the payment saved first and
+    /// goes back to `Cancelled` once the refund went through; if it didn't, the
+    /// order stays `Failed` so admins find it in `/admin orders`.
+    async fn refund_late_payment(&self, order: &Order, payment_id: &str) -> BotResult<()> {
+        let order_id = order.id.to_string();
+        if !self.database.flag_paid_order(&order_id, payment_id).await? {
+            return Ok(());
+        }
+        warn!("Order {} was paid after being cancelled, refunding payment {}", order.id, payment_id);
+
+        let refunded = match self.providers.get(&order.provider) {
+            Some(provider) => provider.refund(payment_id).await,
+            None => Err(BotError::InvalidInput(format!("Payment provider '{}' is not enabled", order.provider))),
+        };
+        match refunded {
+            Ok(()) => {
+                self.database.transition_order(&order_id, &[OrderStatus::Failed], OrderStatus::Cancelled).await?;
+                info!("Refunded payment {} for cancelled order {}", payment_id, order.id);
+            }
+            Err(e) => warn!("Failed to refund payment {} for cancelled order {}, it needs to be refunded by hand: {}", payment_id, order.id, e),
+        }
+        Ok(())
+    }
+
+    /// Cancels a pending order without touching coins. Returns false if the
+    /// order was no longer pending.
+    pub async fn cancel(&self, order: &Order) -> BotResult<bool> {
+        let cancelled = self.database
+            .transition_order(&order.id.to_string(), &[OrderStatus::Pending], OrderStatus::Cancelled)
+            .await?;
+        if cancelled {
+            info!("Cancelled order {}", order.id);
+        }
+        Ok(cancelled)
+    }
+
+    /// Refunds a completed order through its provider and takes its coins back.
+    ///
+    /// The provider's refund webhook finds the order already reversed, so the
+    /// coins are only taken once. Returns false if something else reversed the
+    /// order between the refund and now.
+    pub async fn refund(&self, order: &Order) -> BotResult<bool> {
+        if !matches!(order.status, OrderStatus::Completed) {
+            return Err(BotError::InvalidInput("Only completed orders can be refunded".to_string()));
+        }
+        let payment_id = order.payment_id.as_deref()
+            .ok_or_else(|| BotError::InvalidInput("This order has no payment to refund".to_string()))?;
+        let provider = self.providers.get(&order.provider)
+            .ok_or_else(|| BotError::InvalidInput(format!("Payment provider '{}' is not enabled", order.provider)))?;
+
+        provider.refund(payment_id).await?;
+
+        let reversed = self.database.reverse_order(order, OrderStatus::Cancelled).await?;
+        if reversed {
+            info!("Refunded order {}, took back {} coins", order.id, order.coins);
+        }
+        Ok(reversed)
+    }
+
+    /// Asks each order's provider about orders that have been pending for
+    /// longer than `older_than`, in case their webhook never arrived.
+    pub async fn reconcile(&self, older_than: chrono::Duration) -> BotResult<OrderReconcileReport> {
+        let mut report = OrderReconcileReport::default();
+
+        for order in self.database.get_stale_orders(Utc::now() - older_than).await? {
+            let provider = match self.providers.get(&order.provider) {
+                Some(provider) => provider,
+                None => {
+                    warn!("Order {} uses disabled provider '{}', skipping", order.id, order.provider);
+                    report.failed += 1;
+                    continue;
+                }
+            };
+
+            let status = match provider.get_payment(&order.checkout_id).await {
+                Ok(status) => status,
+                Err(e) => {
+                    warn!("Failed to look up order {} with {}: {}", order.id, order.provider, e);
+                    report.failed += 1;
+                    continue;
+                }
+            };
+
+            match status {
+                PaymentStatus::Paid { payment_id } => {
+                    if self.complete(&order, payment_id).await? {
+                        report.completed.push(order);
+                    }
+                }
+                // The approval webhook never arrived, so capture it here
+                PaymentStatus::Approved => {
+                    if self.capture(&order).await? {
+                        report.completed.push(order);
+                    } else {
+                        report.pending += 1;
+                    }
+                }
+                PaymentStatus::Closed => {
+                    if self.cancel(&order).await? {
+                        report.cancelled += 1;
+                    }
+                }
+                PaymentStatus::Pending => report.pending += 1,
+            }
+        }
+
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::CoinPackage;
+    use crate::models::{CoinChange, TransactionReason};
+    use crate::services::PaymentEvent;
+    use crate::test_support::{self, manual_signature, Fixture, StubServer};
+
+    /// A test user with an `OrderService` over the manual provider.
+    struct OrderFixture {
+        fixture: Fixture,
+        orders: OrderService,
+    }
+
+    impl std::ops::Deref for OrderFixture {
+        type Target = Fixture;
+
+        fn deref(&self) -> &Fixture {
+            &self.fixture
+        }
+    }
+
+    impl OrderFixture {
+        async fn new(config: Config) -> Self {
+            let fixture = Fixture::new(config).await;
+            Self {
+                orders: OrderService::new(fixture.database.clone(), &fixture.config),
+                fixture,
+            }
+        }
+
+        /// Places an order the way `/coins buy` does.
+        async fn place_order(&self) -> Order {
+            let package = CoinPackage {
+                id: "small".to_string(),
+                name: "500 Coins".to_string(),
+                coins: 500,
+                price_cents: 499,
+                enabled: true,
+            };
+            let provider = self.orders.providers().get("manual").unwrap();
+            let mut order = Order::new(self.discord_id, provider.name().to_string(), package.id.clone(), package.price_cents, package.coins);
+            let checkout = provider.create_checkout(&package, self.discord_id, &order.id.to_string()).await.unwrap();
+            order.checkout_id = checkout.id;
+            self.database.create_order(&order).await.unwrap();
+            order
+        }
+
+        async fn webhook(&self, payload: serde_json::Value) -> PaymentEvent {
+            let payload = payload.to_string();
+            self.orders.providers().get("manual").unwrap()
+                .verify_webhook(&manual_signature(&payload), payload.as_bytes())
+                .await
+                .unwrap()
+        }
+
+        async fn order(&self, order: &Order) -> Order {
+            self.database.get_order(&order.id.to_string()).await.unwrap().unwrap()
+        }
+
+        async fn coins(&self) -> i64 {
+            self.user().await.coins
+        }
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_MONGODB_URI/TEST_REDIS_URI"]
+    async fn paid_webhook_completes_order_once() {
+        let fixture = OrderFixture::new(test_support::config()).await;
+        let order = fixture.place_order().await;
+
+        let (checkout_id, payment_id) = match fixture.webhook(serde_json::json!({ "event": "paid", "checkout_id": order.checkout_id })).await {
+            PaymentEvent::Paid { checkout_id, payment_id } => (checkout_id, payment_id),
+            other => panic!("expected a paid event, got {:?}", other),
+        };
+        let order = fixture.database.get_order_by_checkout("manual", &checkout_id).await.unwrap().unwrap();
+
+        assert!(fixture.orders.complete(&order, payment_id.clone()).await.unwrap());
+        assert_eq!(fixture.coins().await, 500);
+        let completed = fixture.order(&order).await;
+        assert!(matches!(completed.status, OrderStatus::Completed));
+        assert_eq!(completed.payment_id, payment_id);
+
+        // A redelivered webhook finds the order completed and credits nothing
+        assert!(!fixture.orders.complete(&completed, payment_id).await.unwrap());
+        assert_eq!(fixture.coins().await, 500);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_MONGODB_URI/TEST_REDIS_URI"]
+    async fn refund_reverses_order_once() {
+        let fixture = OrderFixture::new(test_support::config()).await;
+        let order = fixture.place_order().await;
+        fixture.orders.complete(&order, Some(order.checkout_id.clone())).await.unwrap();
+        let order = fixture.order(&order).await;
+
+        assert!(fixture.orders.refund(&order).await.unwrap());
+        assert_eq!(fixture.coins().await, 0);
+        assert!(matches!(fixture.order(&order).await.status, OrderStatus::Cancelled));
+
+        // The refund webhook that follows finds the order already reversed
+        let payment_id = match fixture.webhook(serde_json::json!({ "event": "refunded", "payment_id": order.checkout_id })).await {
+            PaymentEvent::Refunded { payment_id } => payment_id,
+            other => panic!("expected a refunded event, got {:?}", other),
+        };
+        let refunded = fixture.database.get_order_by_payment("manual", &payment_id).await.unwrap().unwrap();
+        assert!(!fixture.database.reverse_order(&refunded, OrderStatus::Cancelled).await.unwrap());
+        assert_eq!(fixture.coins().await, 0);
+
+        let error = fixture.orders.refund(&fixture.order(&order).await).await.unwrap_err();
+        assert!(matches!(error, BotError::InvalidInput(_)));
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_MONGODB_URI/TEST_REDIS_URI"]
+    async fn dispute_takes_back_spent_coins() {
+        let fixture = OrderFixture::new(test_support::config()).await;
+        let order = fixture.place_order().await;
+        fixture.orders.complete(&order, Some(order.checkout_id.clone())).await.unwrap();
+        fixture.database.deduct_coins(fixture.discord_id, 400, CoinChange::new(TransactionReason::Store)).await.unwrap();
+
+        let payment_id = match fixture.webhook(serde_json::json!({ "event": "disputed", "payment_id": order.checkout_id })).await {
+            PaymentEvent::Disputed { payment_id } => payment_id,
+            other => panic!("expected a disputed event, got {:?}", other),
+        };
+        let disputed = fixture.database.get_order_by_payment("manual", &payment_id).await.unwrap().unwrap();
+
+        assert!(fixture.database.reverse_order(&disputed, OrderStatus::Failed).await.unwrap());
+        assert_eq!(fixture.coins().await, -400);
+        assert!(matches!(fixture.order(&order).await.status, OrderStatus::Failed));
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_MONGODB_URI/TEST_REDIS_URI"]
+    async fn late_payment_is_refunded_not_credited() {
+        let fixture = OrderFixture::new(test_support::config()).await;
+        let order = fixture.place_order().await;
+        assert!(fixture.orders.cancel(&order).await.unwrap());
+        let cancelled = fixture.order(&order).await;
+
+        assert!(!fixture.orders.complete(&cancelled, Some("bank-77".to_string())).await.unwrap());
+        assert_eq!(fixture.coins().await, 0);
+
+        // The manual provider's refund always succeeds, so the order ends up cancelled again
+        let order = fixture.order(&order).await;
+        assert!(matches!(order.status, OrderStatus::Cancelled));
+        assert_eq!(order.payment_id.as_deref(), Some("bank-77"));
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_MONGODB_URI/TEST_REDIS_URI"]
+    async fn approval_is_captured_only_for_pending_orders() {
+        let paypal = StubServer::start(|request| match request.path.as_str() {
+            "/v1/oauth2/token" => (200, serde_json::json!({ "access_token": "A21AA-test", "expires_in": 32400 })),
+            _ => (201, serde_json::json!({
+                "id": "PAYPAL-ORDER",
+                "status": "COMPLETED",
+                "purchase_units": [{ "payments": { "captures": [{ "id": "CAPTURE-1", "status": "COMPLETED" }] } }],
+            })),
+        });
+        let mut config = test_support::config();
+        config.payment_providers = vec!["paypal".to_string()];
+        config.paypal_api_url = paypal.url.clone();
+        let fixture = OrderFixture::new(config).await;
+
+        let mut cancelled = Order::new(fixture.discord_id, "paypal".to_string(), "small".to_string(), 499, 500);
+        cancelled.checkout_id = "PAYPAL-CANCELLED".to_string();
+        fixture.database.create_order(&cancelled).await.unwrap();
+        fixture.orders.cancel(&cancelled).await.unwrap();
+        assert!(!fixture.orders.capture(&fixture.order(&cancelled).await).await.unwrap());
+        assert!(paypal.requests_to("/v2/").is_empty());
+
+        let mut order = Order::new(fixture.discord_id, "paypal".to_string(), "small".to_string(), 499, 500);
+        order.checkout_id = "PAYPAL-ORDER".to_string();
+        fixture.database.create_order(&order).await.unwrap();
+        assert!(fixture.orders.capture(&order).await.unwrap());
+        assert_eq!(paypal.requests_to("/v2/checkout/orders/PAYPAL-ORDER/capture").len(), 1);
+        assert_eq!(fixture.coins().await, 500);
+        assert_eq!(fixture.order(&order).await.payment_id.as_deref(), Some("CAPTURE-1"));
+    }
+}
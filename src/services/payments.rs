@@ -0,0 +1,130 @@
+use std::sync::Arc;
+use hmac::{Hmac, Mac};
+use hyper::HeaderMap;
+use sha2::Sha256;
+use tracing::warn;
+use crate::config::{CoinPackage, Config};
+use crate::errors::{BotError, BotResult};
+use crate::services::{ManualPaymentProvider, PayPalProvider, StripeService};
+
+/// A payment processor coins can be bought through.
+///
+/// Providers only talk to the processor; orders, coins and notifications are
+/// handled the same way for all of them by the caller.
+#[serenity::async_trait]
+pub trait PaymentProvider: Send + Sync {
+    /// Name used in `PAYMENT_PROVIDERS`, on orders and in the webhook path.
+    fn name(&self) -> &'static str;
+
+    /// Starts a checkout for `package`. `reference` is the order ID, used for
+    /// idempotency so a retried request doesn't start a second checkout.
+    async fn create_checkout(&self, package: &CoinPackage, discord_id: u64, reference: &str) -> BotResult<Checkout>;
+
+    /// Authenticates a webhook delivery and translates it into a `PaymentEvent`.
+    async fn verify_webhook(&self, headers: &HeaderMap, payload: &[u8]) -> BotResult<PaymentEvent>;
+
+    /// Asks the processor for the current state of a checkout.
+    async fn get_payment(&self, checkout_id: &str) -> BotResult<PaymentStatus>;
+
+    /// Collects the money for a checkout the buyer approved. Only providers
+    /// that report `Approved` need this; the rest charge at checkout.
+    async fn capture(&self, checkout_id: &str) -> BotResult<PaymentStatus> {
+        Err(BotError::InvalidInput(format!("{} checkout {} can't be captured", self.name(), checkout_id)))
+    }
+
+    /// Refunds a payment in full. Refunding one that's already refunded is not an error.
+    async fn refund(&self, payment_id: &str) -> BotResult<()>;
+}
+
+#[derive(Debug, Clone)]
+pub struct Checkout {
+    pub id: String,
+    /// Page the buyer pays on; `None` for providers settled outside the bot.
+    pub url: Option<String>,
+}
+
+/// What a webhook told us about a payment.
+#[derive(Debug, Clone)]
+pub enum PaymentEvent {
+    /// The checkout was paid; `payment_id` identifies the payment for later refunds.
+    Paid { checkout_id: String, payment_id: Option<String> },
+    /// The buyer approved the checkout, but the money is only collected once
+    /// it's captured.
+    Approved { checkout_id: String },
+    /// The checkout expired or failed without being paid.
+    Closed { checkout_id: String },
+    /// The payment was refunded in full.
+    Refunded { payment_id: String },
+    /// The buyer opened a dispute or chargeback.
+    Disputed { payment_id: String },
+    /// Anything the bot doesn't act on.
+    Ignored,
+}
+
+#[derive(Debug, Clone)]
+pub enum PaymentStatus {
+    Pending,
+    /// Approved by the buyer and waiting to be captured.
+    Approved,
+    Paid { payment_id: Option<String> },
+    Closed,
+}
+
+/// The providers enabled with `PAYMENT_PROVIDERS`, the first being the default.
+#[derive(Clone)]
+pub struct PaymentProviders {
+    providers: Vec<Arc<dyn PaymentProvider>>,
+}
+
+impl PaymentProviders {
+    pub fn new(config: &Config) -> Self {
+        let providers = config.payment_providers.iter()
+            .filter_map(|name| -> Option<Arc<dyn PaymentProvider>> {
+                match name.as_str() {
+                    "stripe" => Some(Arc::new(StripeService::new(config))),
+                    "paypal" => Some(Arc::new(PayPalProvider::new(config))),
+                    "manual" => Some(Arc::new(ManualPaymentProvider::new(config))),
+                    other => {
+                        warn!("Unknown payment provider '{}', ignoring it", other);
+                        None
+                    }
+                }
+            })
+            .collect();
+
+        Self { providers }
+    }
+
+    pub fn get(&self, name: &str) -> Option<Arc<dyn PaymentProvider>> {
+        self.providers.iter().find(|provider| provider.name() == name).cloned()
+    }
+
+    pub fn default_provider(&self) -> BotResult<Arc<dyn PaymentProvider>> {
+        self.providers.first()
+            .cloned()
+            .ok_or_else(|| BotError::InvalidInput("No payment provider is enabled".to_string()))
+    }
+
+    pub fn names(&self) -> Vec<&'static str> {
+        self.providers.iter().map(|provider| provider.name()).collect()
+    }
+}
+
+/// Checks a hex-encoded HMAC-SHA256 `signature` of `message`, in constant time.
+pub fn verify_hmac_sha256(secret: &str, message: &[u8], signature: &str) -> bool {
+    let signature = match hex::decode(signature) {
+        Ok(signature) => signature,
+        Err(_) => return false,
+    };
+    let mut mac = match Hmac::<Sha256>::new_from_slice(secret.as_bytes()) {
+        Ok(mac) => mac,
+        Err(_) => return false,
+    };
+    mac.update(message);
+    mac.verify_slice(&signature).is_ok()
+}
+
+/// Formats cents as a decimal amount, e.g. `499` as `4.99`.
+pub fn format_cents(cents: i64) -> String {
+    format!("{}.{:02}", cents / 100, cents % 100)
+}
@@ -0,0 +1,533 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use hyper::HeaderMap;
+use reqwest::Client;
+use serde::Deserialize;
+use tokio::sync::Mutex;
+use tracing::warn;
+use crate::config::{CoinPackage, Config};
+use crate::errors::{BotError, BotResult};
+use crate::services::{format_cents, Checkout, PaymentEvent, PaymentProvider, PaymentStatus};
+
+/// PayPal checkout through the Orders v2 API.
+///
+/// Buyers approve the order on PayPal, and it's only captured once the bot has
+/// matched the approval to a pending order, so no money moves for orders it
+/// wouldn't credit.
+#[derive(Clone)]
+pub struct PayPalProvider {
+    client: Client,
+    api_url: String,
+    client_id: String,
+    client_secret: String,
+    webhook_id: String,
+    currency: String,
+    success_url: String,
+    cancel_url: String,
+    token: Arc<Mutex<Option<(String, Instant)>>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AccessToken {
+    access_token: String,
+    expires_in: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PayPalOrder {
+    pub id: String,
+    pub status: String,
+    #[serde(default)]
+    pub links: Vec<PayPalLink>,
+    #[serde(default)]
+    pub purchase_units: Vec<PayPalPurchaseUnit>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PayPalLink {
+    pub href: String,
+    pub rel: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PayPalPurchaseUnit {
+    pub payments: Option<PayPalPayments>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PayPalPayments {
+    #[serde(default)]
+    pub captures: Vec<PayPalCapture>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PayPalCapture {
+    pub id: String,
+    pub status: String,
+}
+
+impl PayPalOrder {
+    fn capture_id(&self) -> Option<String> {
+        self.purchase_units.iter()
+            .filter_map(|unit| unit.payments.as_ref())
+            .flat_map(|payments| payments.captures.iter())
+            .find(|capture| capture.status == "COMPLETED")
+            .map(|capture| capture.id.clone())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct WebhookEvent {
+    event_type: String,
+    resource: serde_json::Value,
+}
+
+impl PayPalProvider {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            client: Client::new(),
+            api_url: config.paypal_api_url.trim_end_matches('/').to_string(),
+            client_id: config.paypal_client_id.clone(),
+            client_secret: config.paypal_client_secret.clone(),
+            webhook_id: config.paypal_webhook_id.clone(),
+            currency: config.payment_currency.to_uppercase(),
+            success_url: config.payment_success_url.clone(),
+            cancel_url: config.payment_cancel_url.clone(),
+            token: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// OAuth token for the REST API, cached until shortly before it expires.
+    async fn access_token(&self) -> BotResult<String> {
+        let mut token = self.token.lock().await;
+        if let Some((access_token, expires_at)) = token.as_ref() {
+            if Instant::now() < *expires_at {
+                return Ok(access_token.clone());
+            }
+        }
+
+        let response = self.client
+            .post(format!("{}/v1/oauth2/token", self.api_url))
+            .basic_auth(&self.client_id, Some(&self.client_secret))
+            .form(&[("grant_type", "client_credentials")])
+            .send()
+            .await?;
+        let fresh: AccessToken = Self::parse(response, "get an access token").await?;
+
+        let expires_at = Instant::now() + Duration::from_secs(fresh.expires_in.saturating_sub(60));
+        *token = Some((fresh.access_token.clone(), expires_at));
+        Ok(fresh.access_token)
+    }
+
+    pub async fn get_order(&self, order_id: &str) -> BotResult<PayPalOrder> {
+        let response = self.client
+            .get(format!("{}/v2/checkout/orders/{}", self.api_url, order_id))
+            .bearer_auth(self.access_token().await?)
+            .send()
+            .await?;
+
+        Self::parse(response, "fetch order").await
+    }
+
+    /// Captures an approved order. Capturing one that's already captured
+    /// returns its current state instead of failing.
+    pub async fn capture_order(&self, order_id: &str) -> BotResult<PayPalOrder> {
+        let response = self.client
+            .post(format!("{}/v2/checkout/orders/{}/capture", self.api_url, order_id))
+            .bearer_auth(self.access_token().await?)
+            .header("PayPal-Request-Id", format!("capture-{}", order_id))
+            .json(&serde_json::json!({}))
+            .send()
+            .await?;
+
+        if response.status().as_u16() == 422 {
+            return self.get_order(order_id).await;
+        }
+        Self::parse(response, "capture order").await
+    }
+
+    /// Refunds a capture in full. Refunding one that's already refunded
+    /// succeeds without doing anything.
+    pub async fn refund_capture(&self, capture_id: &str) -> BotResult<()> {
+        let response = self.client
+            .post(format!("{}/v2/payments/captures/{}/refund", self.api_url, capture_id))
+            .bearer_auth(self.access_token().await?)
+            .header("PayPal-Request-Id", format!("refund-{}", capture_id))
+            .json(&serde_json::json!({}))
+            .send()
+            .await?;
+
+        if response.status().as_u16() == 422 && self.get_capture_status(capture_id).await? == "REFUNDED" {
+            return Ok(());
+        }
+        let _: serde_json::Value = Self::parse(response, "refund capture").await?;
+        Ok(())
+    }
+
+    async fn get_capture_status(&self, capture_id: &str) -> BotResult<String> {
+        let response = self.client
+            .get(format!("{}/v2/payments/captures/{}", self.api_url, capture_id))
+            .bearer_auth(self.access_token().await?)
+            .send()
+            .await?;
+
+        let capture: PayPalCapture = Self::parse(response, "fetch capture").await?;
+        Ok(capture.status)
+    }
+
+    /// Has PayPal check a webhook's transmission signature against our webhook ID.
+    async fn verify_signature(&self, headers: &HeaderMap, event: &serde_json::Value) -> BotResult<()> {
+        if self.webhook_id.is_empty() {
+            return Err(BotError::PayPal("PAYPAL_WEBHOOK_ID is not set".to_string()));
+        }
+
+        let header = |name: &str| -> BotResult<String> {
+            headers.get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string)
+                .ok_or_else(|| BotError::PayPal(format!("Missing {} header", name)))
+        };
+
+        let body = serde_json::json!({
+            "auth_algo": header("PAYPAL-AUTH-ALGO")?,
+            "cert_url": header("PAYPAL-CERT-URL")?,
+            "transmission_id": header("PAYPAL-TRANSMISSION-ID")?,
+            "transmission_sig": header("PAYPAL-TRANSMISSION-SIG")?,
+            "transmission_time": header("PAYPAL-TRANSMISSION-TIME")?,
+            "webhook_id": self.webhook_id,
+            "webhook_event": event,
+        });
+
+        let response = self.client
+            .post(format!("{}/v1/notifications/verify-webhook-signature", self.api_url))
+            .bearer_auth(self.access_token().await?)
+            .json(&body)
+            .send()
+            .await?;
+        let result: serde_json::Value = Self::parse(response, "verify webhook signature").await?;
+
+        if result["verification_status"].as_str() != Some("SUCCESS") {
+            return Err(BotError::PayPal("Webhook signature does not match".to_string()));
+        }
+        Ok(())
+    }
+
+    async fn parse<T: serde::de::DeserializeOwned>(response: reqwest::Response, action: &str) -> BotResult<T> {
+        if !response.status().is_success() {
+            let status = response.status();
+            let error_text = response.text().await.unwrap_or_default();
+            return Err(BotError::PayPal(format!("Failed to {} ({}): {}", action, status.as_u16(), error_text)));
+        }
+
+        response.json().await
+            .map_err(|e| BotError::PayPal(format!("Failed to parse {} response: {}", action, e)))
+    }
+}
+
+#[serenity::async_trait]
+impl PaymentProvider for PayPalProvider {
+    fn name(&self) -> &'static str {
+        "paypal"
+    }
+
+    async fn create_checkout(&self, package: &CoinPackage, discord_id: u64, reference: &str) -> BotResult<Checkout> {
+        let body = serde_json::json!({
+            "intent": "CAPTURE",
+            "purchase_units": [{
+                "reference_id": reference,
+                "custom_id": discord_id.to_string(),
+                "description": package.name,
+                "amount": {
+                    "currency_code": self.currency,
+                    "value": format_cents(package.price_cents),
+                },
+            }],
+            "application_context": {
+                "return_url": self.success_url,
+                "cancel_url": self.cancel_url,
+                "shipping_preference": "NO_SHIPPING",
+                "user_action": "PAY_NOW",
+            },
+        });
+
+        let response = self.client
+            .post(format!("{}/v2/checkout/orders", self.api_url))
+            .bearer_auth(self.access_token().await?)
+            .header("PayPal-Request-Id", reference)
+            .json(&body)
+            .send()
+            .await?;
+        let order: PayPalOrder = Self::parse(response, "create order").await?;
+
+        let url = order.links.iter()
+            .find(|link| link.rel == "approve" || link.rel == "payer-action")
+            .map(|link| link.href.clone());
+
+        Ok(Checkout {
+            id: order.id,
+            url,
+        })
+    }
+
+    async fn verify_webhook(&self, headers: &HeaderMap, payload: &[u8]) -> BotResult<PaymentEvent> {
+        let raw: serde_json::Value = serde_json::from_slice(payload)
+            .map_err(|e| BotError::PayPal(format!("Failed to parse webhook event: {}", e)))?;
+        self.verify_signature(headers, &raw).await?;
+
+        let event: WebhookEvent = serde_json::from_value(raw)
+            .map_err(|e| BotError::PayPal(format!("Failed to parse webhook event: {}", e)))?;
+        let resource = &event.resource;
+
+        match event.event_type.as_str() {
+            // Captured by the caller once it has found a pending order for it
+            "CHECKOUT.ORDER.APPROVED" => Ok(resource["id"].as_str()
+                .map(|order_id| PaymentEvent::Approved { checkout_id: order_id.to_string() })
+                .unwrap_or(PaymentEvent::Ignored)),
+            "PAYMENT.CAPTURE.COMPLETED" => {
+                let order_id = resource["supplementary_data"]["related_ids"]["order_id"].as_str();
+                let capture_id = resource["id"].as_str();
+                Ok(match (order_id, capture_id) {
+                    (Some(order_id), Some(capture_id)) => PaymentEvent::Paid {
+                        checkout_id: order_id.to_string(),
+                        payment_id: Some(capture_id.to_string()),
+                    },
+                    _ => PaymentEvent::Ignored,
+                })
+            }
+            "CHECKOUT.ORDER.VOIDED" | "PAYMENT.CAPTURE.DENIED" => {
+                let order_id = resource["supplementary_data"]["related_ids"]["order_id"].as_str()
+                    .or_else(|| resource["id"].as_str());
+                Ok(order_id
+                    .map(|order_id| PaymentEvent::Closed { checkout_id: order_id.to_string() })
+                    .unwrap_or(PaymentEvent::Ignored))
+            }
+            "PAYMENT.CAPTURE.REFUNDED" => {
+                // The resource is the refund; its "up" link points at the capture
+                let capture_id = resource["links"].as_array()
+                    .and_then(|links| links.iter().find(|link| link["rel"] == "up"))
+                    .and_then(|link| link["href"].as_str())
+                    .and_then(|href| href.rsplit('/').next())
+                    .map(str::to_string);
+                let capture_id = match capture_id {
+                    Some(capture_id) => capture_id,
+                    None => return Ok(PaymentEvent::Ignored),
+                };
+
+                if self.get_capture_status(&capture_id).await? != "REFUNDED" {
+                    warn!("PayPal capture {} was partially refunded, not reversing its order", capture_id);
+                    return Ok(PaymentEvent::Ignored);
+                }
+                Ok(PaymentEvent::Refunded { payment_id: capture_id })
+            }
+            "CUSTOMER.DISPUTE.CREATED" => {
+                let capture_id = resource["disputed_transactions"].as_array()
+                    .and_then(|transactions| transactions.first())
+                    .and_then(|transaction| transaction["seller_transaction_id"].as_str());
+                Ok(capture_id
+                    .map(|capture_id| PaymentEvent::Disputed { payment_id: capture_id.to_string() })
+                    .unwrap_or(PaymentEvent::Ignored))
+            }
+            _ => Ok(PaymentEvent::Ignored),
+        }
+    }
+
+    async fn get_payment(&self, checkout_id: &str) -> BotResult<PaymentStatus> {
+        let order = self.get_order(checkout_id).await?;
+        Ok(match order.status.as_str() {
+            "COMPLETED" => PaymentStatus::Paid { payment_id: order.capture_id() },
+            "APPROVED" => PaymentStatus::Approved,
+            "VOIDED" => PaymentStatus::Closed,
+            _ => PaymentStatus::Pending,
+        })
+    }
+
+    async fn capture(&self, checkout_id: &str) -> BotResult<PaymentStatus> {
+        let order = self.capture_order(checkout_id).await?;
+        Ok(match order.capture_id() {
+            Some(capture_id) => PaymentStatus::Paid { payment_id: Some(capture_id) },
+            // Pending captures (e.g. eCheck) complete later with PAYMENT.CAPTURE.COMPLETED
+            None => PaymentStatus::Pending,
+        })
+    }
+
+    async fn refund(&self, payment_id: &str) -> BotResult<()> {
+        self.refund_capture(payment_id).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{self, StubRequest, StubServer};
+
+    const ORDER_APPROVED: &str = include_str!("../../tests/fixtures/paypal/checkout_order_approved.json");
+    const CAPTURE_COMPLETED: &str = include_str!("../../tests/fixtures/paypal/payment_capture_completed.json");
+    const CAPTURE_REFUNDED: &str = include_str!("../../tests/fixtures/paypal/payment_capture_refunded.json");
+    const DISPUTE_CREATED: &str = include_str!("../../tests/fixtures/paypal/customer_dispute_created.json");
+
+    /// Stands in for PayPal: signatures verify as `verification_status`,
+    /// captures complete as `capture_status`.
+    fn paypal(verification_status: &'static str, capture_status: &'static str) -> StubServer {
+        StubServer::start(move |request: &StubRequest| match request.path.as_str() {
+            "/v1/oauth2/token" => (200, serde_json::json!({ "access_token": "A21AA-test", "expires_in": 32400 })),
+            "/v1/notifications/verify-webhook-signature" => (200, serde_json::json!({ "verification_status": verification_status })),
+            path if path.starts_with("/v2/checkout/orders/") && path.ends_with("/capture") => (201, serde_json::json!({
+                "id": "5O190127TN364715T",
+                "status": if capture_status == "COMPLETED" { "COMPLETED" } else { "APPROVED" },
+                "purchase_units": [{
+                    "payments": { "captures": [{ "id": "3C679366HH908993F", "status": capture_status }] },
+                }],
+            })),
+            path if path.starts_with("/v2/checkout/orders/") => (200, serde_json::json!({
+                "id": "5O190127TN364715T",
+                "status": "APPROVED",
+            })),
+            path if path.starts_with("/v2/payments/captures/") => (200, serde_json::json!({
+                "id": "3C679366HH908993F",
+                "status": capture_status,
+            })),
+            _ => (404, serde_json::json!({ "name": "RESOURCE_NOT_FOUND" })),
+        })
+    }
+
+    fn provider(stub: &StubServer) -> PayPalProvider {
+        let mut config = test_support::config();
+        config.paypal_api_url = stub.url.clone();
+        PayPalProvider::new(&config)
+    }
+
+    fn headers() -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert("PAYPAL-AUTH-ALGO", "SHA256withRSA".parse().unwrap());
+        headers.insert("PAYPAL-CERT-URL", "https://api.paypal.com/v1/notifications/certs/CERT-360caa42-fca2a594-1d93a270".parse().unwrap());
+        headers.insert("PAYPAL-TRANSMISSION-ID", "69cd13f0-d67a-11e5-baa3-778b53f4ae55".parse().unwrap());
+        headers.insert("PAYPAL-TRANSMISSION-SIG", "lmI95Jx3Y9nhR5SJWlHVIWpg4AgFk7n9bCHSRxbrd8A9zrhdu2rMyFrmz+Zjh3s3boXB07VXCXUZy/UFzUlnGJn0wDugt7FlSvdKeIJenLRemUxYCPVoEZzg9VFNqOa48gMkvF+XTpxBeUx/kWy6B5cp7GkT2+pOowfRK7OaynuxUoKW3JcMWw272VKjLTtTAShncla7tGF+55rxyt2KNZIIqxNMJ48RDZheGU5w1npu9dZHnPgTXB9iomeVRoD8O/jhRpnKsGrDschyNdkeh81BJJMH4Ctc6lnCCquoP/GzCzz33MMsNdid7vL/NIWaCsekQpW26FpWPi/tfj8nLA==".parse().unwrap());
+        headers.insert("PAYPAL-TRANSMISSION-TIME", "2024-05-02T11:24:52Z".parse().unwrap());
+        headers
+    }
+
+    #[tokio::test]
+    async fn approval_is_verified_but_not_captured() {
+        let stub = paypal("SUCCESS", "COMPLETED");
+
+        match provider(&stub).verify_webhook(&headers(), ORDER_APPROVED.as_bytes()).await.unwrap() {
+            PaymentEvent::Approved { checkout_id } => assert_eq!(checkout_id, "5O190127TN364715T"),
+            other => panic!("expected an approved event, got {:?}", other),
+        }
+
+        let token = &stub.requests_to("/v1/oauth2/token")[0];
+        assert!(token.header("authorization").unwrap().starts_with("Basic "));
+        assert_eq!(token.form()["grant_type"], "client_credentials");
+
+        let verify = stub.requests_to("/v1/notifications/verify-webhook-signature");
+        assert_eq!(verify.len(), 1);
+        assert_eq!(verify[0].header("authorization"), Some("Bearer A21AA-test"));
+        let body = verify[0].json();
+        assert_eq!(body["webhook_id"], "WH-TEST");
+        assert_eq!(body["transmission_id"], "69cd13f0-d67a-11e5-baa3-778b53f4ae55");
+        assert_eq!(body["auth_algo"], "SHA256withRSA");
+        assert_eq!(body["webhook_event"], serde_json::from_str::<serde_json::Value>(ORDER_APPROVED).unwrap());
+
+        // Capturing is left to whoever matches the approval to an order
+        assert!(stub.requests_to("/v2/").is_empty());
+    }
+
+    #[tokio::test]
+    async fn capture_collects_approved_order() {
+        let stub = paypal("SUCCESS", "COMPLETED");
+
+        match provider(&stub).capture("5O190127TN364715T").await.unwrap() {
+            PaymentStatus::Paid { payment_id } => assert_eq!(payment_id.as_deref(), Some("3C679366HH908993F")),
+            other => panic!("expected a paid status, got {:?}", other),
+        }
+
+        let capture = stub.requests_to("/v2/checkout/orders/5O190127TN364715T/capture");
+        assert_eq!(capture.len(), 1);
+        assert_eq!(capture[0].header("paypal-request-id"), Some("capture-5O190127TN364715T"));
+    }
+
+    #[tokio::test]
+    async fn pending_capture_is_still_pending() {
+        let stub = paypal("SUCCESS", "PENDING");
+        let status = provider(&stub).capture("5O190127TN364715T").await.unwrap();
+        assert!(matches!(status, PaymentStatus::Pending), "{:?}", status);
+    }
+
+    #[tokio::test]
+    async fn approved_order_reports_approved() {
+        let stub = paypal("SUCCESS", "COMPLETED");
+        let status = provider(&stub).get_payment("5O190127TN364715T").await.unwrap();
+        assert!(matches!(status, PaymentStatus::Approved), "{:?}", status);
+    }
+
+    #[tokio::test]
+    async fn completed_capture_is_paid() {
+        let stub = paypal("SUCCESS", "COMPLETED");
+
+        match provider(&stub).verify_webhook(&headers(), CAPTURE_COMPLETED.as_bytes()).await.unwrap() {
+            PaymentEvent::Paid { checkout_id, payment_id } => {
+                assert_eq!(checkout_id, "5O190127TN364715T");
+                assert_eq!(payment_id.as_deref(), Some("3C679366HH908993F"));
+            }
+            other => panic!("expected a paid event, got {:?}", other),
+        }
+        assert!(stub.requests_to("/v2/checkout/orders").is_empty());
+    }
+
+    #[tokio::test]
+    async fn full_refund_reverses_the_capture() {
+        let stub = paypal("SUCCESS", "REFUNDED");
+
+        match provider(&stub).verify_webhook(&headers(), CAPTURE_REFUNDED.as_bytes()).await.unwrap() {
+            PaymentEvent::Refunded { payment_id } => assert_eq!(payment_id, "3C679366HH908993F"),
+            other => panic!("expected a refunded event, got {:?}", other),
+        }
+        assert_eq!(stub.requests_to("/v2/payments/captures/3C679366HH908993F").len(), 1);
+    }
+
+    #[tokio::test]
+    async fn partial_refund_is_ignored() {
+        let stub = paypal("SUCCESS", "PARTIALLY_REFUNDED");
+        let event = provider(&stub).verify_webhook(&headers(), CAPTURE_REFUNDED.as_bytes()).await.unwrap();
+        assert!(matches!(event, PaymentEvent::Ignored), "{:?}", event);
+    }
+
+    #[tokio::test]
+    async fn dispute_names_the_capture() {
+        let stub = paypal("SUCCESS", "COMPLETED");
+
+        match provider(&stub).verify_webhook(&headers(), DISPUTE_CREATED.as_bytes()).await.unwrap() {
+            PaymentEvent::Disputed { payment_id } => assert_eq!(payment_id, "3C679366HH908993F"),
+            other => panic!("expected a disputed event, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn failed_verification_is_rejected() {
+        let stub = paypal("FAILURE", "COMPLETED");
+
+        let error = provider(&stub).verify_webhook(&headers(), ORDER_APPROVED.as_bytes()).await.unwrap_err();
+        assert!(error.to_string().contains("does not match"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn missing_transmission_headers_are_rejected() {
+        let stub = paypal("SUCCESS", "COMPLETED");
+        let mut headers = headers();
+        headers.remove("PAYPAL-TRANSMISSION-SIG");
+
+        let error = provider(&stub).verify_webhook(&headers, ORDER_APPROVED.as_bytes()).await.unwrap_err();
+        assert!(error.to_string().contains("PAYPAL-TRANSMISSION-SIG"), "{}", error);
+        assert!(stub.requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn access_token_is_reused() {
+        let stub = paypal("SUCCESS", "COMPLETED");
+        let provider = provider(&stub);
+
+        provider.verify_webhook(&headers(), CAPTURE_COMPLETED.as_bytes()).await.unwrap();
+        provider.verify_webhook(&headers(), DISPUTE_CREATED.as_bytes()).await.unwrap();
+        assert_eq!(stub.requests_to("/v1/oauth2/token").len(), 1);
+    }
+}
@@ -0,0 +1,92 @@
+use std::time::Duration;
+use tracing::{info, warn};
+use crate::errors::{BotError, BotResult};
+use crate::models::{Server, ServerStatus};
+use crate::services::{Database, PowerSignal, PterodactylClient, ServerResourceUsage};
+
+/// How long a power action waits for the server to reach its target state.
+const SETTLE_TIMEOUT: Duration = Duration::from_secs(20);
+const SETTLE_POLL: Duration = Duration::from_secs(2);
+
+/// Sends power signals through the Client API and keeps the stored status in step.
+pub struct PowerService {
+    database: Database,
+    pterodactyl: PterodactylClient,
+}
+
+impl PowerService {
+    pub fn new(database: Database, pterodactyl: PterodactylClient) -> Self {
+        Self { database, pterodactyl }
+    }
+
+    /// The server's Client API identifier, looked up once for servers
+    /// provisioned before it was stored.
+    pub async fn identifier(&self, server: &Server) -> BotResult<String> {
+        if let Some(identifier) = &server.pterodactyl_identifier {
+            return Ok(identifier.clone());
+        }
+
+        let pterodactyl_id = server.pterodactyl_id
+            .ok_or_else(|| BotError::InvalidInput("This server hasn't been created on the panel yet".to_string()))?;
+        let identifier = self.pterodactyl.get_server(pterodactyl_id).await?.identifier;
+
+        self.database.set_server_identifier(&server.id.to_string(), &identifier).await?;
+
+        Ok(identifier)
+    }
+
+    /// Fetches the server's live usage and brings `server.status` in line with the panel.
+    pub async fn usage(&self, server: &mut Server) -> BotResult<ServerResourceUsage> {
+        let identifier = self.identifier(server).await?;
+        let usage = self.pterodactyl.get_resource_usage(&identifier).await?;
+        if let Some(status) = self.sync_status(server, &usage.current_state).await {
+            server.status = status;
+        }
+        Ok(usage)
+    }
+
+    /// Sends `signal` and waits until the server reaches the state it implies,
+    /// returning the last state seen, which is still transitional on a timeout.
+    pub async fn send(&self, server: &Server, signal: PowerSignal) -> BotResult<ServerResourceUsage> {
+        if !matches!(server.status, ServerStatus::Running | ServerStatus::Stopped) {
+            return Err(BotError::InvalidInput("Only running or stopped servers can be powered on or off".to_string()));
+        }
+
+        let identifier = self.identifier(server).await?;
+        self.pterodactyl.send_power_signal(&identifier, signal).await?;
+        info!("Sent {} to server {} ({})", signal.as_str(), server.id, identifier);
+
+        // A restart passes through offline before it is running again, so
+        // give the daemon a moment before the first check
+        let deadline = tokio::time::Instant::now() + SETTLE_TIMEOUT;
+        let usage = loop {
+            tokio::time::sleep(SETTLE_POLL).await;
+            let usage = self.pterodactyl.get_resource_usage(&identifier).await?;
+            if usage.current_state == signal.target_state() || tokio::time::Instant::now() >= deadline {
+                break usage;
+            }
+        };
+
+        self.sync_status(server, &usage.current_state).await;
+        Ok(usage)
+    }
+
+    /// Mirrors the panel's power state onto a running or stopped server,
+    /// returning the new status if it changed.
+    async fn sync_status(&self, server: &Server, current_state: &str) -> Option<ServerStatus> {
+        let (from, to) = match current_state {
+            "running" => (ServerStatus::Stopped, ServerStatus::Running),
+            "offline" => (ServerStatus::Running, ServerStatus::Stopped),
+            _ => return None,
+        };
+
+        match self.database.transition_server(&server.id.to_string(), &[from], to.clone()).await {
+            Ok(Some(_)) => Some(to),
+            Ok(None) => None,
+            Err(e) => {
+                warn!("Failed to sync status of server {}: {}", server.id, e);
+                None
+            }
+        }
+    }
+}
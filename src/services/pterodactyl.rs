@@ -1,7 +1,7 @@
 use anyhow::Result;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
-use crate::config::Config;
+use crate::config::{Config, ServerTemplate};
 use crate::errors::{BotError, BotResult};
 use crate::models::{Server, Resources};
 
@@ -10,11 +10,13 @@ pub struct PterodactylClient {
     client: Client,
     base_url: String,
     api_key: String,
+    client_api_key: String,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CreateServerRequest {
     pub name: String,
+    pub external_id: Option<String>,
     pub user: i64,
     pub egg: i64,
     pub docker_image: String,
@@ -46,6 +48,60 @@ pub struct AllocationRequest {
     pub default: i64,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PterodactylNode {
+    pub id: i64,
+    pub name: String,
+    pub location_id: i64,
+    #[serde(default)]
+    pub maintenance_mode: bool,
+    pub memory: i64,
+    pub memory_overallocate: i64,
+    pub disk: i64,
+    pub disk_overallocate: i64,
+    #[serde(default)]
+    pub allocated_resources: AllocatedResources,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AllocatedResources {
+    pub memory: i64,
+    pub disk: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PterodactylAllocation {
+    pub id: i64,
+    pub ip: String,
+    pub port: i64,
+    pub assigned: bool,
+}
+
+impl PterodactylNode {
+    /// Memory still available for new servers, or `None` when overallocation checks are disabled.
+    pub fn free_memory(&self) -> Option<i64> {
+        Self::free(self.memory, self.memory_overallocate, self.allocated_resources.memory)
+    }
+
+    pub fn free_disk(&self) -> Option<i64> {
+        Self::free(self.disk, self.disk_overallocate, self.allocated_resources.disk)
+    }
+
+    fn free(total: i64, overallocate: i64, allocated: i64) -> Option<i64> {
+        if overallocate < 0 {
+            return None;
+        }
+        Some(total + total * overallocate / 100 - allocated)
+    }
+
+    pub fn memory_usage(&self) -> f64 {
+        if self.memory <= 0 {
+            return 1.0;
+        }
+        self.allocated_resources.memory as f64 / self.memory as f64
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct PterodactylServer {
     pub id: i64,
@@ -60,23 +116,178 @@ pub struct PterodactylServer {
     pub feature_limits: FeatureLimits,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PterodactylEgg {
+    pub id: i64,
+    pub nest: i64,
+    pub docker_image: String,
+    pub startup: String,
+}
+
+/// Everything the panel needs to know about the software a server runs.
+#[derive(Debug, Clone)]
+pub struct EggSettings {
+    pub egg: i64,
+    pub docker_image: String,
+    pub startup: String,
+    pub environment: serde_json::Map<String, serde_json::Value>,
+}
+
+/// Signals the Client API accepts on `/power`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PowerSignal {
+    Start,
+    Stop,
+    Restart,
+    Kill,
+}
+
+impl PowerSignal {
+    pub const ALL: [PowerSignal; 4] = [PowerSignal::Start, PowerSignal::Stop, PowerSignal::Restart, PowerSignal::Kill];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PowerSignal::Start => "start",
+            PowerSignal::Stop => "stop",
+            PowerSignal::Restart => "restart",
+            PowerSignal::Kill => "kill",
+        }
+    }
+
+    pub fn parse(signal: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|candidate| candidate.as_str() == signal)
+    }
+
+    /// The power state the server should settle in once the signal is handled.
+    pub fn target_state(&self) -> &'static str {
+        match self {
+            PowerSignal::Start | PowerSignal::Restart => "running",
+            PowerSignal::Stop | PowerSignal::Kill => "offline",
+        }
+    }
+}
+
+/// A server's live state from the Client API's `/resources`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServerResourceUsage {
+    pub current_state: String, // offline, starting, running or stopping
+    #[serde(default)]
+    pub is_suspended: bool,
+    pub resources: ResourceStats,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ResourceStats {
+    #[serde(default)]
+    pub memory_bytes: i64,
+    #[serde(default)]
+    pub cpu_absolute: f64,
+    #[serde(default)]
+    pub disk_bytes: i64,
+    #[serde(default)]
+    pub network_rx_bytes: i64,
+    #[serde(default)]
+    pub network_tx_bytes: i64,
+    #[serde(default)]
+    pub uptime: i64, // milliseconds
+}
+
+/// Short-lived credentials for a server's console websocket on its node.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebsocketCredentials {
+    pub token: String,
+    pub socket: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PterodactylBackup {
+    pub uuid: String,
+    pub name: String,
+    #[serde(default)]
+    pub bytes: i64,
+    pub is_successful: bool,
+    pub is_locked: bool,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub completed_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl PterodactylBackup {
+    /// Failed backups don't take up one of the server's slots; finished and running ones do.
+    pub fn uses_slot(&self) -> bool {
+        self.is_successful || self.completed_at.is_none()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PterodactylDatabase {
+    pub id: String,
+    pub name: String,
+    pub username: String,
+    pub host: DatabaseHost,
+    pub connections_from: String,
+    #[serde(default)]
+    pub password: Option<String>, // only when the password relationship is included
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DatabaseHost {
+    pub address: String,
+    pub port: i64,
+}
+
+impl PterodactylDatabase {
+    /// Reads a database from a Client API item, lifting the included password.
+    fn from_item(item: &serde_json::Value) -> BotResult<Self> {
+        let mut database: PterodactylDatabase = serde_json::from_value(item["attributes"].clone())
+            .map_err(|e| BotError::Pterodactyl(format!("Failed to parse database response: {}", e)))?;
+        database.password = item["attributes"]["relationships"]["password"]["attributes"]["password"]
+            .as_str()
+            .map(|password| password.to_string());
+        Ok(database)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateUserRequest {
+    pub external_id: String,
+    pub email: String,
+    pub username: String,
+    pub first_name: String,
+    pub last_name: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PterodactylUser {
+    pub id: i64,
+    pub external_id: Option<String>,
+    pub uuid: String,
+    pub username: String,
+    pub email: String,
+    pub first_name: String,
+    pub last_name: String,
+    #[serde(default)]
+    pub root_admin: bool,
+}
+
 impl PterodactylClient {
     pub fn new(config: &Config) -> Self {
         Self {
             client: Client::new(),
             base_url: config.pterodactyl_url.clone(),
             api_key: config.pterodactyl_api_key.clone(),
+            client_api_key: config.pterodactyl_client_api_key.clone(),
         }
     }
 
-    pub async fn create_server(&self, server: &Server, user_id: i64) -> BotResult<PterodactylServer> {
+    pub async fn create_server(&self, server: &Server, user_id: i64, egg: &EggSettings, allocation_id: i64) -> BotResult<PterodactylServer> {
         let request = CreateServerRequest {
             name: server.name.clone(),
+            external_id: Some(server.id.to_string()),
             user: user_id,
-            egg: 1, // Default egg ID - should be configurable
-            docker_image: "quay.io/pterodactyl/core:java".to_string(),
-            startup: "java -Xms128M -Xmx{{SERVER_MEMORY}}M -jar {{SERVER_JARFILE}}".to_string(),
-            environment: serde_json::json!({}),
+            egg: egg.egg,
+            docker_image: egg.docker_image.clone(),
+            startup: egg.startup.clone(),
+            environment: serde_json::Value::Object(egg.environment.clone()),
             limits: ServerLimits {
                 memory: server.resources.ram,
                 swap: 0,
@@ -90,7 +301,7 @@ impl PterodactylClient {
                 backups: server.resources.backups,
             },
             allocation: AllocationRequest {
-                default: 1, // Default allocation ID
+                default: allocation_id,
             },
         };
 
@@ -104,8 +315,9 @@ impl PterodactylClient {
             .await?;
 
         if !response.status().is_success() {
+            let status = response.status().as_u16();
             let error_text = response.text().await.unwrap_or_default();
-            return Err(BotError::Pterodactyl(format!("Failed to create server: {}", error_text)));
+            return Err(BotError::PterodactylApi { status, message: format!("Failed to create server: {}", error_text) });
         }
 
         let server_response: serde_json::Value = response.json().await?;
@@ -116,6 +328,121 @@ impl PterodactylClient {
         Ok(pterodactyl_server)
     }
 
+    /// Fetches every page of an application API list endpoint.
+    async fn get_all_pages(&self, path: &str) -> BotResult<Vec<serde_json::Value>> {
+        let mut items = Vec::new();
+        let mut page = 1;
+
+        loop {
+            let response = self.client
+                .get(&format!("{}{}", self.base_url, path))
+                .query(&[("page", page), ("per_page", 100)])
+                .header("Authorization", format!("Bearer {}", self.api_key))
+                .header("Accept", "application/json")
+                .send()
+                .await?;
+
+            if !response.status().is_success() {
+                let status = response.status().as_u16();
+                let error_text = response.text().await.unwrap_or_default();
+                return Err(BotError::PterodactylApi { status, message: format!("Failed to list {}: {}", path, error_text) });
+            }
+
+            let list_response: serde_json::Value = response.json().await?;
+            if let Some(data) = list_response["data"].as_array() {
+                items.extend(data.iter().map(|item| item["attributes"].clone()));
+            }
+
+            let total_pages = list_response["meta"]["pagination"]["total_pages"].as_i64().unwrap_or(1);
+            if page >= total_pages {
+                break;
+            }
+            page += 1;
+        }
+
+        Ok(items)
+    }
+
+    pub async fn list_nodes(&self) -> BotResult<Vec<PterodactylNode>> {
+        self.get_all_pages("/api/application/nodes").await?
+            .into_iter()
+            .map(|node| serde_json::from_value(node)
+                .map_err(|e| BotError::Pterodactyl(format!("Failed to parse node response: {}", e))))
+            .collect()
+    }
+
+    pub async fn find_free_allocation(&self, node_id: i64) -> BotResult<Option<PterodactylAllocation>> {
+        let allocations = self.get_all_pages(&format!("/api/application/nodes/{}/allocations", node_id)).await?;
+
+        for allocation in allocations {
+            let allocation: PterodactylAllocation = serde_json::from_value(allocation)
+                .map_err(|e| BotError::Pterodactyl(format!("Failed to parse allocation response: {}", e)))?;
+            if !allocation.assigned {
+                return Ok(Some(allocation));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Fetches an egg with its variables and returns the panel's default
+    /// image, startup command and environment.
+    pub async fn get_egg(&self, nest_id: i64, egg_id: i64) -> BotResult<EggSettings> {
+        let response = self.client
+            .get(&format!("{}/api/application/nests/{}/eggs/{}", self.base_url, nest_id, egg_id))
+            .query(&[("include", "variables")])
+            .header("Authorization", format!("Bearer {}", self.api_key))
+            .header("Accept", "application/json")
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let status = response.status().as_u16();
+            let error_text = response.text().await.unwrap_or_default();
+            return Err(BotError::PterodactylApi { status, message: format!("Failed to fetch egg: {}", error_text) });
+        }
+
+        let egg_response: serde_json::Value = response.json().await?;
+        let attributes = &egg_response["attributes"];
+        let egg: PterodactylEgg = serde_json::from_value(attributes.clone())
+            .map_err(|e| BotError::Pterodactyl(format!("Failed to parse egg response: {}", e)))?;
+
+        let mut environment = serde_json::Map::new();
+        if let Some(variables) = attributes["relationships"]["variables"]["data"].as_array() {
+            for variable in variables {
+                if let Some(name) = variable["attributes"]["env_variable"].as_str() {
+                    let default_value = variable["attributes"]["default_value"].clone();
+                    environment.insert(name.to_string(), default_value);
+                }
+            }
+        }
+
+        Ok(EggSettings {
+            egg: egg.id,
+            docker_image: egg.docker_image,
+            startup: egg.startup,
+            environment,
+        })
+    }
+
+    /// Resolves a template against the panel's egg definition, applying the
+    /// template's overrides on top.
+    pub async fn resolve_template(&self, template: &ServerTemplate) -> BotResult<EggSettings> {
+        let mut settings = self.get_egg(template.nest, template.egg).await?;
+
+        if let Some(docker_image) = &template.docker_image {
+            settings.docker_image = docker_image.clone();
+        }
+        if let Some(startup) = &template.startup {
+            settings.startup = startup.clone();
+        }
+        for (name, value) in &template.environment {
+            settings.environment.insert(name.clone(), serde_json::Value::String(value.clone()));
+        }
+
+        Ok(settings)
+    }
+
     pub async fn get_server(&self, server_id: i64) -> BotResult<PterodactylServer> {
         let response = self.client
             .get(&format!("{}/api/application/servers/{}", self.base_url, server_id))
@@ -125,7 +452,7 @@ impl PterodactylClient {
             .await?;
 
         if !response.status().is_success() {
-            return Err(BotError::Pterodactyl("Server not found".to_string()));
+            return Err(BotError::PterodactylApi { status: response.status().as_u16(), message: "Server not found".to_string() });
         }
 
         let server_response: serde_json::Value = response.json().await?;
@@ -136,6 +463,32 @@ impl PterodactylClient {
         Ok(pterodactyl_server)
     }
 
+    pub async fn get_server_by_external_id(&self, external_id: &str) -> BotResult<Option<PterodactylServer>> {
+        let response = self.client
+            .get(&format!("{}/api/application/servers/external/{}", self.base_url, external_id))
+            .header("Authorization", format!("Bearer {}", self.api_key))
+            .header("Accept", "application/json")
+            .send()
+            .await?;
+
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+
+        if !response.status().is_success() {
+            let status = response.status().as_u16();
+            let error_text = response.text().await.unwrap_or_default();
+            return Err(BotError::PterodactylApi { status, message: format!("Failed to look up server: {}", error_text) });
+        }
+
+        let server_response: serde_json::Value = response.json().await?;
+        let pterodactyl_server: PterodactylServer = serde_json::from_value(
+            server_response["attributes"].clone()
+        ).map_err(|e| BotError::Pterodactyl(format!("Failed to parse server response: {}", e)))?;
+
+        Ok(Some(pterodactyl_server))
+    }
+
     pub async fn delete_server(&self, server_id: i64) -> BotResult<()> {
         let response = self.client
             .delete(&format!("{}/api/application/servers/{}", self.base_url, server_id))
@@ -144,29 +497,459 @@ impl PterodactylClient {
             .send()
             .await?;
 
+        // Already gone, e.g. when a deletion job runs twice
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(());
+        }
+
+        if !response.status().is_success() {
+            let status = response.status().as_u16();
+            let error_text = response.text().await.unwrap_or_default();
+            return Err(BotError::PterodactylApi { status, message: format!("Failed to delete server: {}", error_text) });
+        }
+
+        Ok(())
+    }
+
+    pub async fn suspend_server(&self, server_id: i64) -> BotResult<()> {
+        self.set_suspended(server_id, "suspend").await
+    }
+
+    pub async fn unsuspend_server(&self, server_id: i64) -> BotResult<()> {
+        self.set_suspended(server_id, "unsuspend").await
+    }
+
+    async fn set_suspended(&self, server_id: i64, action: &str) -> BotResult<()> {
+        let response = self.client
+            .post(&format!("{}/api/application/servers/{}/{}", self.base_url, server_id, action))
+            .header("Authorization", format!("Bearer {}", self.api_key))
+            .header("Accept", "application/json")
+            .send()
+            .await?;
+
         if !response.status().is_success() {
+            let status = response.status().as_u16();
             let error_text = response.text().await.unwrap_or_default();
-            return Err(BotError::Pterodactyl(format!("Failed to delete server: {}", error_text)));
+            return Err(BotError::PterodactylApi { status, message: format!("Failed to {} server: {}", action, error_text) });
         }
 
         Ok(())
     }
 
-    pub async fn power_action(&self, server_id: i64, action: &str) -> BotResult<()> {
+    pub async fn create_user(&self, request: &CreateUserRequest) -> BotResult<PterodactylUser> {
         let response = self.client
-            .post(&format!("{}/api/application/servers/{}/power", self.base_url, server_id))
+            .post(&format!("{}/api/application/users", self.base_url))
             .header("Authorization", format!("Bearer {}", self.api_key))
             .header("Content-Type", "application/json")
             .header("Accept", "application/json")
-            .json(&serde_json::json!({ "signal": action }))
+            .json(request)
             .send()
             .await?;
 
         if !response.status().is_success() {
+            let status = response.status().as_u16();
             let error_text = response.text().await.unwrap_or_default();
-            return Err(BotError::Pterodactyl(format!("Failed to perform power action: {}", error_text)));
+            return Err(BotError::PterodactylApi { status, message: format!("Failed to create user: {}", error_text) });
+        }
+
+        let user_response: serde_json::Value = response.json().await?;
+        let pterodactyl_user: PterodactylUser = serde_json::from_value(
+            user_response["attributes"].clone()
+        ).map_err(|e| BotError::Pterodactyl(format!("Failed to parse user response: {}", e)))?;
+
+        Ok(pterodactyl_user)
+    }
+
+    pub async fn get_user_by_external_id(&self, external_id: &str) -> BotResult<Option<PterodactylUser>> {
+        let response = self.client
+            .get(&format!("{}/api/application/users/external/{}", self.base_url, external_id))
+            .header("Authorization", format!("Bearer {}", self.api_key))
+            .header("Accept", "application/json")
+            .send()
+            .await?;
+
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+
+        if !response.status().is_success() {
+            let status = response.status().as_u16();
+            let error_text = response.text().await.unwrap_or_default();
+            return Err(BotError::PterodactylApi { status, message: format!("Failed to look up user: {}", error_text) });
+        }
+
+        let user_response: serde_json::Value = response.json().await?;
+        let pterodactyl_user: PterodactylUser = serde_json::from_value(
+            user_response["attributes"].clone()
+        ).map_err(|e| BotError::Pterodactyl(format!("Failed to parse user response: {}", e)))?;
+
+        Ok(Some(pterodactyl_user))
+    }
+
+    pub async fn find_user_by_email(&self, email: &str) -> BotResult<Option<PterodactylUser>> {
+        let response = self.client
+            .get(&format!("{}/api/application/users", self.base_url))
+            .query(&[("filter[email]", email)])
+            .header("Authorization", format!("Bearer {}", self.api_key))
+            .header("Accept", "application/json")
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let status = response.status().as_u16();
+            let error_text = response.text().await.unwrap_or_default();
+            return Err(BotError::PterodactylApi { status, message: format!("Failed to search users: {}", error_text) });
+        }
+
+        let users_response: serde_json::Value = response.json().await?;
+        let user = users_response["data"]
+            .as_array()
+            .and_then(|users| users.first())
+            .map(|user| serde_json::from_value::<PterodactylUser>(user["attributes"].clone()))
+            .transpose()
+            .map_err(|e| BotError::Pterodactyl(format!("Failed to parse user response: {}", e)))?;
+
+        Ok(user)
+    }
+
+    // Client API: acts on a server as a panel user, addressed by its short identifier.
+    // The key must belong to an admin account so it can reach every server.
+
+    pub fn has_client_api(&self) -> bool {
+        !self.client_api_key.is_empty()
+    }
+
+    fn client_api_key(&self) -> BotResult<&str> {
+        if self.client_api_key.is_empty() {
+            return Err(BotError::Pterodactyl("PTERODACTYL_CLIENT_API_KEY is not set".to_string()));
+        }
+        Ok(&self.client_api_key)
+    }
+
+    pub async fn send_power_signal(&self, identifier: &str, signal: PowerSignal) -> BotResult<()> {
+        let response = self.client
+            .post(&format!("{}/api/client/servers/{}/power", self.base_url, identifier))
+            .header("Authorization", format!("Bearer {}", self.client_api_key()?))
+            .header("Content-Type", "application/json")
+            .header("Accept", "application/json")
+            .json(&serde_json::json!({ "signal": signal.as_str() }))
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let status = response.status().as_u16();
+            let error_text = response.text().await.unwrap_or_default();
+            return Err(BotError::PterodactylApi { status, message: format!("Failed to {} server: {}", signal.as_str(), error_text) });
+        }
+
+        Ok(())
+    }
+
+    pub async fn get_resource_usage(&self, identifier: &str) -> BotResult<ServerResourceUsage> {
+        let response = self.client
+            .get(&format!("{}/api/client/servers/{}/resources", self.base_url, identifier))
+            .header("Authorization", format!("Bearer {}", self.client_api_key()?))
+            .header("Accept", "application/json")
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let status = response.status().as_u16();
+            let error_text = response.text().await.unwrap_or_default();
+            return Err(BotError::PterodactylApi { status, message: format!("Failed to get server resources: {}", error_text) });
+        }
+
+        let usage_response: serde_json::Value = response.json().await?;
+        let usage: ServerResourceUsage = serde_json::from_value(
+            usage_response["attributes"].clone()
+        ).map_err(|e| BotError::Pterodactyl(format!("Failed to parse resources response: {}", e)))?;
+
+        Ok(usage)
+    }
+
+    pub async fn send_command(&self, identifier: &str, command: &str) -> BotResult<()> {
+        let response = self.client
+            .post(&format!("{}/api/client/servers/{}/command", self.base_url, identifier))
+            .header("Authorization", format!("Bearer {}", self.client_api_key()?))
+            .header("Content-Type", "application/json")
+            .header("Accept", "application/json")
+            .json(&serde_json::json!({ "command": command }))
+            .send()
+            .await?;
+
+        // Wings answers 502 when the server isn't running to receive it
+        if response.status() == reqwest::StatusCode::BAD_GATEWAY {
+            return Err(BotError::InvalidInput("The server must be running to receive commands".to_string()));
+        }
+
+        if !response.status().is_success() {
+            let status = response.status().as_u16();
+            let error_text = response.text().await.unwrap_or_default();
+            return Err(BotError::PterodactylApi { status, message: format!("Failed to send command: {}", error_text) });
+        }
+
+        Ok(())
+    }
+
+    pub async fn get_websocket_credentials(&self, identifier: &str) -> BotResult<WebsocketCredentials> {
+        let response = self.client
+            .get(&format!("{}/api/client/servers/{}/websocket", self.base_url, identifier))
+            .header("Authorization", format!("Bearer {}", self.client_api_key()?))
+            .header("Accept", "application/json")
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let status = response.status().as_u16();
+            let error_text = response.text().await.unwrap_or_default();
+            return Err(BotError::PterodactylApi { status, message: format!("Failed to get console credentials: {}", error_text) });
+        }
+
+        let websocket_response: serde_json::Value = response.json().await?;
+        let credentials: WebsocketCredentials = serde_json::from_value(
+            websocket_response["data"].clone()
+        ).map_err(|e| BotError::Pterodactyl(format!("Failed to parse websocket response: {}", e)))?;
+
+        Ok(credentials)
+    }
+
+    pub async fn list_backups(&self, identifier: &str) -> BotResult<Vec<PterodactylBackup>> {
+        let key = self.client_api_key()?.to_string();
+        let mut backups = Vec::new();
+        let mut page = 1;
+
+        loop {
+            let response = self.client
+                .get(&format!("{}/api/client/servers/{}/backups", self.base_url, identifier))
+                .query(&[("page", page.to_string())])
+                .header("Authorization", format!("Bearer {}", key))
+                .header("Accept", "application/json")
+                .send()
+                .await?;
+
+            if !response.status().is_success() {
+                let status = response.status().as_u16();
+                let error_text = response.text().await.unwrap_or_default();
+                return Err(BotError::PterodactylApi { status, message: format!("Failed to list backups: {}", error_text) });
+            }
+
+            let list_response: serde_json::Value = response.json().await?;
+            if let Some(data) = list_response["data"].as_array() {
+                for backup in data {
+                    backups.push(serde_json::from_value(backup["attributes"].clone())
+                        .map_err(|e| BotError::Pterodactyl(format!("Failed to parse backup response: {}", e)))?);
+                }
+            }
+
+            let total_pages = list_response["meta"]["pagination"]["total_pages"].as_i64().unwrap_or(1);
+            if page >= total_pages {
+                break;
+            }
+            page += 1;
+        }
+
+        Ok(backups)
+    }
+
+    pub async fn create_backup(&self, identifier: &str, name: &str) -> BotResult<PterodactylBackup> {
+        let response = self.client
+            .post(&format!("{}/api/client/servers/{}/backups", self.base_url, identifier))
+            .header("Authorization", format!("Bearer {}", self.client_api_key()?))
+            .header("Content-Type", "application/json")
+            .header("Accept", "application/json")
+            .json(&serde_json::json!({ "name": name }))
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let status = response.status().as_u16();
+            let error_text = response.text().await.unwrap_or_default();
+            return Err(BotError::PterodactylApi { status, message: format!("Failed to create backup: {}", error_text) });
+        }
+
+        let backup_response: serde_json::Value = response.json().await?;
+        let backup: PterodactylBackup = serde_json::from_value(
+            backup_response["attributes"].clone()
+        ).map_err(|e| BotError::Pterodactyl(format!("Failed to parse backup response: {}", e)))?;
+
+        Ok(backup)
+    }
+
+    /// A signed link to download the backup, valid for a few minutes.
+    pub async fn backup_download_url(&self, identifier: &str, backup: &str) -> BotResult<String> {
+        let response = self.client
+            .get(&format!("{}/api/client/servers/{}/backups/{}/download", self.base_url, identifier, backup))
+            .header("Authorization", format!("Bearer {}", self.client_api_key()?))
+            .header("Accept", "application/json")
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let status = response.status().as_u16();
+            let error_text = response.text().await.unwrap_or_default();
+            return Err(BotError::PterodactylApi { status, message: format!("Failed to get backup download link: {}", error_text) });
+        }
+
+        let download_response: serde_json::Value = response.json().await?;
+        download_response["attributes"]["url"]
+            .as_str()
+            .map(|url| url.to_string())
+            .ok_or_else(|| BotError::Pterodactyl("Backup download response has no url".to_string()))
+    }
+
+    /// Restores a backup over the server's files; `truncate` deletes every file first.
+    pub async fn restore_backup(&self, identifier: &str, backup: &str, truncate: bool) -> BotResult<()> {
+        let response = self.client
+            .post(&format!("{}/api/client/servers/{}/backups/{}/restore", self.base_url, identifier, backup))
+            .header("Authorization", format!("Bearer {}", self.client_api_key()?))
+            .header("Content-Type", "application/json")
+            .header("Accept", "application/json")
+            .json(&serde_json::json!({ "truncate": truncate }))
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let status = response.status().as_u16();
+            let error_text = response.text().await.unwrap_or_default();
+            return Err(BotError::PterodactylApi { status, message: format!("Failed to restore backup: {}", error_text) });
+        }
+
+        Ok(())
+    }
+
+    /// Flips a backup's lock, returning it with the new state.
+    pub async fn toggle_backup_lock(&self, identifier: &str, backup: &str) -> BotResult<PterodactylBackup> {
+        let response = self.client
+            .post(&format!("{}/api/client/servers/{}/backups/{}/lock", self.base_url, identifier, backup))
+            .header("Authorization", format!("Bearer {}", self.client_api_key()?))
+            .header("Accept", "application/json")
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let status = response.status().as_u16();
+            let error_text = response.text().await.unwrap_or_default();
+            return Err(BotError::PterodactylApi { status, message: format!("Failed to lock backup: {}", error_text) });
+        }
+
+        let backup_response: serde_json::Value = response.json().await?;
+        let backup: PterodactylBackup = serde_json::from_value(
+            backup_response["attributes"].clone()
+        ).map_err(|e| BotError::Pterodactyl(format!("Failed to parse backup response: {}", e)))?;
+
+        Ok(backup)
+    }
+
+    pub async fn delete_backup(&self, identifier: &str, backup: &str) -> BotResult<()> {
+        let response = self.client
+            .delete(&format!("{}/api/client/servers/{}/backups/{}", self.base_url, identifier, backup))
+            .header("Authorization", format!("Bearer {}", self.client_api_key()?))
+            .header("Accept", "application/json")
+            .send()
+            .await?;
+
+        // Already gone, e.g. rotated by another run
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(());
+        }
+
+        if !response.status().is_success() {
+            let status = response.status().as_u16();
+            let error_text = response.text().await.unwrap_or_default();
+            return Err(BotError::PterodactylApi { status, message: format!("Failed to delete backup: {}", error_text) });
+        }
+
+        Ok(())
+    }
+
+    pub async fn list_databases(&self, identifier: &str) -> BotResult<Vec<PterodactylDatabase>> {
+        let response = self.client
+            .get(&format!("{}/api/client/servers/{}/databases", self.base_url, identifier))
+            .query(&[("include", "password")])
+            .header("Authorization", format!("Bearer {}", self.client_api_key()?))
+            .header("Accept", "application/json")
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let status = response.status().as_u16();
+            let error_text = response.text().await.unwrap_or_default();
+            return Err(BotError::PterodactylApi { status, message: format!("Failed to list databases: {}", error_text) });
+        }
+
+        let list_response: serde_json::Value = response.json().await?;
+        list_response["data"]
+            .as_array()
+            .map(|data| data.iter().map(PterodactylDatabase::from_item).collect())
+            .unwrap_or_else(|| Ok(Vec::new()))
+    }
+
+    /// Creates a database; `remote` is the host pattern allowed to connect, `%` for any.
+    pub async fn create_database(&self, identifier: &str, name: &str, remote: &str) -> BotResult<PterodactylDatabase> {
+        let response = self.client
+            .post(&format!("{}/api/client/servers/{}/databases", self.base_url, identifier))
+            .header("Authorization", format!("Bearer {}", self.client_api_key()?))
+            .header("Content-Type", "application/json")
+            .header("Accept", "application/json")
+            .json(&serde_json::json!({ "database": name, "remote": remote }))
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let status = response.status().as_u16();
+            let error_text = response.text().await.unwrap_or_default();
+            return Err(BotError::PterodactylApi { status, message: format!("Failed to create database: {}", error_text) });
+        }
+
+        let database_response: serde_json::Value = response.json().await?;
+        PterodactylDatabase::from_item(&database_response)
+    }
+
+    pub async fn rotate_database_password(&self, identifier: &str, database: &str) -> BotResult<PterodactylDatabase> {
+        let response = self.client
+            .post(&format!("{}/api/client/servers/{}/databases/{}/rotate-password", self.base_url, identifier, database))
+            .header("Authorization", format!("Bearer {}", self.client_api_key()?))
+            .header("Accept", "application/json")
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let status = response.status().as_u16();
+            let error_text = response.text().await.unwrap_or_default();
+            return Err(BotError::PterodactylApi { status, message: format!("Failed to rotate database password: {}", error_text) });
+        }
+
+        let database_response: serde_json::Value = response.json().await?;
+        PterodactylDatabase::from_item(&database_response)
+    }
+
+    pub async fn delete_database(&self, identifier: &str, database: &str) -> BotResult<()> {
+        let response = self.client
+            .delete(&format!("{}/api/client/servers/{}/databases/{}", self.base_url, identifier, database))
+            .header("Authorization", format!("Bearer {}", self.client_api_key()?))
+            .header("Accept", "application/json")
+            .send()
+            .await?;
+
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(());
+        }
+
+        if !response.status().is_success() {
+            let status = response.status().as_u16();
+            let error_text = response.text().await.unwrap_or_default();
+            return Err(BotError::PterodactylApi { status, message: format!("Failed to delete database: {}", error_text) });
         }
 
         Ok(())
     }
+
+    /// Wings only accepts console connections whose `Origin` is the panel.
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    pub fn password_reset_url(&self) -> String {
+        format!("{}/auth/password", self.base_url)
+    }
 }
@@ -4,6 +4,16 @@ use uuid::Uuid;
 use crate::errors::{BotError, BotResult};
 use crate::services::Database;
 
+const QUEUE_KEY: &str = "job_queue";
+const DELAYED_KEY: &str = "job_queue:delayed";
+const DEAD_KEY: &str = "job_queue:dead";
+const WAITING_KEY: &str = "job_queue:waiting";
+const SEQUENCE_KEY: &str = "job_queue:seq";
+const DURATIONS_KEY: &str = "job_queue:durations";
+const PROCESSED_TTL_SECS: u64 = 7 * 24 * 60 * 60;
+const DURATION_SAMPLES: isize = 50;
+const DEFAULT_JOB_SECS: f64 = 30.0;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct QueueJob {
     pub id: Uuid,
@@ -11,6 +21,10 @@ pub struct QueueJob {
     pub discord_id: u64,
     pub data: serde_json::Value,
     pub created_at: chrono::DateTime<chrono::Utc>,
+    #[serde(default)]
+    pub attempts: u32,
+    #[serde(default)]
+    pub last_error: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -28,10 +42,21 @@ impl QueueJob {
             discord_id,
             data,
             created_at: chrono::Utc::now(),
+            attempts: 0,
+            last_error: None,
         }
     }
 }
 
+/// A job that has been moved into a worker's processing list and must be
+/// acknowledged, retried or dead-lettered once the worker is done with it.
+#[derive(Debug, Clone)]
+pub struct ReservedJob {
+    pub job: QueueJob,
+    payload: String,
+    processing_key: String,
+}
+
 pub struct QueueService {
     database: Database,
 }
@@ -41,46 +66,278 @@ impl QueueService {
         Self { database }
     }
 
+    fn processing_key(worker_id: usize) -> String {
+        format!("{}:processing:{}", QUEUE_KEY, worker_id)
+    }
+
+    fn user_waiting_key(discord_id: u64) -> String {
+        format!("{}:user:{}", QUEUE_KEY, discord_id)
+    }
+
+    /// Pushes a job onto the queue and indexes it by arrival order, both
+    /// globally and per user, so positions can be looked up with `ZRANK`.
+    async fn push_waiting(conn: &mut redis::aio::Connection, job: &QueueJob, job_json: &str) -> BotResult<()> {
+        let sequence: i64 = conn.incr(SEQUENCE_KEY, 1).await?;
+
+        let _: () = redis::pipe()
+            .atomic()
+            .lpush(QUEUE_KEY, job_json).ignore()
+            .zadd(WAITING_KEY, job.id.to_string(), sequence).ignore()
+            .zadd(Self::user_waiting_key(job.discord_id), job.id.to_string(), sequence).ignore()
+            .query_async(conn)
+            .await?;
+        Ok(())
+    }
+
+    fn serialize(job: &QueueJob) -> BotResult<String> {
+        serde_json::to_string(job)
+            .map_err(|e| BotError::InvalidInput(format!("Failed to serialize job: {}", e)))
+    }
+
+    fn deserialize(json: &str) -> BotResult<QueueJob> {
+        serde_json::from_str(json)
+            .map_err(|e| BotError::InvalidInput(format!("Failed to deserialize job: {}", e)))
+    }
+
     pub async fn enqueue(&self, job: QueueJob) -> BotResult<()> {
         let mut conn = self.database.get_redis_connection().await?;
-        let job_json = serde_json::to_string(&job)
-            .map_err(|e| BotError::InvalidInput(format!("Failed to serialize job: {}", e)))?;
-        
-        conn.lpush("job_queue", job_json).await?;
+        let job_json = Self::serialize(&job)?;
+
+        Self::push_waiting(&mut conn, &job, &job_json).await
+    }
+
+    /// Atomically moves the next job into this worker's processing list so it
+    /// survives a crash until it is acknowledged.
+    pub async fn dequeue(&self, worker_id: usize) -> BotResult<Option<ReservedJob>> {
+        let mut conn = self.database.get_redis_connection().await?;
+        let processing_key = Self::processing_key(worker_id);
+        let job_json: Option<String> = conn.brpoplpush(QUEUE_KEY, &processing_key, 1.0).await?;
+
+        let payload = match job_json {
+            Some(json) => json,
+            None => return Ok(None),
+        };
+
+        match Self::deserialize(&payload) {
+            Ok(job) => {
+                let _: () = redis::pipe()
+                    .zrem(WAITING_KEY, job.id.to_string()).ignore()
+                    .zrem(Self::user_waiting_key(job.discord_id), job.id.to_string()).ignore()
+                    .query_async(&mut conn)
+                    .await?;
+                Ok(Some(ReservedJob { job, payload, processing_key }))
+            }
+            Err(e) => {
+                // Unreadable payloads can never succeed, park them straight away
+                let _: () = redis::pipe()
+                    .atomic()
+                    .lrem(&processing_key, 1, &payload)
+                    .lpush(DEAD_KEY, &payload)
+                    .query_async(&mut conn)
+                    .await?;
+                Err(e)
+            }
+        }
+    }
+
+    pub async fn ack(&self, reserved: &ReservedJob) -> BotResult<()> {
+        let mut conn = self.database.get_redis_connection().await?;
+        let _: () = conn.lrem(&reserved.processing_key, 1, &reserved.payload).await?;
+        Ok(())
+    }
+
+    /// Remembers that a job completed so redeliveries of it are skipped.
+    pub async fn mark_processed(&self, job_id: Uuid) -> BotResult<()> {
+        let mut conn = self.database.get_redis_connection().await?;
+        let _: () = conn.set_ex(format!("{}:done:{}", QUEUE_KEY, job_id), 1, PROCESSED_TTL_SECS).await?;
         Ok(())
     }
 
-    pub async fn dequeue(&self) -> BotResult<Option<QueueJob>> {
+    pub async fn is_processed(&self, job_id: Uuid) -> BotResult<bool> {
+        let mut conn = self.database.get_redis_connection().await?;
+        let processed: bool = conn.exists(format!("{}:done:{}", QUEUE_KEY, job_id)).await?;
+        Ok(processed)
+    }
+
+    /// Records the failure and schedules the job to run again after `delay_secs`.
+    pub async fn retry(&self, reserved: &ReservedJob, error: &str, delay_secs: u64) -> BotResult<()> {
         let mut conn = self.database.get_redis_connection().await?;
-        let job_json: Option<String> = conn.brpop("job_queue", 1.0).await?;
-        
-        if let Some(json) = job_json {
-            let job: QueueJob = serde_json::from_str(&json)
-                .map_err(|e| BotError::InvalidInput(format!("Failed to deserialize job: {}", e)))?;
-            Ok(Some(job))
-        } else {
-            Ok(None)
+
+        let mut job = reserved.job.clone();
+        job.attempts += 1;
+        job.last_error = Some(error.to_string());
+        let ready_at = chrono::Utc::now().timestamp() + delay_secs as i64;
+
+        let _: () = redis::pipe()
+            .atomic()
+            .lrem(&reserved.processing_key, 1, &reserved.payload)
+            .zadd(DELAYED_KEY, Self::serialize(&job)?, ready_at)
+            .query_async(&mut conn)
+            .await?;
+        Ok(())
+    }
+
+    /// Records the failure and parks the job in the dead-letter list.
+    pub async fn dead_letter(&self, reserved: &ReservedJob, error: &str) -> BotResult<()> {
+        let mut conn = self.database.get_redis_connection().await?;
+
+        let mut job = reserved.job.clone();
+        job.attempts += 1;
+        job.last_error = Some(error.to_string());
+
+        let _: () = redis::pipe()
+            .atomic()
+            .lrem(&reserved.processing_key, 1, &reserved.payload)
+            .lpush(DEAD_KEY, Self::serialize(&job)?)
+            .query_async(&mut conn)
+            .await?;
+        Ok(())
+    }
+
+    /// Moves delayed retries whose backoff has elapsed back onto the queue.
+    pub async fn promote_delayed(&self) -> BotResult<usize> {
+        let mut conn = self.database.get_redis_connection().await?;
+        let now = chrono::Utc::now().timestamp();
+        let due: Vec<String> = conn.zrangebyscore_limit(DELAYED_KEY, "-inf", now, 0, 100).await?;
+
+        let mut promoted = 0;
+        for job_json in due {
+            // Only the worker that wins the ZREM gets to push the job back
+            let removed: usize = conn.zrem(DELAYED_KEY, &job_json).await?;
+            if removed == 1 {
+                match Self::deserialize(&job_json) {
+                    Ok(job) => Self::push_waiting(&mut conn, &job, &job_json).await?,
+                    Err(_) => {
+                        let _: () = conn.lpush(DEAD_KEY, &job_json).await?;
+                        continue;
+                    }
+                }
+                promoted += 1;
+            }
         }
+
+        Ok(promoted)
     }
 
-    pub async fn get_queue_position(&self, discord_id: u64) -> BotResult<Option<usize>> {
+    /// Returns jobs left behind in a worker's processing list by a crash to the queue.
+    pub async fn recover(&self, worker_id: usize) -> BotResult<usize> {
         let mut conn = self.database.get_redis_connection().await?;
-        let queue: Vec<String> = conn.lrange("job_queue", 0, -1).await?;
-        
-        for (index, job_json) in queue.iter().enumerate() {
-            if let Ok(job) = serde_json::from_str::<QueueJob>(job_json) {
-                if job.discord_id == discord_id {
-                    return Ok(Some(index + 1));
+        let processing_key = Self::processing_key(worker_id);
+
+        let mut recovered = 0;
+        loop {
+            let job_json: Option<String> = conn.rpop(&processing_key, None).await?;
+            let job_json = match job_json {
+                Some(json) => json,
+                None => break,
+            };
+
+            match Self::deserialize(&job_json) {
+                Ok(job) => Self::push_waiting(&mut conn, &job, &job_json).await?,
+                Err(_) => {
+                    let _: () = conn.lpush(DEAD_KEY, &job_json).await?;
+                    continue;
                 }
             }
+            recovered += 1;
+        }
+
+        Ok(recovered)
+    }
+
+    pub async fn get_dead_jobs(&self, limit: isize) -> BotResult<Vec<QueueJob>> {
+        let mut conn = self.database.get_redis_connection().await?;
+        let dead: Vec<String> = conn.lrange(DEAD_KEY, 0, limit - 1).await?;
+
+        Ok(dead.iter()
+            .filter_map(|job_json| Self::deserialize(job_json).ok())
+            .collect())
+    }
+
+    pub async fn get_dead_length(&self) -> BotResult<usize> {
+        let mut conn = self.database.get_redis_connection().await?;
+        let length: usize = conn.llen(DEAD_KEY).await?;
+        Ok(length)
+    }
+
+    /// Moves a dead job back onto the queue with a fresh attempt counter.
+    pub async fn requeue_dead(&self, job_id: Uuid) -> BotResult<bool> {
+        let mut conn = self.database.get_redis_connection().await?;
+        let dead: Vec<String> = conn.lrange(DEAD_KEY, 0, -1).await?;
+
+        for job_json in dead {
+            let mut job = match Self::deserialize(&job_json) {
+                Ok(job) if job.id == job_id => job,
+                _ => continue,
+            };
+
+            let removed: usize = conn.lrem(DEAD_KEY, 1, &job_json).await?;
+            if removed == 0 {
+                return Ok(false);
+            }
+
+            job.attempts = 0;
+            Self::push_waiting(&mut conn, &job, &Self::serialize(&job)?).await?;
+            return Ok(true);
+        }
+
+        Ok(false)
+    }
+
+    /// 1-based position of the user's earliest waiting job.
+    pub async fn get_queue_position(&self, discord_id: u64) -> BotResult<Option<usize>> {
+        let mut conn = self.database.get_redis_connection().await?;
+        let first: Vec<String> = conn.zrange(Self::user_waiting_key(discord_id), 0, 0).await?;
+
+        match first.first() {
+            Some(job_id) => {
+                let rank: Option<usize> = conn.zrank(WAITING_KEY, job_id).await?;
+                Ok(rank.map(|rank| rank + 1))
+            }
+            None => Ok(None),
         }
-        
-        Ok(None)
+    }
+
+    /// 1-based position of a specific job, or `None` once a worker has picked it up.
+    pub async fn get_job_position(&self, job_id: Uuid) -> BotResult<Option<usize>> {
+        let mut conn = self.database.get_redis_connection().await?;
+        let rank: Option<usize> = conn.zrank(WAITING_KEY, job_id.to_string()).await?;
+        Ok(rank.map(|rank| rank + 1))
+    }
+
+    pub async fn record_duration(&self, duration: std::time::Duration) -> BotResult<()> {
+        let mut conn = self.database.get_redis_connection().await?;
+        let _: () = redis::pipe()
+            .lpush(DURATIONS_KEY, duration.as_millis() as u64).ignore()
+            .ltrim(DURATIONS_KEY, 0, DURATION_SAMPLES - 1).ignore()
+            .query_async(&mut conn)
+            .await?;
+        Ok(())
+    }
+
+    /// Rolling average of the most recent job durations, in seconds.
+    pub async fn get_average_duration(&self) -> BotResult<f64> {
+        let mut conn = self.database.get_redis_connection().await?;
+        let samples: Vec<u64> = conn.lrange(DURATIONS_KEY, 0, -1).await?;
+
+        if samples.is_empty() {
+            return Ok(DEFAULT_JOB_SECS);
+        }
+
+        let total: u64 = samples.iter().sum();
+        Ok(total as f64 / samples.len() as f64 / 1000.0)
+    }
+
+    /// Estimated seconds until a job at `position` is picked up by one of `workers` workers.
+    pub async fn estimate_wait(&self, position: usize, workers: usize) -> BotResult<u64> {
+        let average = self.get_average_duration().await?;
+        let rounds = (position + workers.max(1) - 1) / workers.max(1);
+        Ok((rounds as f64 * average).ceil() as u64)
     }
 
     pub async fn get_queue_length(&self) -> BotResult<usize> {
         let mut conn = self.database.get_redis_connection().await?;
-        let length: usize = conn.llen("job_queue").await?;
+        let length: usize = conn.llen(QUEUE_KEY).await?;
         Ok(length)
     }
 }
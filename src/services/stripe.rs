@@ -1,25 +1,411 @@
-// Temporarily disabled due to dependency issues
-use crate::config::Config;
+use hyper::HeaderMap;
+use reqwest::Client;
+use serde::Deserialize;
+use tracing::warn;
+use crate::config::{CoinPackage, Config};
 use crate::errors::{BotError, BotResult};
+use crate::services::{verify_hmac_sha256, Checkout, PaymentEvent, PaymentProvider, PaymentStatus};
 
+/// Stripe Checkout over its REST API.
+///
+/// `STRIPE_API_URL` can point this at a local stripe-mock instead of api.stripe.com.
 #[derive(Debug, Clone)]
 pub struct StripeService {
-    client: (), // Temporarily disabled
+    client: Client,
+    api_url: String,
+    secret_key: String,
+    webhook_secret: String,
+    currency: String,
+    success_url: String,
+    cancel_url: String,
+}
+
+/// How old a webhook's signature timestamp may be before it's rejected as a replay.
+const WEBHOOK_TOLERANCE_SECS: i64 = 300;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CheckoutSession {
+    pub id: String,
+    pub payment_intent: Option<String>,
+    pub url: Option<String>,
+    pub status: Option<String>,
+    pub payment_status: Option<String>,
+    pub client_reference_id: Option<String>,
+    pub amount_total: Option<i64>,
+}
+
+impl CheckoutSession {
+    pub fn is_paid(&self) -> bool {
+        self.payment_status.as_deref() == Some("paid")
+    }
+}
+
+/// A webhook event; `data.object` is parsed according to `kind`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct StripeEvent {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub data: StripeEventData,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct StripeEventData {
+    pub object: serde_json::Value,
+}
+
+impl StripeEvent {
+    pub fn object<T: serde::de::DeserializeOwned>(&self) -> BotResult<T> {
+        serde_json::from_value(self.data.object.clone())
+            .map_err(|e| BotError::Stripe(format!("Failed to parse {} event {}: {}", self.kind, self.id, e)))
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Charge {
+    pub id: String,
+    pub payment_intent: Option<String>,
+    pub amount: i64,
+    pub amount_refunded: i64,
+    pub refunded: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Dispute {
+    pub id: String,
+    pub charge: Option<String>,
+    pub payment_intent: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Refund {
+    pub id: String,
+    pub status: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct StripeErrorResponse {
+    error: StripeErrorBody,
+}
+
+#[derive(Debug, Deserialize)]
+struct StripeErrorBody {
+    message: Option<String>,
 }
 
 impl StripeService {
-    pub fn new(_config: &Config) -> Self {
-        // Temporarily disabled
-        Self { client: () }
+    pub fn new(config: &Config) -> Self {
+        Self {
+            client: Client::new(),
+            api_url: config.stripe_api_url.trim_end_matches('/').to_string(),
+            secret_key: config.stripe_secret_key.clone(),
+            webhook_secret: config.stripe_webhook_secret.clone(),
+            currency: config.payment_currency.clone(),
+            success_url: config.payment_success_url.clone(),
+            cancel_url: config.payment_cancel_url.clone(),
+        }
+    }
+
+    /// Creates a one-off Checkout Session selling `package` to a Discord user.
+    ///
+    /// `reference` is sent as the idempotency key and in the session metadata, so
+    /// a retried request can't create a second session for the same order.
+    pub async fn create_checkout_session(&self, package: &CoinPackage, discord_id: u64, reference: &str) -> BotResult<CheckoutSession> {
+        let form = [
+            ("mode", "payment".to_string()),
+            ("success_url", self.success_url.clone()),
+            ("cancel_url", self.cancel_url.clone()),
+            ("client_reference_id", discord_id.to_string()),
+            ("line_items[0][quantity]", "1".to_string()),
+            ("line_items[0][price_data][currency]", self.currency.clone()),
+            ("line_items[0][price_data][unit_amount]", package.price_cents.to_string()),
+            ("line_items[0][price_data][product_data][name]", package.name.clone()),
+            ("metadata[discord_id]", discord_id.to_string()),
+            ("metadata[package_id]", package.id.clone()),
+            ("metadata[coins]", package.coins.to_string()),
+            ("metadata[reference]", reference.to_string()),
+        ];
+
+        let response = self.client
+            .post(format!("{}/v1/checkout/sessions", self.api_url))
+            .bearer_auth(&self.secret_key)
+            .header("Idempotency-Key", reference)
+            .form(&form)
+            .send()
+            .await?;
+
+        Self::parse(response, "create checkout session").await
+    }
+
+    pub async fn get_session(&self, session_id: &str) -> BotResult<CheckoutSession> {
+        let response = self.client
+            .get(format!("{}/v1/checkout/sessions/{}", self.api_url, session_id))
+            .bearer_auth(&self.secret_key)
+            .send()
+            .await?;
+
+        Self::parse(response, "fetch checkout session").await
+    }
+
+    /// Refunds a payment intent in full. The idempotency key makes repeated
+    /// calls return the first refund instead of failing.
+    pub async fn create_refund(&self, payment_intent: &str) -> BotResult<Refund> {
+        let response = self.client
+            .post(format!("{}/v1/refunds", self.api_url))
+            .bearer_auth(&self.secret_key)
+            .header("Idempotency-Key", format!("refund-{}", payment_intent))
+            .form(&[("payment_intent", payment_intent)])
+            .send()
+            .await?;
+
+        Self::parse(response, "create refund").await
+    }
+
+    /// Checks a webhook's `Stripe-Signature` header against the raw body and
+    /// parses the event. Fails on a bad signature or a stale timestamp.
+    pub fn verify_event(&self, payload: &[u8], signature_header: &str) -> BotResult<StripeEvent> {
+        if self.webhook_secret.is_empty() {
+            return Err(BotError::Stripe("STRIPE_WEBHOOK_SECRET is not set".to_string()));
+        }
+
+        let mut timestamp = None;
+        let mut signatures = Vec::new();
+        for part in signature_header.split(',') {
+            match part.trim().split_once('=') {
+                Some(("t", value)) => timestamp = value.parse::<i64>().ok(),
+                Some(("v1", value)) => signatures.push(value),
+                _ => {}
+            }
+        }
+
+        let timestamp = timestamp
+            .ok_or_else(|| BotError::Stripe("Webhook signature has no timestamp".to_string()))?;
+        if (chrono::Utc::now().timestamp() - timestamp).abs() > WEBHOOK_TOLERANCE_SECS {
+            return Err(BotError::Stripe("Webhook timestamp is outside the tolerance".to_string()));
+        }
+
+        let signed = [timestamp.to_string().as_bytes(), b".", payload].concat();
+        if !signatures.iter().any(|signature| verify_hmac_sha256(&self.webhook_secret, &signed, signature)) {
+            return Err(BotError::Stripe("Webhook signature does not match".to_string()));
+        }
+
+        serde_json::from_slice(payload)
+            .map_err(|e| BotError::Stripe(format!("Failed to parse webhook event: {}", e)))
     }
 
-    pub async fn create_checkout_session(&self, _amount_cents: i64, _coins: i64, _discord_id: u64) -> BotResult<()> {
-        // Temporarily disabled
-        Err(BotError::Stripe("Stripe service temporarily disabled".to_string()))
+    async fn parse<T: serde::de::DeserializeOwned>(response: reqwest::Response, action: &str) -> BotResult<T> {
+        if !response.status().is_success() {
+            let status = response.status();
+            let error_text = response.text().await.unwrap_or_default();
+            let message = serde_json::from_str::<StripeErrorResponse>(&error_text)
+                .ok()
+                .and_then(|error| error.error.message)
+                .unwrap_or(error_text);
+            return Err(BotError::Stripe(format!("Failed to {} ({}): {}", action, status.as_u16(), message)));
+        }
+
+        response.json().await
+            .map_err(|e| BotError::Stripe(format!("Failed to parse {} response: {}", action, e)))
     }
+}
+
+#[serenity::async_trait]
+impl PaymentProvider for StripeService {
+    fn name(&self) -> &'static str {
+        "stripe"
+    }
+
+    async fn create_checkout(&self, package: &CoinPackage, discord_id: u64, reference: &str) -> BotResult<Checkout> {
+        let session = self.create_checkout_session(package, discord_id, reference).await?;
+        Ok(Checkout {
+            id: session.id,
+            url: session.url,
+        })
+    }
+
+    async fn verify_webhook(&self, headers: &HeaderMap, payload: &[u8]) -> BotResult<PaymentEvent> {
+        let signature = headers.get("Stripe-Signature")
+            .and_then(|value| value.to_str().ok())
+            .ok_or_else(|| BotError::Stripe("Missing Stripe-Signature header".to_string()))?;
+        let event = self.verify_event(payload, signature)?;
+
+        match event.kind.as_str() {
+            "checkout.session.completed" | "checkout.session.async_payment_succeeded" => {
+                let session: CheckoutSession = event.object()?;
+                // Delayed payment methods complete the session before the money arrives
+                if !session.is_paid() {
+                    return Ok(PaymentEvent::Ignored);
+                }
+                Ok(PaymentEvent::Paid { checkout_id: session.id, payment_id: session.payment_intent })
+            }
+            "checkout.session.expired" | "checkout.session.async_payment_failed" => {
+                let session: CheckoutSession = event.object()?;
+                Ok(PaymentEvent::Closed { checkout_id: session.id })
+            }
+            "charge.refunded" => {
+                let charge: Charge = event.object()?;
+                if !charge.refunded {
+                    // Partial refunds are left to admins to settle
+                    warn!("Charge {} was partially refunded ({} of {}), not reversing its order", charge.id, charge.amount_refunded, charge.amount);
+                    return Ok(PaymentEvent::Ignored);
+                }
+                Ok(charge.payment_intent
+                    .map(|payment_id| PaymentEvent::Refunded { payment_id })
+                    .unwrap_or(PaymentEvent::Ignored))
+            }
+            "charge.dispute.created" => {
+                let dispute: Dispute = event.object()?;
+                if dispute.payment_intent.is_none() {
+                    warn!("Dispute {} on charge {:?} has no payment intent", dispute.id, dispute.charge);
+                }
+                Ok(dispute.payment_intent
+                    .map(|payment_id| PaymentEvent::Disputed { payment_id })
+                    .unwrap_or(PaymentEvent::Ignored))
+            }
+            _ => Ok(PaymentEvent::Ignored),
+        }
+    }
+
+    async fn get_payment(&self, checkout_id: &str) -> BotResult<PaymentStatus> {
+        let session = self.get_session(checkout_id).await?;
+        Ok(match session.status.as_deref() {
+            Some("complete") if session.is_paid() => PaymentStatus::Paid { payment_id: session.payment_intent },
+            Some("expired") => PaymentStatus::Closed,
+            _ => PaymentStatus::Pending,
+        })
+    }
+
+    async fn refund(&self, payment_id: &str) -> BotResult<()> {
+        self.create_refund(payment_id).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hmac::{Hmac, Mac};
+    use sha2::Sha256;
+    use super::*;
+    use crate::test_support::{self, StubServer};
+
+    const EVENT: &str = r#"{"id":"evt_1","type":"checkout.session.completed","data":{"object":{"id":"cs_1","payment_intent":"pi_1","payment_status":"paid"}}}"#;
+
+    fn service(api_url: &str) -> StripeService {
+        let mut config = test_support::config();
+        config.stripe_api_url = format!("{}/", api_url);
+        StripeService::new(&config)
+    }
+
+    fn sign(payload: &str, timestamp: i64) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(b"whsec_test").unwrap();
+        mac.update(format!("{}.{}", timestamp, payload).as_bytes());
+        format!("t={},v1={}", timestamp, hex::encode(mac.finalize().into_bytes()))
+    }
+
+    fn package() -> CoinPackage {
+        CoinPackage {
+            id: "small".to_string(),
+            name: "500 Coins".to_string(),
+            coins: 500,
+            price_cents: 499,
+            enabled: true,
+        }
+    }
+
+    #[tokio::test]
+    async fn checkout_session_request() {
+        let stub = StubServer::start(|_| (200, serde_json::json!({
+            "id": "cs_1",
+            "url": "https://checkout.stripe.com/c/pay/cs_1",
+            "status": "open",
+        })));
+
+        let session = service(&stub.url).create_checkout_session(&package(), 42, "order-1").await.unwrap();
+        assert_eq!(session.id, "cs_1");
+        assert_eq!(session.url.as_deref(), Some("https://checkout.stripe.com/c/pay/cs_1"));
+
+        let requests = stub.requests();
+        assert_eq!(requests.len(), 1);
+        let request = &requests[0];
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, "/v1/checkout/sessions");
+        assert_eq!(request.header("authorization"), Some("Bearer sk_test"));
+        assert_eq!(request.header("idempotency-key"), Some("order-1"));
+
+        let form = request.form();
+        assert_eq!(form["mode"], "payment");
+        assert_eq!(form["client_reference_id"], "42");
+        assert_eq!(form["success_url"], "https://example.com/payment/success");
+        assert_eq!(form["line_items[0][price_data][currency]"], "usd");
+        assert_eq!(form["line_items[0][price_data][unit_amount]"], "499");
+        assert_eq!(form["line_items[0][price_data][product_data][name]"], "500 Coins");
+        assert_eq!(form["metadata[package_id]"], "small");
+        assert_eq!(form["metadata[coins]"], "500");
+        assert_eq!(form["metadata[reference]"], "order-1");
+    }
+
+    #[tokio::test]
+    async fn checkout_session_error() {
+        let stub = StubServer::start(|_| (400, serde_json::json!({
+            "error": { "message": "Invalid currency: xyz" },
+        })));
+
+        let error = service(&stub.url).create_checkout_session(&package(), 42, "order-1").await.unwrap_err();
+        assert!(error.to_string().contains("Invalid currency: xyz"), "{}", error);
+    }
+
+    #[test]
+    fn valid_signature() {
+        let now = chrono::Utc::now().timestamp();
+        let event = service("").verify_event(EVENT.as_bytes(), &sign(EVENT, now)).unwrap();
+        assert_eq!(event.id, "evt_1");
+        assert_eq!(event.kind, "checkout.session.completed");
+    }
+
+    #[test]
+    fn any_matching_signature_is_accepted() {
+        let now = chrono::Utc::now().timestamp();
+        // Stripe sends one v1 signature per active secret while a secret is being rolled
+        let header = format!("t={},v1={},{}", now, "00".repeat(32), sign(EVENT, now).split_once(',').unwrap().1);
+        assert!(service("").verify_event(EVENT.as_bytes(), &header).is_ok());
+    }
+
+    #[test]
+    fn tampered_body() {
+        let now = chrono::Utc::now().timestamp();
+        let tampered = EVENT.replace("cs_1", "cs_2");
+        let error = service("").verify_event(tampered.as_bytes(), &sign(EVENT, now)).unwrap_err();
+        assert!(error.to_string().contains("does not match"), "{}", error);
+    }
+
+    #[test]
+    fn stale_timestamp() {
+        let stale = chrono::Utc::now().timestamp() - WEBHOOK_TOLERANCE_SECS - 60;
+        let error = service("").verify_event(EVENT.as_bytes(), &sign(EVENT, stale)).unwrap_err();
+        assert!(error.to_string().contains("tolerance"), "{}", error);
+
+        let future = chrono::Utc::now().timestamp() + WEBHOOK_TOLERANCE_SECS + 60;
+        assert!(service("").verify_event(EVENT.as_bytes(), &sign(EVENT, future)).is_err());
+    }
+
+    #[test]
+    fn missing_timestamp() {
+        let header = sign(EVENT, chrono::Utc::now().timestamp());
+        let header = header.split_once(',').unwrap().1;
+        assert!(service("").verify_event(EVENT.as_bytes(), header).is_err());
+    }
+
+    #[tokio::test]
+    async fn webhook_maps_paid_session() {
+        let mut headers = HeaderMap::new();
+        headers.insert("Stripe-Signature", sign(EVENT, chrono::Utc::now().timestamp()).parse().unwrap());
 
-    pub async fn get_session(&self, _session_id: &str) -> BotResult<()> {
-        // Temporarily disabled
-        Err(BotError::Stripe("Stripe service temporarily disabled".to_string()))
+        match service("").verify_webhook(&headers, EVENT.as_bytes()).await.unwrap() {
+            PaymentEvent::Paid { checkout_id, payment_id } => {
+                assert_eq!(checkout_id, "cs_1");
+                assert_eq!(payment_id.as_deref(), Some("pi_1"));
+            }
+            other => panic!("expected a paid event, got {:?}", other),
+        }
     }
 }
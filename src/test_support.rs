@@ -0,0 +1,239 @@
+//! Fixtures shared by the unit tests.
+//!
+//! External APIs (Stripe, PayPal, the Pterodactyl panel) are replaced by a
+//! [`StubServer`] on a local port. Tests that need MongoDB and Redis are
+//! `#[ignore]`d; run them with `cargo test -- --ignored` once `TEST_MONGODB_URI`
+//! and `TEST_REDIS_URI` point at throwaway instances.
+
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use hmac::{Hmac, Mac};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, HeaderMap, Request, Response, Server};
+use sha2::Sha256;
+use crate::config::{Config, NodeSelectionPolicy, StoreConfig, TemplateConfig};
+use crate::models::User;
+use crate::services::Database;
+
+/// A config that needs nothing from the environment. External URLs point
+/// nowhere until a test aims them at a [`StubServer`].
+pub fn config() -> Config {
+    Config {
+        discord_token: String::new(),
+        discord_app_id: 0,
+        pterodactyl_url: "http://127.0.0.1:9".to_string(),
+        pterodactyl_api_key: "ptla_test".to_string(),
+        pterodactyl_client_api_key: String::new(),
+        mongodb_uri: std::env::var("TEST_MONGODB_URI").unwrap_or_default(),
+        redis_uri: std::env::var("TEST_REDIS_URI").unwrap_or_default(),
+        stripe_secret_key: "sk_test".to_string(),
+        stripe_public_key: "pk_test".to_string(),
+        stripe_api_url: "http://127.0.0.1:9".to_string(),
+        stripe_webhook_secret: "whsec_test".to_string(),
+        paypal_api_url: "http://127.0.0.1:9".to_string(),
+        paypal_client_id: "paypal-client".to_string(),
+        paypal_client_secret: "paypal-secret".to_string(),
+        paypal_webhook_id: "WH-TEST".to_string(),
+        manual_payment_secret: "manual-secret".to_string(),
+        payment_providers: vec!["manual".to_string()],
+        payment_currency: "usd".to_string(),
+        payment_success_url: "https://example.com/payment/success".to_string(),
+        payment_cancel_url: "https://example.com/payment/cancel".to_string(),
+        webhook_bind_addr: "127.0.0.1:0".to_string(),
+        order_reconcile_after_minutes: 30,
+        order_reconcile_interval_secs: 600,
+        afk_page_url: "https://example.com/afk".to_string(),
+        linkvertise_verify_url: "https://example.com/verify".to_string(),
+        admin_discord_ids: Vec::new(),
+        enable_transfer: true,
+        enable_renew: true,
+        enable_delete: true,
+        refund_unused_days: false,
+        queue_workers: 1,
+        queue_max_attempts: 3,
+        queue_retry_base_secs: 10,
+        expiry_warning_days: 3,
+        expiry_grace_days: 7,
+        over_allocation_grace_days: 3,
+        expiry_sweep_interval_secs: 300,
+        console_tail_secs: 10,
+        backup_schedule_interval_secs: 900,
+        pterodactyl_user_email_domain: "users.shaden-rs.local".to_string(),
+        pterodactyl_send_reset_link: false,
+        node_selection: NodeSelectionPolicy::LeastLoaded,
+        node_locations: Vec::new(),
+        node_pins: HashMap::new(),
+        store_config: StoreConfig::default(),
+        template_config: TemplateConfig::default(),
+    }
+}
+
+/// The database for tests marked `#[ignore = "needs TEST_MONGODB_URI/TEST_REDIS_URI"]`,
+/// which run with `cargo test -- --ignored`.
+pub async fn database(config: &Config) -> Database {
+    assert!(
+        !config.mongodb_uri.is_empty() && !config.redis_uri.is_empty(),
+        "TEST_MONGODB_URI and TEST_REDIS_URI must be set to run database tests",
+    );
+    Database::new(config).await.expect("failed to connect to the test database")
+}
+
+/// A Discord user ID no other test run will have used.
+pub fn discord_id() -> u64 {
+    uuid::Uuid::new_v4().as_u128() as u64 >> 1
+}
+
+/// A new user in the test database, with no coins or resources.
+pub struct Fixture {
+    pub config: Config,
+    pub database: Database,
+    pub discord_id: u64,
+}
+
+impl Fixture {
+    pub async fn new(config: Config) -> Self {
+        let database = database(&config).await;
+        let discord_id = discord_id();
+        database.create_user(discord_id).await.unwrap();
+        Self { config, database, discord_id }
+    }
+
+    pub async fn user(&self) -> User {
+        self.database.get_user(self.discord_id).await.unwrap().expect("test user is gone")
+    }
+}
+
+/// Tests sharing the Redis job queue take this so they don't pick up each other's jobs.
+pub static QUEUE_LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+/// Headers signing `payload` as a `/webhooks/manual` call with the test config's secret.
+pub fn manual_signature(payload: &str) -> HeaderMap {
+    let mut mac = Hmac::<Sha256>::new_from_slice(b"manual-secret").unwrap();
+    mac.update(payload.as_bytes());
+    let mut headers = HeaderMap::new();
+    headers.insert("X-Signature", hex::encode(mac.finalize().into_bytes()).parse().unwrap());
+    headers
+}
+
+/// A request the stub server received.
+#[derive(Debug, Clone)]
+pub struct StubRequest {
+    pub method: String,
+    pub path: String,
+    pub headers: HeaderMap,
+    pub body: Vec<u8>,
+}
+
+impl StubRequest {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name).and_then(|value| value.to_str().ok())
+    }
+
+    pub fn json(&self) -> serde_json::Value {
+        serde_json::from_slice(&self.body).expect("request body is not JSON")
+    }
+
+    /// The body decoded as `application/x-www-form-urlencoded`.
+    pub fn form(&self) -> HashMap<String, String> {
+        String::from_utf8_lossy(&self.body)
+            .split('&')
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| {
+                let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+                (decode_form(key), decode_form(value))
+            })
+            .collect()
+    }
+}
+
+fn decode_form(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' if i + 2 < bytes.len() => {
+                let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).unwrap_or("");
+                match u8::from_str_radix(hex, 16) {
+                    Ok(byte) => {
+                        decoded.push(byte);
+                        i += 2;
+                    }
+                    Err(_) => decoded.push(b'%'),
+                }
+            }
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+type Handler = dyn Fn(&StubRequest) -> (u16, serde_json::Value) + Send + Sync;
+
+/// An HTTP server on a local port that answers every request with `handler`
+/// and records what it was sent.
+pub struct StubServer {
+    pub url: String,
+    requests: Arc<Mutex<Vec<StubRequest>>>,
+}
+
+impl StubServer {
+    pub fn start<F>(handler: F) -> Self
+    where
+        F: Fn(&StubRequest) -> (u16, serde_json::Value) + Send + Sync + 'static,
+    {
+        let handler: Arc<Handler> = Arc::new(handler);
+        let requests = Arc::new(Mutex::new(Vec::new()));
+
+        let recorded = requests.clone();
+        let make_service = make_service_fn(move |_| {
+            let handler = handler.clone();
+            let recorded = recorded.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request: Request<Body>| {
+                    let handler = handler.clone();
+                    let recorded = recorded.clone();
+                    async move {
+                        let (parts, body) = request.into_parts();
+                        let body = hyper::body::to_bytes(body).await.unwrap_or_default().to_vec();
+                        let request = StubRequest {
+                            method: parts.method.to_string(),
+                            path: parts.uri.path().to_string(),
+                            headers: parts.headers,
+                            body,
+                        };
+
+                        let (status, response) = handler(&request);
+                        recorded.lock().unwrap().push(request);
+
+                        let response = Response::builder()
+                            .status(status)
+                            .header("Content-Type", "application/json")
+                            .body(Body::from(response.to_string()))
+                            .unwrap();
+                        Ok::<_, Infallible>(response)
+                    }
+                }))
+            }
+        });
+
+        let server = Server::bind(&SocketAddr::from(([127, 0, 0, 1], 0))).serve(make_service);
+        let url = format!("http://{}", server.local_addr());
+        tokio::spawn(server);
+
+        Self { url, requests }
+    }
+
+    pub fn requests(&self) -> Vec<StubRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    /// The recorded requests to paths starting with `path`.
+    pub fn requests_to(&self, path: &str) -> Vec<StubRequest> {
+        self.requests().into_iter().filter(|request| request.path.starts_with(path)).collect()
+    }
+}
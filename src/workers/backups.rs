@@ -0,0 +1,78 @@
+use std::sync::Arc;
+use std::time::Duration;
+use chrono::Utc;
+use serenity::http::Http;
+use tracing::{error, warn};
+use crate::config::Config;
+use crate::errors::BotResult;
+use crate::services::{BackupService, Database, PterodactylClient};
+
+/// Takes the automatic backups owners scheduled with `/servers backups schedule`.
+#[derive(Clone)]
+pub struct BackupScheduler {
+    database: Database,
+    pterodactyl: PterodactylClient,
+    config: Config,
+    http: Arc<Http>,
+}
+
+impl BackupScheduler {
+    pub fn new(database: Database, pterodactyl: PterodactylClient, config: Config, http: Arc<Http>) -> Self {
+        Self {
+            database,
+            pterodactyl,
+            config,
+            http,
+        }
+    }
+
+    pub async fn run(&self) {
+        let mut interval = tokio::time::interval(Duration::from_secs(self.config.backup_schedule_interval_secs.max(1)));
+
+        loop {
+            interval.tick().await;
+            if let Err(e) = self.sweep().await {
+                error!("Backup schedule sweep failed: {}", e);
+            }
+        }
+    }
+
+    async fn sweep(&self) -> BotResult<()> {
+        let backups = BackupService::new(self.database.clone(), self.pterodactyl.clone());
+        let now = Utc::now();
+
+        for server in self.database.get_backup_scheduled_servers().await? {
+            if !server.backup_due(now) {
+                continue;
+            }
+
+            match backups.run_scheduled(&server, now).await {
+                Ok(Some(run)) => {
+                    if let Some(rotated) = run.rotated {
+                        super::notify(
+                            &self.http,
+                            server.discord_id,
+                            "💾 Backup Rotated",
+                            format!("To make room for the automatic backup of **{}**, its oldest backup **{}** was deleted.", server.name, rotated.name),
+                            0x3498db,
+                        ).await;
+                    }
+                }
+                Ok(None) => {}
+                // The run is still recorded, so a broken server is retried next interval rather than every sweep
+                Err(e) => {
+                    warn!("Automatic backup of server {} failed: {}", server.id, e);
+                    super::notify(
+                        &self.http,
+                        server.discord_id,
+                        "⚠️ Automatic Backup Failed",
+                        format!("The automatic backup of **{}** couldn't be taken: {}", server.name, e),
+                        0xffaa00,
+                    ).await;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
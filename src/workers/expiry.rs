@@ -0,0 +1,168 @@
+use std::sync::Arc;
+use std::time::Duration;
+use chrono::Utc;
+use serenity::http::Http;
+use tracing::{info, warn, error};
+use crate::config::Config;
+use crate::errors::BotResult;
+use crate::models::ServerStatus;
+use crate::services::{Database, LifecycleService, PterodactylClient};
+
+/// Warns owners about expiring servers, suspends expired ones and deletes them
+/// once their grace period is over.
+#[derive(Clone)]
+pub struct ExpirySweeper {
+    database: Database,
+    pterodactyl: PterodactylClient,
+    config: Config,
+    http: Arc<Http>,
+}
+
+impl ExpirySweeper {
+    pub fn new(database: Database, pterodactyl: PterodactylClient, config: Config, http: Arc<Http>) -> Self {
+        Self {
+            database,
+            pterodactyl,
+            config,
+            http,
+        }
+    }
+
+    pub async fn run(&self) {
+        let mut interval = tokio::time::interval(Duration::from_secs(self.config.expiry_sweep_interval_secs.max(1)));
+
+        loop {
+            interval.tick().await;
+            if let Err(e) = self.sweep().await {
+                error!("Expiry sweep failed: {}", e);
+            }
+        }
+    }
+
+    async fn sweep(&self) -> BotResult<()> {
+        let lifecycle = LifecycleService::new(self.database.clone(), self.pterodactyl.clone());
+        let now = Utc::now();
+
+        // Finish deletions a previous run was interrupted in the middle of
+        for server in self.database.get_deleted_servers().await? {
+            if let Err(e) = lifecycle.finish_deletion(&server).await {
+                error!("Failed to finish deleting server {}: {}", server.id, e);
+            }
+        }
+
+        // Auto-renewal is attempted from the time the warning would go out; servers
+        // whose owner can't afford it fall through to the warning below
+        let warn_before = now + chrono::Duration::days(self.config.expiry_warning_days);
+        for server in self.database.get_auto_renew_due(warn_before).await? {
+            let plan = match self.config.store_config.plan(&server.plan) {
+                Some(plan) => plan,
+                None => {
+                    warn!("Server {} is on unknown plan '{}', skipping auto-renew", server.id, server.plan);
+                    continue;
+                }
+            };
+
+            // Free plans would renew forever, so they have to be renewed by hand
+            if plan.renewal_cost(1) == 0 || plan.duration_days == 0 {
+                continue;
+            }
+
+            // Renew at most one period ahead: on a plan shorter than the warning
+            // window a renewed server would still be due and renew again next sweep
+            let renew_before = warn_before.min(now + chrono::Duration::days(plan.duration_days as i64));
+            if server.expires_at > renew_before {
+                continue;
+            }
+
+            match lifecycle.auto_renew(&server, plan).await {
+                Ok(Some(renewal)) => {
+                    let mut description = format!(
+                        "Server **{}** was renewed for {} day(s).\n\nCharged: {} coins\nNew expiry: {}\nRemaining balance: {} coins",
+                        server.name,
+                        renewal.days,
+                        renewal.cost,
+                        renewal.expires_at.format("%Y-%m-%d %H:%M UTC"),
+                        renewal.balance
+                    );
+                    if renewal.unsuspended {
+                        description.push_str("\n\nThe server has been unsuspended.");
+                    }
+                    super::notify(&self.http, server.discord_id, "🧾 Server Auto-Renewed", description, 0x00ff00).await;
+                }
+                Ok(None) => {}
+                Err(e) => error!("Failed to auto-renew server {}: {}", server.id, e),
+            }
+        }
+
+        for server in self.database.get_servers_to_warn(warn_before).await? {
+            match lifecycle.claim_warning(&server).await {
+                Ok(true) => {
+                    let auto_renews = server.auto_renew && self.config.store_config.plan(&server.plan)
+                        .map_or(false, |plan| plan.renewal_cost(1) > 0);
+                    let hint = if auto_renews {
+                        "Auto-renew is on, but your balance is too low to cover the renewal. Top up your coins or renew it"
+                    } else {
+                        "Renew it"
+                    };
+                    super::notify(
+                        &self.http,
+                        server.discord_id,
+                        "⏰ Server Expiring Soon",
+                        format!(
+                            "Server **{}** expires on {}.\n\n{} with `/renew {}` to keep it running.",
+                            server.name,
+                            server.expires_at.format("%Y-%m-%d %H:%M UTC"),
+                            hint,
+                            server.id
+                        ),
+                        0xffaa00,
+                    ).await;
+                }
+                Ok(false) => {}
+                Err(e) => error!("Failed to warn about server {}: {}", server.id, e),
+            }
+        }
+
+        let active = [ServerStatus::Running, ServerStatus::Stopped];
+        for server in self.database.get_expired_servers(&active, now).await? {
+            match lifecycle.suspend_expired(&server, now).await {
+                Ok(true) => {
+                    super::notify(
+                        &self.http,
+                        server.discord_id,
+                        "⛔ Server Suspended",
+                        format!(
+                            "Server **{}** has expired and was suspended.\n\nRenew it with `/renew {}` within {} day(s) or it will be deleted.",
+                            server.name,
+                            server.id,
+                            self.config.expiry_grace_days
+                        ),
+                        0xff6b6b,
+                    ).await;
+                }
+                Ok(false) => {}
+                Err(e) => error!("Failed to suspend server {}: {}", server.id, e),
+            }
+        }
+
+        let grace_cutoff = now - chrono::Duration::days(self.config.expiry_grace_days);
+        for server in self.database.get_expired_servers(&[ServerStatus::Suspended], grace_cutoff).await? {
+            match lifecycle.delete_expired(&server, grace_cutoff).await {
+                Ok(true) => {
+                    info!("Deleted server {} after its grace period", server.id);
+                    super::notify(
+                        &self.http,
+                        server.discord_id,
+                        "🗑️ Server Deleted",
+                        format!("Server **{}** was not renewed and has been deleted.", server.name),
+                        0xff0000,
+                    ).await;
+                }
+                Ok(false) => {}
+                Err(e) => error!("Failed to delete server {}: {}", server.id, e),
+            }
+        }
+
+        Ok(())
+    }
+}
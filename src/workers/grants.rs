@@ -0,0 +1,140 @@
+use std::collections::BTreeSet;
+use std::sync::Arc;
+use std::time::Duration;
+use chrono::Utc;
+use serenity::http::Http;
+use tracing::error;
+use crate::config::Config;
+use crate::errors::BotResult;
+use crate::services::{Database, GrantService, LifecycleService, PterodactylClient};
+
+/// Takes expired timed grants back out of their owners' pools, flags servers
+/// the smaller pool no longer covers and suspends them if they stay flagged
+/// for `OVER_ALLOCATION_GRACE_DAYS`.
+#[derive(Clone)]
+pub struct GrantSweeper {
+    database: Database,
+    pterodactyl: PterodactylClient,
+    config: Config,
+    http: Arc<Http>,
+}
+
+impl GrantSweeper {
+    pub fn new(database: Database, pterodactyl: PterodactylClient, config: Config, http: Arc<Http>) -> Self {
+        Self {
+            database,
+            pterodactyl,
+            config,
+            http,
+        }
+    }
+
+    pub async fn run(&self) {
+        let mut interval = tokio::time::interval(Duration::from_secs(self.config.expiry_sweep_interval_secs.max(1)));
+
+        loop {
+            interval.tick().await;
+            if let Err(e) = self.sweep().await {
+                error!("Grant sweep failed: {}", e);
+            }
+        }
+    }
+
+    async fn sweep(&self) -> BotResult<()> {
+        let grants = GrantService::new(self.database.clone());
+
+        // Owners of already flagged servers are re-checked too, so flags clear
+        // once they buy more resources or delete a server
+        let mut owners: BTreeSet<u64> = self.database.get_over_allocated_owners().await?.into_iter().collect();
+
+        for grant in grants.revoke_expired(Utc::now()).await? {
+            owners.insert(grant.discord_id);
+
+            let name = self.config.store_config.items.iter()
+                .find(|item| item.id == grant.item_id)
+                .map(|item| item.name.as_str())
+                .unwrap_or(&grant.item_id);
+            super::notify(
+                &self.http,
+                grant.discord_id,
+                "⌛ Resources Expired",
+                format!("Your **{}** purchase has run out, so these resources were removed: {}", name, grant.resources.summary()),
+                0xffaa00,
+            ).await;
+        }
+
+        let lifecycle = LifecycleService::new(self.database.clone(), self.pterodactyl.clone());
+        let suspend_at = Utc::now() + chrono::Duration::days(self.config.over_allocation_grace_days);
+
+        for discord_id in owners {
+            let change = match grants.check_allocation(discord_id).await {
+                Ok(Some(change)) => change,
+                Ok(None) => continue,
+                Err(e) => {
+                    error!("Failed to check allocation for user {}: {}", discord_id, e);
+                    continue;
+                }
+            };
+
+            // Servers suspended for being over-allocated run again once covered
+            for server in &change.cleared {
+                match lifecycle.unsuspend_covered(server).await {
+                    Ok(true) => {
+                        super::notify(
+                            &self.http,
+                            discord_id,
+                            "✅ Server Unsuspended",
+                            format!("Your resources cover **{}** again, so it has been unsuspended.", server.name),
+                            0x00ff00,
+                        ).await;
+                    }
+                    Ok(false) => {}
+                    Err(e) => error!("Failed to unsuspend server {}: {}", server.id, e),
+                }
+            }
+
+            if change.flagged.is_empty() {
+                continue;
+            }
+
+            let servers = change.flagged.iter()
+                .map(|server| format!("• **{}** (`{}`)", server.name, server.id))
+                .collect::<Vec<_>>()
+                .join("\n");
+            super::notify(
+                &self.http,
+                discord_id,
+                "⚠️ Servers Over Your Resources",
+                format!(
+                    "Your servers now use more {} than you have. These servers will be suspended on {} unless you buy more resources or delete a server:\n\n{}",
+                    change.shortfall.join(", "),
+                    suspend_at.format("%Y-%m-%d %H:%M UTC"),
+                    servers
+                ),
+                0xff6b6b,
+            ).await;
+        }
+
+        let cutoff = Utc::now() - chrono::Duration::days(self.config.over_allocation_grace_days);
+        for server in self.database.get_over_allocation_due(cutoff).await? {
+            match lifecycle.suspend_over_allocated(&server, cutoff).await {
+                Ok(true) => {
+                    super::notify(
+                        &self.http,
+                        server.discord_id,
+                        "⛔ Server Suspended",
+                        format!(
+                            "Server **{}** was suspended because your resources no longer cover it. It is unsuspended once you buy more resources or delete another server.",
+                            server.name
+                        ),
+                        0xff6b6b,
+                    ).await;
+                }
+                Ok(false) => {}
+                Err(e) => error!("Failed to suspend over-allocated server {}: {}", server.id, e),
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,153 @@
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+use serenity::http::Http;
+use serenity::model::id::UserId;
+use tracing::{info, warn, error};
+use crate::config::Config;
+use crate::services::{Database, PterodactylClient};
+
+pub mod queue;
+pub mod expiry;
+pub mod webhooks;
+pub mod orders;
+pub mod grants;
+pub mod backups;
+
+#[cfg(not(test))]
+const RESTART_DELAY: Duration = Duration::from_secs(5);
+#[cfg(test)]
+const RESTART_DELAY: Duration = Duration::from_millis(10);
+
+pub fn spawn_all(database: Database, config: Config, http: Arc<Http>) {
+    let pterodactyl = PterodactylClient::new(&config);
+
+    for worker_id in 0..config.queue_workers {
+        let worker = queue::QueueWorker::new(
+            worker_id,
+            database.clone(),
+            pterodactyl.clone(),
+            config.clone(),
+            http.clone(),
+        );
+        supervise(format!("queue worker {}", worker_id), move || {
+            let worker = worker.clone();
+            async move { worker.run().await }
+        });
+    }
+
+    info!("Started {} queue worker(s)", config.queue_workers);
+
+    if config.payment_providers.is_empty() {
+        info!("No payment providers enabled, not starting the webhook listener or order reconciler");
+    } else {
+        let webhooks = webhooks::WebhookServer::new(database.clone(), config.clone(), http.clone());
+        supervise("webhook listener".to_string(), move || {
+            let webhooks = webhooks.clone();
+            async move { webhooks.run().await }
+        });
+
+        let reconciler = orders::OrderReconciler::new(database.clone(), config.clone(), http.clone());
+        supervise("order reconciler".to_string(), move || {
+            let reconciler = reconciler.clone();
+            async move { reconciler.run().await }
+        });
+    }
+
+    let grants = grants::GrantSweeper::new(database.clone(), pterodactyl.clone(), config.clone(), http.clone());
+    supervise("grant sweeper".to_string(), move || {
+        let grants = grants.clone();
+        async move { grants.run().await }
+    });
+
+    if pterodactyl.has_client_api() {
+        let backups = backups::BackupScheduler::new(database.clone(), pterodactyl.clone(), config.clone(), http.clone());
+        supervise("backup scheduler".to_string(), move || {
+            let backups = backups.clone();
+            async move { backups.run().await }
+        });
+    } else {
+        info!("No Pterodactyl client API key set, not starting the backup scheduler");
+    }
+
+    let sweeper = expiry::ExpirySweeper::new(database, pterodactyl, config, http);
+    supervise("expiry sweeper".to_string(), move || {
+        let sweeper = sweeper.clone();
+        async move { sweeper.run().await }
+    });
+}
+
+/// Runs a background task forever, restarting it whenever it returns or panics.
+pub fn supervise<F, Fut>(name: String, mut task: F)
+where
+    F: FnMut() -> Fut + Send + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    tokio::spawn(async move {
+        loop {
+            match tokio::spawn(task()).await {
+                Ok(()) => warn!("{} exited, restarting", name),
+                Err(e) => error!("{} crashed: {}, restarting", name, e),
+            }
+            tokio::time::sleep(RESTART_DELAY).await;
+        }
+    });
+}
+
+/// DMs a user an embed. Failures are logged, since users can have DMs closed.
+pub async fn notify(http: &Http, discord_id: u64, title: &str, description: String, color: u32) {
+    let result = async {
+        let channel = UserId(discord_id).create_dm_channel(http).await?;
+        channel.send_message(http, |message| {
+            message.embed(|embed| {
+                embed
+                    .title(title)
+                    .description(description)
+                    .color(color)
+            })
+        }).await?;
+        Ok::<(), serenity::Error>(())
+    }.await;
+
+    if let Err(e) = result {
+        warn!("Failed to notify user {}: {}", discord_id, e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::sync::mpsc;
+    use super::*;
+
+    #[tokio::test]
+    async fn supervise_restarts_tasks_that_exit_or_panic() {
+        let runs = Arc::new(AtomicUsize::new(0));
+        let (started, mut starts) = mpsc::unbounded_channel();
+
+        let counter = runs.clone();
+        supervise("test task".to_string(), move || {
+            let run = counter.fetch_add(1, Ordering::SeqCst) + 1;
+            let started = started.clone();
+            async move {
+                started.send(run).unwrap();
+                match run {
+                    1 => panic!("first run crashes"),
+                    2 => {}
+                    _ => std::future::pending().await,
+                }
+            }
+        });
+
+        for expected in 1..=3 {
+            let run = tokio::time::timeout(Duration::from_secs(5), starts.recv()).await
+                .expect("task was not restarted")
+                .unwrap();
+            assert_eq!(run, expected);
+        }
+
+        // The third run never finishes, so nothing restarts it
+        tokio::time::sleep(RESTART_DELAY * 5).await;
+        assert_eq!(runs.load(Ordering::SeqCst), 3);
+    }
+}
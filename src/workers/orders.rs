@@ -0,0 +1,82 @@
+use std::sync::Arc;
+use std::time::Duration;
+use serenity::http::Http;
+use tracing::{info, error};
+use crate::config::Config;
+use crate::errors::BotResult;
+use crate::models::Order;
+use crate::services::{Database, OrderService};
+
+/// Settles orders left pending because their provider's webhook never
+/// arrived, by asking the provider what happened to them.
+#[derive(Clone)]
+pub struct OrderReconciler {
+    orders: OrderService,
+    config: Config,
+    http: Arc<Http>,
+}
+
+impl OrderReconciler {
+    pub fn new(database: Database, config: Config, http: Arc<Http>) -> Self {
+        Self {
+            orders: OrderService::new(database, &config),
+            config,
+            http,
+        }
+    }
+
+    pub async fn run(&self) {
+        let mut interval = tokio::time::interval(Duration::from_secs(self.config.order_reconcile_interval_secs.max(1)));
+
+        loop {
+            interval.tick().await;
+            if let Err(e) = self.reconcile().await {
+                error!("Order reconciliation failed: {}", e);
+            }
+        }
+    }
+
+    async fn reconcile(&self) -> BotResult<()> {
+        let report = self.orders
+            .reconcile(chrono::Duration::minutes(self.config.order_reconcile_after_minutes))
+            .await?;
+
+        for order in &report.completed {
+            notify_completed(&self.http, order).await;
+        }
+
+        if !report.completed.is_empty() || report.cancelled > 0 {
+            info!(
+                "Reconciled orders: {} completed, {} cancelled, {} still pending, {} failed",
+                report.completed.len(),
+                report.cancelled,
+                report.pending,
+                report.failed
+            );
+        }
+
+        Ok(())
+    }
+}
+
+/// Tells a buyer their order went through.
+pub async fn notify_completed(http: &Http, order: &Order) {
+    super::notify(
+        http,
+        order.discord_id,
+        "✅ Payment Received",
+        format!("Thanks for your purchase! **{} coins** have been added to your balance.\n\nOrder: `{}`", order.coins, order.id),
+        0x00ff00,
+    ).await;
+}
+
+/// Tells a buyer their payment was `what` (refunded, disputed) and the coins taken back.
+pub async fn notify_reversed(http: &Http, order: &Order, what: &str) {
+    super::notify(
+        http,
+        order.discord_id,
+        "↩️ Payment Reversed",
+        format!("Your payment for order `{}` was {}, so **{} coins** have been removed from your balance.", order.id, what, order.coins),
+        0xff6b6b,
+    ).await;
+}
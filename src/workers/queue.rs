@@ -0,0 +1,531 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use serenity::http::Http;
+use tracing::{debug, info, warn, error};
+use crate::config::Config;
+use crate::errors::{BotError, BotResult};
+use crate::models::{CoinChange, ServerStatus, TransactionReason};
+use crate::services::{AccountService, Database, NodeSelector, PterodactylClient, QueueService, QueueJob, JobType, ReservedJob};
+
+const MAX_RETRY_DELAY_SECS: u64 = 3600;
+const HOLDING_STATUSES: [ServerStatus; 4] = [ServerStatus::Creating, ServerStatus::Running, ServerStatus::Stopped, ServerStatus::Suspended];
+
+/// Drains `job_queue` and carries out the panel side of each job.
+#[derive(Clone)]
+pub struct QueueWorker {
+    id: usize,
+    database: Database,
+    pterodactyl: PterodactylClient,
+    config: Config,
+    http: Arc<Http>,
+}
+
+impl QueueWorker {
+    pub fn new(id: usize, database: Database, pterodactyl: PterodactylClient, config: Config, http: Arc<Http>) -> Self {
+        Self {
+            id,
+            database,
+            pterodactyl,
+            config,
+            http,
+        }
+    }
+
+    pub async fn run(&self) {
+        let queue_service = QueueService::new(self.database.clone());
+
+        match queue_service.recover(self.id).await {
+            Ok(0) => {}
+            Ok(count) => warn!("Queue worker {} recovered {} unacknowledged job(s)", self.id, count),
+            Err(e) => error!("Queue worker {} failed to recover jobs: {}", self.id, e),
+        }
+
+        loop {
+            if let Err(e) = queue_service.promote_delayed().await {
+                error!("Queue worker {} failed to promote delayed jobs: {}", self.id, e);
+            }
+
+            match queue_service.dequeue(self.id).await {
+                Ok(Some(reserved)) => self.process(&queue_service, reserved).await,
+                Ok(None) => {}
+                Err(e) => {
+                    error!("Queue worker {} failed to dequeue: {}", self.id, e);
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                }
+            }
+        }
+    }
+
+    pub async fn process(&self, queue_service: &QueueService, reserved: ReservedJob) {
+        let job = &reserved.job;
+        info!("Queue worker {} processing {:?} job {} (attempt {})", self.id, job.job_type, job.id, job.attempts + 1);
+
+        match queue_service.is_processed(job.id).await {
+            Ok(true) => {
+                info!("Job {} was already processed, skipping", job.id);
+                if let Err(e) = queue_service.ack(&reserved).await {
+                    error!("Queue worker {} failed to settle job {}: {}", self.id, job.id, e);
+                }
+                return;
+            }
+            Ok(false) => {}
+            Err(e) => warn!("Failed to check whether job {} was processed: {}", job.id, e),
+        }
+
+        let started = Instant::now();
+        let result = match job.job_type {
+            JobType::CreateServer => self.create_server(job).await,
+            JobType::DeleteServer => self.delete_server(job).await,
+            JobType::Login => {
+                warn!("Ignoring unsupported {:?} job {}", job.job_type, job.id);
+                Ok(())
+            }
+        };
+
+        let outcome = match result {
+            Ok(()) => match queue_service.mark_processed(job.id).await {
+                Ok(()) if matches!(job.job_type, JobType::CreateServer) => {
+                    if let Err(e) = queue_service.record_duration(started.elapsed()).await {
+                        warn!("Failed to record duration of job {}: {}", job.id, e);
+                    }
+                    queue_service.ack(&reserved).await
+                }
+                Ok(()) => queue_service.ack(&reserved).await,
+                Err(e) => Err(e),
+            },
+            Err(e) if e.is_transient() && job.attempts + 1 < self.config.queue_max_attempts => {
+                let delay = self.retry_delay(job.attempts);
+                warn!("Job {} failed, retrying in {}s: {}", job.id, delay, e);
+                queue_service.retry(&reserved, &e.to_string(), delay).await
+            }
+            Err(e) => {
+                error!("Job {} failed permanently: {}", job.id, e);
+                self.on_failure(job, &e).await;
+                queue_service.dead_letter(&reserved, &e.to_string()).await
+            }
+        };
+
+        if let Err(e) = outcome {
+            error!("Queue worker {} failed to settle job {}: {}", self.id, job.id, e);
+        }
+    }
+
+    fn retry_delay(&self, attempts: u32) -> u64 {
+        let delay = self.config.queue_retry_base_secs.saturating_mul(1 << attempts.min(16));
+        delay.min(MAX_RETRY_DELAY_SECS)
+    }
+
+    async fn on_failure(&self, job: &QueueJob, error: &BotError) {
+        match job.job_type {
+            JobType::CreateServer => {
+                let server_id = match job_server_id(job) {
+                    Ok(server_id) => server_id,
+                    Err(_) => return,
+                };
+
+                // Only the transition into Failed releases resources, so a server
+                // that failed before (or was deleted meanwhile) isn't released twice
+                let server = match self.database.transition_server(&server_id, &HOLDING_STATUSES, ServerStatus::Failed).await {
+                    Ok(server) => server,
+                    Err(e) => {
+                        error!("Failed to mark server {} as failed: {}", server_id, e);
+                        None
+                    }
+                };
+
+                if let Some(server) = server {
+                    if server.holds_resources() {
+                        if let Err(e) = self.database.release_resources(server.discord_id, &server.resources).await {
+                            error!("Failed to release resources of server {}: {}", server.id, e);
+                        }
+                    }
+
+                    let mut description = format!("Server **{}** could not be created.\n\nError: {}", server.name, error);
+
+                    // Servers are paid for up front, so give the coins back
+                    if server.prepaid_coins > 0 {
+                        let change = CoinChange::new(TransactionReason::Refund).reference(server.id.to_string());
+                        match self.database.add_coins(server.discord_id, server.prepaid_coins, change).await {
+                            Ok(_) => description.push_str(&format!("\n\n**{} coins** have been refunded.", server.prepaid_coins)),
+                            Err(e) => error!("Failed to refund {} coins for server {}: {}", server.prepaid_coins, server.id, e),
+                        }
+                    }
+
+                    self.update_progress(job, "❌ Server Creation Failed", &description, 0xff0000).await;
+                    super::notify(&self.http, job.discord_id, "❌ Server Creation Failed", description, 0xff0000).await;
+                }
+            }
+            JobType::DeleteServer => {
+                super::notify(
+                    &self.http,
+                    job.discord_id,
+                    "❌ Server Deletion Failed",
+                    format!("Server `{}` could not be deleted from the panel. An administrator has been notified.", job.data["server_id"].as_str().unwrap_or("unknown")),
+                    0xff0000,
+                ).await;
+            }
+            JobType::Login => {}
+        }
+    }
+
+    async fn create_server(&self, job: &QueueJob) -> BotResult<()> {
+        let server_id = job_server_id(job)?;
+
+        let mut server = match self.database.get_server(&server_id).await? {
+            Some(server) => server,
+            None => {
+                info!("Server {} no longer exists, skipping creation", server_id);
+                return Ok(());
+            }
+        };
+
+        if server.pterodactyl_id.is_some() {
+            info!("Server {} is already provisioned, skipping creation", server_id);
+            return Ok(());
+        }
+
+        // A requeued job for a server that failed before has to reserve its
+        // resources again, and pay again for the plan it was refunded
+        if matches!(server.status, ServerStatus::Failed) {
+            if server.item_id.is_some() {
+                return Err(BotError::InvalidInput("This server was refunded when it failed and has to be bought again".to_string()));
+            }
+            let plan = self.config.store_config.plan(&server.plan)
+                .ok_or_else(|| BotError::InvalidInput(format!("Server {} is on unknown plan '{}'", server.id, server.plan)))?;
+            let price = plan.price as i64;
+
+            let change = CoinChange::new(TransactionReason::Store).reference(plan.id.clone());
+            if !self.database.restart_failed_server(&server, price, change).await? {
+                return Ok(());
+            }
+            server.status = ServerStatus::Creating;
+            server.prepaid_coins = price;
+        }
+
+        self.update_progress(
+            job,
+            "⚙️ Server Provisioning",
+            &format!("Server **{}** is being created on the panel.\n\nServer ID: `{}`\nPlan: {}", server.name, server.id, server.plan),
+            0x3498db,
+        ).await;
+
+        // A previous attempt may have reached the panel before failing, so adopt
+        // that server instead of creating a duplicate
+        let external_id = server.id.to_string();
+        let pterodactyl_server = match self.pterodactyl.get_server_by_external_id(&external_id).await? {
+            Some(existing) => {
+                info!("Server {} already exists on the panel as {}, adopting it", server_id, existing.id);
+                existing
+            }
+            None => {
+                let accounts = AccountService::new(
+                    self.database.clone(),
+                    self.pterodactyl.clone(),
+                    self.config.pterodactyl_user_email_domain.clone(),
+                );
+                let owner_id = accounts.ensure_linked(server.discord_id).await?;
+
+                let template_id = server.software.as_deref()
+                    .unwrap_or(&self.config.template_config.default_template);
+                let template = self.config.template_config.get(template_id)
+                    .ok_or_else(|| BotError::InvalidInput(format!("Unknown server template '{}'", template_id)))?;
+                let egg = self.pterodactyl.resolve_template(template).await?;
+
+                let selector = NodeSelector::new(self.database.clone(), self.pterodactyl.clone(), self.config.clone());
+                let placement = selector.select(&server).await?;
+
+                match self.pterodactyl.create_server(&server, owner_id, &egg, placement.allocation_id).await {
+                    Ok(created) => created,
+                    // Another worker may have won the race for the same external_id
+                    Err(e) => match self.pterodactyl.get_server_by_external_id(&external_id).await? {
+                        Some(existing) => existing,
+                        None => return Err(e),
+                    },
+                }
+            }
+        };
+
+        // The user may have deleted the server, or it may have failed, while the
+        // panel was still creating it
+        if !self.database.mark_server_provisioned(&server_id, pterodactyl_server.id, &pterodactyl_server.identifier).await? {
+            warn!("Server {} stopped being created during creation, removing it from the panel", server_id);
+            return self.pterodactyl.delete_server(pterodactyl_server.id).await;
+        }
+
+        let description = format!("Server **{}** is ready!\n\nServer ID: `{}`\nPlan: {}", server.name, server.id, server.plan);
+        self.update_progress(job, "✅ Server Created", &description, 0x00ff00).await;
+        super::notify(&self.http, server.discord_id, "✅ Server Created", description, 0x00ff00).await;
+        Ok(())
+    }
+
+    async fn delete_server(&self, job: &QueueJob) -> BotResult<()> {
+        let server_id = job_server_id(job)?;
+
+        // Servers that never reached the panel have nothing to clean up
+        let pterodactyl_id = match job.data["pterodactyl_id"].as_i64() {
+            Some(id) => id,
+            None => return Ok(()),
+        };
+
+        self.pterodactyl.delete_server(pterodactyl_id).await?;
+
+        if job.data["quiet"].as_bool().unwrap_or(false) {
+            return Ok(());
+        }
+
+        super::notify(
+            &self.http,
+            job.discord_id,
+            "🗑️ Server Deleted",
+            format!("Server `{}` has been permanently deleted from the panel.", server_id),
+            0xff6b6b,
+        ).await;
+        Ok(())
+    }
+
+    /// Edits the interaction response that queued the job, if it is still editable.
+    async fn update_progress(&self, job: &QueueJob, title: &str, description: &str, color: u32) {
+        let token = match job.data["interaction_token"].as_str() {
+            Some(token) => token,
+            None => return,
+        };
+
+        let body = serde_json::json!({
+            "embeds": [{
+                "title": title,
+                "description": description,
+                "color": color,
+            }]
+        });
+
+        // Interaction tokens expire after 15 minutes, so failures here are expected for old jobs
+        if let Err(e) = self.http.edit_original_interaction_response(token, &body).await {
+            debug!("Failed to update progress for job {}: {}", job.id, e);
+        }
+    }
+}
+
+fn job_server_id(job: &QueueJob) -> BotResult<String> {
+    job.data["server_id"]
+        .as_str()
+        .map(|id| id.to_string())
+        .ok_or_else(|| BotError::InvalidInput(format!("Job {} has no server_id", job.id)))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use redis::AsyncCommands;
+    use super::*;
+    use crate::models::{Resources, Server};
+    use crate::test_support::{self, Fixture, StubServer, QUEUE_LOCK};
+
+    fn worker(database: Database, config: Config) -> QueueWorker {
+        QueueWorker::new(99, database, PterodactylClient::new(&config), config, Arc::new(Http::new("")))
+    }
+
+    /// Stands in for the panel, answering every server lookup with `status`.
+    fn panel(status: u16) -> StubServer {
+        StubServer::start(move |_| (status, serde_json::json!({
+            "errors": [{ "code": "PanelError", "status": status.to_string(), "detail": "stubbed failure" }],
+        })))
+    }
+
+    /// A test user who paid 100 coins for a server that's waiting to be
+    /// created, and a worker whose panel answers with a fixed status.
+    struct QueueFixture {
+        fixture: Fixture,
+        queue: QueueService,
+        worker: QueueWorker,
+        server: Server,
+        _panel: StubServer,
+    }
+
+    impl std::ops::Deref for QueueFixture {
+        type Target = Fixture;
+
+        fn deref(&self) -> &Fixture {
+            &self.fixture
+        }
+    }
+
+    impl QueueFixture {
+        async fn new(panel_status: u16) -> Self {
+            let panel = panel(panel_status);
+            let mut config = test_support::config();
+            config.pterodactyl_url = panel.url.clone();
+            let fixture = Fixture::new(config).await;
+            let (database, discord_id) = (&fixture.database, fixture.discord_id);
+
+            let resources = Resources { ram: 1024, cpu: 100, disk: 2048, databases: 1, allocations: 1, backups: 1 };
+            database.add_coins(discord_id, 200, CoinChange::new(TransactionReason::Admin)).await.unwrap();
+            database.purchase_resources(discord_id, 100, &resources, CoinChange::new(TransactionReason::Store)).await.unwrap();
+
+            let mut server = Server::new(discord_id, "queue-test".to_string(), "basic".to_string(), "paper".to_string(), resources, 30);
+            server.prepaid_coins = 100;
+            database.create_plan_server(&server, 100, None, CoinChange::new(TransactionReason::Store)).await.unwrap();
+
+            Self {
+                queue: QueueService::new(database.clone()),
+                worker: worker(database.clone(), fixture.config.clone()),
+                fixture,
+                server,
+                _panel: panel,
+            }
+        }
+
+        /// Queues a creation job that has already failed `attempts` times and
+        /// hands it to the worker.
+        async fn process(&self, attempts: u32) -> QueueJob {
+            let mut conn = self.database.get_redis_connection().await.unwrap();
+            let _: () = conn.del(&["job_queue", "job_queue:processing:99"]).await.unwrap();
+
+            let mut job = QueueJob::new(JobType::CreateServer, self.server.discord_id, serde_json::json!({ "server_id": self.server.id.to_string() }));
+            job.attempts = attempts;
+            self.queue.enqueue(job.clone()).await.unwrap();
+
+            let reserved = self.queue.dequeue(99).await.unwrap().expect("job was not queued");
+            assert_eq!(reserved.job.id, job.id);
+            self.worker.process(&self.queue, reserved).await;
+            job
+        }
+
+        async fn delayed(&self, job: &QueueJob) -> Option<(QueueJob, i64)> {
+            let mut conn = self.database.get_redis_connection().await.unwrap();
+            let delayed: Vec<(String, i64)> = conn.zrange_withscores("job_queue:delayed", 0, -1).await.unwrap();
+            delayed.into_iter()
+                .filter_map(|(json, ready_at)| serde_json::from_str::<QueueJob>(&json).ok().map(|job| (job, ready_at)))
+                .find(|(delayed, _)| delayed.id == job.id)
+        }
+
+        async fn dead(&self, job: &QueueJob) -> Option<QueueJob> {
+            self.queue.get_dead_jobs(1000).await.unwrap().into_iter().find(|dead| dead.id == job.id)
+        }
+
+        async fn status(&self) -> ServerStatus {
+            self.database.get_server(&self.server.id.to_string()).await.unwrap().unwrap().status
+        }
+    }
+
+    #[tokio::test]
+    async fn retry_delay_backs_off_exponentially_up_to_a_cap() {
+        let mut config = test_support::config();
+        config.mongodb_uri = "mongodb://127.0.0.1:9".to_string();
+        config.redis_uri = "redis://127.0.0.1:9".to_string();
+        let worker = worker(Database::new(&config).await.unwrap(), config);
+
+        assert_eq!(worker.retry_delay(0), 10);
+        assert_eq!(worker.retry_delay(1), 20);
+        assert_eq!(worker.retry_delay(4), 160);
+        assert_eq!(worker.retry_delay(9), MAX_RETRY_DELAY_SECS);
+        assert_eq!(worker.retry_delay(u32::MAX), MAX_RETRY_DELAY_SECS);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_MONGODB_URI/TEST_REDIS_URI"]
+    async fn transient_panel_error_is_retried_later() {
+        let _lock = QUEUE_LOCK.lock().await;
+        let fixture = QueueFixture::new(503).await;
+
+        let before = Utc::now().timestamp();
+        let job = fixture.process(1).await;
+
+        let (retried, ready_at) = fixture.delayed(&job).await.expect("job was not scheduled for a retry");
+        assert_eq!(retried.attempts, 2);
+        assert!(retried.last_error.unwrap().contains("503"));
+        // Second failure: twice the base delay
+        assert!(ready_at >= before + 20 && ready_at <= Utc::now().timestamp() + 20);
+        assert!(fixture.dead(&job).await.is_none());
+
+        // Nothing is given back while the job can still succeed
+        assert!(matches!(fixture.status().await, ServerStatus::Creating));
+        let user = fixture.user().await;
+        assert_eq!(user.coins, 0);
+        assert_eq!(user.allocated.ram, 1024);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_MONGODB_URI/TEST_REDIS_URI"]
+    async fn last_attempt_is_dead_lettered_and_refunded() {
+        let _lock = QUEUE_LOCK.lock().await;
+        let fixture = QueueFixture::new(503).await;
+
+        let job = fixture.process(2).await;
+
+        let dead = fixture.dead(&job).await.expect("job was not dead-lettered");
+        assert_eq!(dead.attempts, 3);
+        assert!(fixture.delayed(&job).await.is_none());
+        assert!(matches!(fixture.status().await, ServerStatus::Failed));
+
+        let user = fixture.user().await;
+        assert_eq!(user.coins, 100);
+        assert_eq!(user.allocated.ram, 0);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_MONGODB_URI/TEST_REDIS_URI"]
+    async fn permanent_panel_error_releases_and_refunds_once() {
+        let _lock = QUEUE_LOCK.lock().await;
+        let fixture = QueueFixture::new(403).await;
+
+        let job = fixture.process(0).await;
+
+        let dead = fixture.dead(&job).await.expect("job was not dead-lettered");
+        assert_eq!(dead.attempts, 1);
+        assert!(dead.last_error.unwrap().contains("403"));
+        assert!(matches!(fixture.status().await, ServerStatus::Failed));
+
+        let user = fixture.user().await;
+        assert_eq!(user.coins, 100);
+        assert_eq!(user.allocated.ram, 0);
+        assert_eq!(user.allocated.disk, 0);
+
+        // A second failure finds the server already failed and gives nothing back again
+        fixture.worker.on_failure(&job, &BotError::InvalidInput("again".to_string())).await;
+        let user = fixture.user().await;
+        assert_eq!(user.coins, 100);
+        assert_eq!(user.allocated.ram, 0);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_MONGODB_URI/TEST_REDIS_URI"]
+    async fn requeued_failed_server_is_paid_for_again() {
+        let _lock = QUEUE_LOCK.lock().await;
+        let fixture = QueueFixture::new(503).await;
+        let discord_id = fixture.server.discord_id;
+
+        let job = QueueJob::new(JobType::CreateServer, discord_id, serde_json::json!({ "server_id": fixture.server.id.to_string() }));
+        fixture.worker.on_failure(&job, &BotError::InvalidInput("panel rejected it".to_string())).await;
+        fixture.database.add_coins(discord_id, 400, CoinChange::new(TransactionReason::Admin)).await.unwrap();
+
+        fixture.process(0).await;
+
+        // The basic plan costs 500, charged again along with the resources
+        assert!(matches!(fixture.status().await, ServerStatus::Creating));
+        let server = fixture.database.get_server(&fixture.server.id.to_string()).await.unwrap().unwrap();
+        assert_eq!(server.prepaid_coins, 500);
+        let user = fixture.user().await;
+        assert_eq!(user.coins, 0);
+        assert_eq!(user.allocated.ram, 1024);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_MONGODB_URI/TEST_REDIS_URI"]
+    async fn requeued_failed_server_needs_the_coins() {
+        let _lock = QUEUE_LOCK.lock().await;
+        let fixture = QueueFixture::new(503).await;
+        let discord_id = fixture.server.discord_id;
+
+        let job = QueueJob::new(JobType::CreateServer, discord_id, serde_json::json!({ "server_id": fixture.server.id.to_string() }));
+        fixture.worker.on_failure(&job, &BotError::InvalidInput("panel rejected it".to_string())).await;
+
+        let job = fixture.process(0).await;
+
+        assert!(fixture.dead(&job).await.is_some());
+        assert!(matches!(fixture.status().await, ServerStatus::Failed));
+        let user = fixture.user().await;
+        assert_eq!(user.coins, 100);
+        assert_eq!(user.allocated.ram, 0);
+    }
+
+}
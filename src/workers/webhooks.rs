@@ -0,0 +1,173 @@
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use serenity::http::Http;
+use tracing::{info, warn, error};
+use crate::config::Config;
+use crate::errors::BotResult;
+use crate::models::OrderStatus;
+use crate::services::{Database, OrderService, PaymentEvent, PaymentProvider, PaymentProviders};
+
+/// HTTP listener for payment provider webhooks, at `/webhooks/<provider>`.
+#[derive(Clone)]
+pub struct WebhookServer {
+    database: Database,
+    config: Config,
+    orders: OrderService,
+    http: Arc<Http>,
+}
+
+impl WebhookServer {
+    pub fn new(database: Database, config: Config, http: Arc<Http>) -> Self {
+        Self {
+            orders: OrderService::new(database.clone(), &config),
+            database,
+            config,
+            http,
+        }
+    }
+
+    pub async fn run(&self) {
+        let addr: SocketAddr = match self.config.webhook_bind_addr.parse() {
+            Ok(addr) => addr,
+            Err(e) => {
+                error!("Invalid WEBHOOK_BIND_ADDR '{}': {}", self.config.webhook_bind_addr, e);
+                return;
+            }
+        };
+
+        let server = self.clone();
+        let make_service = make_service_fn(move |_| {
+            let server = server.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request| {
+                    let server = server.clone();
+                    async move { Ok::<_, Infallible>(server.route(request).await) }
+                }))
+            }
+        });
+
+        info!("Webhook listener on {} for {}", addr, self.providers().names().join(", "));
+        if let Err(e) = Server::bind(&addr).serve(make_service).await {
+            error!("Webhook listener failed: {}", e);
+        }
+    }
+
+    async fn route(&self, request: Request<Body>) -> Response<Body> {
+        if request.method() != Method::POST {
+            return respond(StatusCode::METHOD_NOT_ALLOWED, "Method not allowed");
+        }
+
+        let provider = request.uri().path()
+            .strip_prefix("/webhooks/")
+            .and_then(|name| self.providers().get(name));
+        match provider {
+            Some(provider) => self.webhook(provider, request).await,
+            None => respond(StatusCode::NOT_FOUND, "Not found"),
+        }
+    }
+
+    async fn webhook(&self, provider: Arc<dyn PaymentProvider>, request: Request<Body>) -> Response<Body> {
+        let (parts, body) = request.into_parts();
+        let payload = match hyper::body::to_bytes(body).await {
+            Ok(payload) => payload,
+            Err(e) => return respond(StatusCode::BAD_REQUEST, &e.to_string()),
+        };
+
+        let event = match provider.verify_webhook(&parts.headers, &payload).await {
+            Ok(event) => event,
+            Err(e) => {
+                warn!("Rejected {} webhook: {}", provider.name(), e);
+                return respond(StatusCode::BAD_REQUEST, &e.to_string());
+            }
+        };
+
+        // A non-2xx response makes the provider redeliver the event later, and
+        // every handler below is safe to run more than once
+        match self.handle_event(provider.name(), event).await {
+            Ok(()) => respond(StatusCode::OK, "OK"),
+            Err(e) => {
+                error!("Failed to handle {} webhook: {}", provider.name(), e);
+                respond(StatusCode::INTERNAL_SERVER_ERROR, "Failed to handle event")
+            }
+        }
+    }
+
+    fn providers(&self) -> &PaymentProviders {
+        self.orders.providers()
+    }
+
+    async fn handle_event(&self, provider: &str, event: PaymentEvent) -> BotResult<()> {
+        match event {
+            PaymentEvent::Paid { checkout_id, payment_id } => self.complete(provider, &checkout_id, payment_id).await,
+            PaymentEvent::Approved { checkout_id } => self.capture(provider, &checkout_id).await,
+            PaymentEvent::Closed { checkout_id } => self.close(provider, &checkout_id).await,
+            PaymentEvent::Refunded { payment_id } => self.reverse(provider, &payment_id, OrderStatus::Cancelled, "refunded").await,
+            PaymentEvent::Disputed { payment_id } => self.reverse(provider, &payment_id, OrderStatus::Failed, "disputed").await,
+            PaymentEvent::Ignored => Ok(()),
+        }
+    }
+
+    async fn complete(&self, provider: &str, checkout_id: &str, payment_id: Option<String>) -> BotResult<()> {
+        let order = match self.database.get_order_by_checkout(provider, checkout_id).await? {
+            Some(order) => order,
+            None => {
+                warn!("No order for paid {} checkout {}", provider, checkout_id);
+                return Ok(());
+            }
+        };
+
+        if self.orders.complete(&order, payment_id).await? {
+            super::orders::notify_completed(&self.http, &order).await;
+        }
+
+        Ok(())
+    }
+
+    /// Captures an approval only for a pending order, so buyers of unknown or
+    /// cancelled orders are never charged.
+    async fn capture(&self, provider: &str, checkout_id: &str) -> BotResult<()> {
+        let order = match self.database.get_order_by_checkout(provider, checkout_id).await? {
+            Some(order) => order,
+            None => {
+                warn!("No order for approved {} checkout {}, not capturing it", provider, checkout_id);
+                return Ok(());
+            }
+        };
+
+        if self.orders.capture(&order).await? {
+            super::orders::notify_completed(&self.http, &order).await;
+        }
+
+        Ok(())
+    }
+
+    async fn close(&self, provider: &str, checkout_id: &str) -> BotResult<()> {
+        if let Some(order) = self.database.get_order_by_checkout(provider, checkout_id).await? {
+            self.orders.cancel(&order).await?;
+        }
+        Ok(())
+    }
+
+    async fn reverse(&self, provider: &str, payment_id: &str, status: OrderStatus, what: &str) -> BotResult<()> {
+        let order = match self.database.get_order_by_payment(provider, payment_id).await? {
+            Some(order) => order,
+            None => return Ok(()),
+        };
+
+        if self.database.reverse_order(&order, status).await? {
+            info!("Reversed {} order {}, took back {} coins", what, order.id, order.coins);
+            super::orders::notify_reversed(&self.http, &order, what).await;
+        }
+
+        Ok(())
+    }
+}
+
+fn respond(status: StatusCode, body: &str) -> Response<Body> {
+    let mut response = Response::new(Body::from(body.to_string()));
+    *response.status_mut() = status;
+    response
+}